@@ -1,4 +1,4 @@
-use crate::winapi::device_io_control;
+use crate::winapi::{device_io_control, device_io_control_string};
 use std::fmt::{Debug, Display, Formatter};
 use windows::core::{Error as WindowsError, Owned, PCWSTR};
 use windows::Win32::Devices::DeviceAndDriverInstallation::{
@@ -14,14 +14,52 @@ use windows::Win32::Storage::FileSystem::{
 };
 use windows::Win32::System::Memory::{LocalAlloc, LPTR};
 use windows::Win32::System::Power::{
-    BatteryInformation, BATTERY_CAPACITY_RELATIVE, BATTERY_INFORMATION, BATTERY_IS_SHORT_TERM,
-    BATTERY_QUERY_INFORMATION, BATTERY_STATUS, BATTERY_SYSTEM_BATTERY, BATTERY_WAIT_STATUS,
-    IOCTL_BATTERY_QUERY_INFORMATION, IOCTL_BATTERY_QUERY_STATUS, IOCTL_BATTERY_QUERY_TAG,
+    BatteryInformation, BatteryManufactureName, GetSystemPowerStatus, BATTERY_CAPACITY_RELATIVE,
+    BATTERY_INFORMATION, BATTERY_IS_SHORT_TERM, BATTERY_QUERY_INFORMATION, BATTERY_STATUS,
+    BATTERY_SYSTEM_BATTERY, BATTERY_UNKNOWN_CAPACITY, BATTERY_UNKNOWN_RATE,
+    BATTERY_UNKNOWN_VOLTAGE, BATTERY_WAIT_STATUS, IOCTL_BATTERY_QUERY_INFORMATION,
+    IOCTL_BATTERY_QUERY_STATUS, IOCTL_BATTERY_QUERY_TAG, SYSTEM_POWER_STATUS,
 };
 
+/// Reported by Windows when it cannot estimate the remaining battery time.
+const BATTERY_LIFE_UNKNOWN: u32 = u32::MAX;
+/// Reported by Windows when it cannot tell the battery charge level.
+const BATTERY_PERCENTAGE_UNKNOWN: u8 = 255;
+const AC_LINE_OFFLINE: u8 = 0;
+const AC_LINE_ONLINE: u8 = 1;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+fn get_system_power_status() -> Result<SYSTEM_POWER_STATUS, Error> {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    // SAFETY: The provided pointer references a local structure
+    unsafe { GetSystemPowerStatus(&mut status)? };
+    Ok(status)
+}
+
+/// Returns `true` if Windows Battery Saver is currently on.
+pub fn is_battery_saver_on() -> Result<bool, Error> {
+    Ok(get_system_power_status()?.SystemStatusFlag == 1)
+}
+
+/// Returns `None` if the AC line status is unknown.
+pub fn get_power_source() -> Result<Option<PowerSource>, Error> {
+    match get_system_power_status()?.ACLineStatus {
+        AC_LINE_ONLINE => Ok(Some(PowerSource::Ac)),
+        AC_LINE_OFFLINE => Ok(Some(PowerSource::Battery)),
+        _ => Ok(None),
+    }
+}
+
 pub enum Error {
     WindowsError(WindowsError),
     UnexpectedResponse,
+    /// The battery does not currently report its charge rate.
+    RateUnavailable,
 }
 
 impl From<WindowsError> for Error {
@@ -41,6 +79,7 @@ impl Display for Error {
         match self {
             Self::WindowsError(inner) => Display::fmt(inner, f),
             Self::UnexpectedResponse => write!(f, "Unexpected response from a WinAPI call"),
+            Self::RateUnavailable => write!(f, "Charge rate is not available"),
         }
     }
 }
@@ -53,24 +92,23 @@ pub struct BatteriesIterator {
 }
 
 impl BatteriesIterator {
-    pub fn new() -> Self {
+    /// Fails if the device information set cannot be acquired, e.g. when the system is low on
+    /// resources.
+    pub fn new() -> Result<Self, Error> {
         // SAFETY: using hardcoded GUID and correct flags to get device info set
-        // The call is not expected to fail
-        let device_info_set_handle = unsafe {
-            Owned::new(
-                SetupDiGetClassDevsW(
-                    Some(&GUID_DEVCLASS_BATTERY),
-                    None,
-                    None,
-                    DIGCF_PRESENT | DIGCF_INTERFACEDEVICE,
-                )
-                .unwrap(),
+        let handle = unsafe {
+            SetupDiGetClassDevsW(
+                Some(&GUID_DEVCLASS_BATTERY),
+                None,
+                None,
+                DIGCF_PRESENT | DIGCF_INTERFACEDEVICE,
             )
-        };
-        BatteriesIterator {
-            device_info_set_handle,
+        }?;
+        Ok(BatteriesIterator {
+            // SAFETY: We own the returned handle
+            device_info_set_handle: unsafe { Owned::new(handle) },
             index: 0,
-        }
+        })
     }
 
     /// # Safety
@@ -173,9 +211,58 @@ impl Iterator for BatteriesIterator {
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct BatteryStatus {
     pub charge_rate: i32,
     pub capacity: u32,
+    /// Remaining time estimated by Windows from recent usage, in seconds.
+    pub os_runtime_secs: Option<u32>,
+    /// Charge level as reported by Windows.
+    pub percent: Option<u32>,
+    /// Voltage in mV
+    pub voltage: Option<u32>,
+}
+
+/// Rarely changing battery information. Fields are `None` if the battery does not report them.
+#[derive(Clone, PartialEq)]
+pub struct BatteryDetails {
+    pub manufacturer: Option<String>,
+    pub cycle_count: Option<u32>,
+    /// Capacity in mWh
+    pub designed_capacity: Option<u32>,
+    /// Capacity in mWh
+    pub full_charged_capacity: Option<u32>,
+    /// Voltage in mV
+    pub voltage: Option<u32>,
+}
+
+impl BatteryDetails {
+    /// Returns full charged capacity as a percentage of the designed capacity.
+    pub fn health(&self) -> Option<u32> {
+        match (self.full_charged_capacity, self.designed_capacity) {
+            (Some(full), Some(designed)) if designed > 0 => {
+                Some((full as u64 * 100 / designed as u64) as u32)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn known_capacity(capacity: u32) -> Option<u32> {
+    if capacity == BATTERY_UNKNOWN_CAPACITY {
+        None
+    } else {
+        Some(capacity)
+    }
+}
+
+/// Rate is reported in mW, since batteries reporting relative capacity are not supported.
+fn known_rate(rate: i32) -> Result<i32, Error> {
+    if rate as u32 == BATTERY_UNKNOWN_RATE {
+        Err(Error::RateUnavailable)
+    } else {
+        Ok(rate)
+    }
 }
 
 pub struct Battery {
@@ -184,27 +271,75 @@ pub struct Battery {
 }
 
 impl Battery {
-    pub fn get_status(&self) -> Result<BatteryStatus, Error> {
+    fn query_status(&self) -> Result<BATTERY_STATUS, Error> {
         let bws = BATTERY_WAIT_STATUS {
             BatteryTag: self.tag,
             ..Default::default()
         };
-        let status: BATTERY_STATUS =
-            device_io_control(&self.handle, IOCTL_BATTERY_QUERY_STATUS, &bws)?;
+        Ok(device_io_control(
+            &self.handle,
+            IOCTL_BATTERY_QUERY_STATUS,
+            &bws,
+        )?)
+    }
+
+    pub fn get_status(&self) -> Result<BatteryStatus, Error> {
+        let status = self.query_status()?;
+        let power_status = get_system_power_status().ok();
+        let os_runtime_secs = power_status
+            .map(|s| s.BatteryLifeTime)
+            .filter(|&secs| secs != BATTERY_LIFE_UNKNOWN);
+        let percent = power_status
+            .map(|s| s.BatteryLifePercent)
+            .filter(|&p| p != BATTERY_PERCENTAGE_UNKNOWN)
+            .map(u32::from);
         Ok(BatteryStatus {
-            charge_rate: status.Rate,
+            charge_rate: known_rate(status.Rate)?,
             capacity: status.Capacity,
+            os_runtime_secs,
+            percent,
+            voltage: Some(status.Voltage).filter(|&v| v != BATTERY_UNKNOWN_VOLTAGE),
         })
     }
 
-    fn is_supported(&self) -> Result<bool, Error> {
+    fn query_information(&self) -> Result<BATTERY_INFORMATION, Error> {
         let query = BATTERY_QUERY_INFORMATION {
             BatteryTag: self.tag,
             InformationLevel: BatteryInformation,
             ..Default::default()
         };
-        let info: BATTERY_INFORMATION =
-            device_io_control(&self.handle, IOCTL_BATTERY_QUERY_INFORMATION, &query)?;
+        Ok(device_io_control(
+            &self.handle,
+            IOCTL_BATTERY_QUERY_INFORMATION,
+            &query,
+        )?)
+    }
+
+    pub fn get_details(&self) -> Result<BatteryDetails, Error> {
+        let info = self.query_information()?;
+        let status = self.query_status()?;
+        let query = BATTERY_QUERY_INFORMATION {
+            BatteryTag: self.tag,
+            InformationLevel: BatteryManufactureName,
+            ..Default::default()
+        };
+        // Many batteries do not report their manufacturer
+        let manufacturer =
+            device_io_control_string(&self.handle, IOCTL_BATTERY_QUERY_INFORMATION, &query)
+                .ok()
+                .filter(|name| !name.is_empty());
+        Ok(BatteryDetails {
+            manufacturer,
+            // Zero is reported by batteries that do not count cycles
+            cycle_count: Some(info.CycleCount).filter(|&count| count > 0),
+            designed_capacity: known_capacity(info.DesignedCapacity),
+            full_charged_capacity: known_capacity(info.FullChargedCapacity),
+            voltage: Some(status.Voltage).filter(|&v| v != BATTERY_UNKNOWN_VOLTAGE),
+        })
+    }
+
+    fn is_supported(&self) -> Result<bool, Error> {
+        let info = self.query_information()?;
         let rel_capacity =
             info.Capabilities & BATTERY_CAPACITY_RELATIVE == BATTERY_CAPACITY_RELATIVE;
         debug!("Battery cycle count: {}", info.CycleCount);
@@ -214,6 +349,25 @@ impl Battery {
         );
         let short_term_battery = info.Capabilities & BATTERY_IS_SHORT_TERM == BATTERY_IS_SHORT_TERM;
         let system_battery = info.Capabilities & BATTERY_SYSTEM_BATTERY == BATTERY_SYSTEM_BATTERY;
+        if rel_capacity {
+            // Capacity and rate of such batteries are not in mWh and mW
+            debug!("Skipping a battery that reports relative capacity");
+        }
         Ok(system_battery && !short_term_battery && !rel_capacity)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_rate_is_unavailable() {
+        assert!(matches!(
+            known_rate(BATTERY_UNKNOWN_RATE as i32),
+            Err(Error::RateUnavailable)
+        ));
+        assert!(matches!(known_rate(-12345), Ok(-12345)));
+        assert!(matches!(known_rate(0), Ok(0)));
+    }
+}
@@ -3,8 +3,8 @@ use std::marker::PhantomData;
 use std::ptr::null_mut;
 use windows::core::Owned;
 use windows::Win32::Graphics::GdiPlus::{
-    GdipCreateBitmapFromScan0, GdipCreateHICONFromBitmap, GdipDisposeImage, GpBitmap,
-    PixelFormatAlpha, PixelFormatCanonical, PixelFormatGDI,
+    GdipBitmapSetResolution, GdipCreateBitmapFromScan0, GdipCreateHICONFromBitmap,
+    GdipDisposeImage, GpBitmap, PixelFormatAlpha, PixelFormatCanonical, PixelFormatGDI,
 };
 use windows::Win32::UI::WindowsAndMessaging::HICON;
 
@@ -36,6 +36,12 @@ impl<'init> Bitmap<'init> {
         })
     }
 
+    /// Sets the DPI used to convert physical units, e.g. font points, to pixels.
+    pub fn set_resolution(&mut self, dpi: f32) -> Result<()> {
+        // SAFETY: The native pointer is guaranteed to be valid
+        Error::check(unsafe { GdipBitmapSetResolution(self.native, dpi, dpi) })
+    }
+
     /// Get native GDI+ Bitmap pointer.
     ///
     /// # Safety
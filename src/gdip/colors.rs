@@ -1,3 +1,4 @@
+use windows::Win32::Foundation::COLORREF;
 use windows::Win32::Graphics::GdiPlus::Color as GdipColor;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -5,10 +6,23 @@ pub struct Color(u32);
 
 impl Color {
     pub const WHITE: Color = Color(GdipColor::White as _);
+    pub const BLACK: Color = Color(GdipColor::Black as _);
     pub const CYAN: Color = Color(GdipColor::Cyan as _);
     pub const RED: Color = Color(GdipColor::Red as _);
     pub const GREEN: Color = Color(GdipColor::Green as _);
     pub const YELLOW: Color = Color(GdipColor::Yellow as _);
+    pub const ORANGE: Color = Color(GdipColor::Orange as _);
+    pub const GRAY: Color = Color(GdipColor::Gray as _);
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Color {
+        Color(0xFF000000 | (r as u32) << 16 | (g as u32) << 8 | b as u32)
+    }
+
+    /// Converts an opaque GDI color (`0x00BBGGRR`) to a GDI+ color (`0xAARRGGBB`).
+    pub const fn from_colorref(color: COLORREF) -> Color {
+        let c = color.0;
+        Color(0xFF000000 | (c & 0xFF) << 16 | (c & 0xFF00) | (c >> 16) & 0xFF)
+    }
 }
 
 impl Into<u32> for Color {
@@ -6,8 +6,8 @@ use std::marker::PhantomData;
 use std::ptr::null_mut;
 use windows::core::PCWSTR;
 use windows::Win32::Graphics::GdiPlus::{
-    GdipCreateSolidFill, GdipDeleteBrush, GdipDeleteGraphics, GdipDrawString,
-    GdipGetImageGraphicsContext, GpGraphics, RectF,
+    GdipCreateSolidFill, GdipDeleteBrush, GdipDeleteGraphics, GdipDrawString, GdipFillRectangle,
+    GdipGetImageGraphicsContext, GdipGraphicsClear, GdipMeasureString, GpGraphics, RectF,
 };
 
 pub struct Graphics<'init, 'bitmap> {
@@ -29,6 +29,34 @@ impl<'init, 'bitmap> Graphics<'init, 'bitmap> {
         }
     }
 
+    pub fn clear(&mut self, color: Color) -> Result<&mut Self> {
+        // SAFETY: The native pointer is guaranteed to be valid
+        Error::check(unsafe { GdipGraphicsClear(self.native, color.into()) })?;
+        Ok(self)
+    }
+
+    /// Returns the width and height of the text as drawn by `draw_string`.
+    pub fn measure_string(&self, text: &str, font: &Font) -> Result<(f32, f32)> {
+        let str: Vec<u16> = text.encode_utf16().collect();
+        let layout = RectF::default();
+        let mut bounds = RectF::default();
+        // SAFETY: The provided pointers are valid for the duration of the GDI+ call
+        Error::check(unsafe {
+            GdipMeasureString(
+                self.native,
+                PCWSTR::from_raw(str.as_ptr()),
+                str.len() as i32,
+                font.get_native(),
+                &layout,
+                null_mut(),
+                &mut bounds,
+                null_mut(),
+                null_mut(),
+            )
+        })?;
+        Ok((bounds.Width, bounds.Height))
+    }
+
     pub fn draw_string(
         &mut self,
         text: &str,
@@ -63,6 +91,24 @@ impl<'init, 'bitmap> Graphics<'init, 'bitmap> {
             Ok(())
         }
     }
+
+    pub fn fill_rectangle(
+        &mut self,
+        color: Color,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Result<()> {
+        unsafe {
+            let mut fill = null_mut();
+            Error::check(GdipCreateSolidFill(color.into(), &mut fill))?;
+            let brush = fill as *mut _ as *mut _;
+            let result = Error::check(GdipFillRectangle(self.native, brush, x, y, width, height));
+            Error::check(GdipDeleteBrush(brush))?;
+            result
+        }
+    }
 }
 
 impl Drop for Graphics<'_, '_> {
@@ -1,35 +1,184 @@
 use crate::gdip::{Bitmap, Color, Font, GdiPlus, Graphics};
-use std::cmp::min;
+use std::collections::HashMap;
 use windows::core::{Error, Owned, Result};
 use windows::Win32::Foundation::{ERROR_INVALID_PARAMETER, HWND};
 use windows::Win32::Graphics::GdiPlus::{FontStyleBold, UnitPoint};
 use windows::Win32::UI::Shell::{
-    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_SHOWTIP, NIF_TIP, NIM_ADD, NIM_DELETE,
-    NIM_MODIFY, NIM_SETVERSION, NOTIFYICONDATAW, NOTIFYICONDATAW_0, NOTIFYICON_VERSION_4,
+    Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_SHOWTIP, NIF_TIP, NIIF_INFO, NIM_ADD,
+    NIM_DELETE, NIM_MODIFY, NIM_SETVERSION, NOTIFYICONDATAW, NOTIFYICONDATAW_0,
+    NOTIFYICON_VERSION_4,
 };
 use windows::Win32::UI::WindowsAndMessaging::{HICON, WM_APP};
 
 pub const WM_NOTIFY_ICON: u32 = WM_APP + 1;
+/// Icon size in pixels at 96 DPI.
+const ICON_SIZE: i32 = 32;
+const DEFAULT_DPI: u32 = 96;
+const FONT_NAME: &str = "Segoe UI";
+// Font sizes are in points
+const DEFAULT_FONT_SIZE: f32 = 9.0;
+/// Text is never shrunk below this size, even if it gets clipped.
+const MIN_FONT_SIZE: f32 = 6.0;
+const FONT_SIZE_STEP: f32 = 0.5;
+/// Width of the gauge bar in pixels at 96 DPI.
+const GAUGE_WIDTH: i32 = 4;
+/// Halo drawn around the text, so that it stands out on light backgrounds too.
+const OUTLINE_COLOR: Color = Color::BLACK;
+const OUTLINE_OFFSETS: [(f32, f32); 4] = [(-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0)];
+
+/// System colors used instead of the regular icon colors when a high contrast theme is active.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HighContrastColors {
+    pub text: Color,
+    pub background: Color,
+}
+
+/// Cuts the text to at most `max` units without splitting a surrogate pair.
+fn truncate_utf16_at_boundary(text: &[u16], max: usize) -> &[u16] {
+    if text.len() <= max {
+        return text;
+    }
+    // A high surrogate at the end would lose its low half
+    let len = if max > 0 && (0xD800..0xDC00).contains(&text[max - 1]) {
+        max - 1
+    } else {
+        max
+    };
+    &text[..len]
+}
 
 struct IconFactory<'gdip> {
     gdi_plus: &'gdip GdiPlus,
-    font: Font<'gdip>,
+    /// Fonts by their size in steps
+    fonts: HashMap<u32, Font<'gdip>>,
+    /// Largest font size known to fit the icon, by text length
+    fitted_sizes: HashMap<usize, f32>,
+    high_contrast: Option<HighContrastColors>,
+    /// Fill level from 0 to 1 and color of the bar next to the text, `None` if there is no bar.
+    gauge: Option<(f32, Color)>,
+    outline: bool,
+    dpi: u32,
     // TODO: Add brush cache
 }
 
 impl<'gdip> IconFactory<'gdip> {
     pub fn new(gdi_plus: &'gdip GdiPlus) -> IconFactory {
-        // We expect the font to be found
-        let font = Font::new(gdi_plus, "Segoe UI", 9.0, UnitPoint, FontStyleBold).unwrap();
-        IconFactory { gdi_plus, font }
+        IconFactory {
+            gdi_plus,
+            fonts: HashMap::new(),
+            fitted_sizes: HashMap::new(),
+            high_contrast: None,
+            gauge: None,
+            outline: false,
+            dpi: DEFAULT_DPI,
+        }
     }
 
-    pub fn render_icon(&self, text: &str, color: Color) -> Owned<HICON> {
+    fn icon_size(&self) -> i32 {
+        ICON_SIZE * self.dpi as i32 / DEFAULT_DPI as i32
+    }
+
+    fn gauge_width(&self) -> i32 {
+        match self.gauge {
+            Some(_) => GAUGE_WIDTH * self.dpi as i32 / DEFAULT_DPI as i32,
+            None => 0,
+        }
+    }
+
+    fn set_gauge(&mut self, gauge: Option<(f32, Color)>) {
+        if gauge.is_some() != self.gauge.is_some() {
+            // The text gets a different width
+            self.fitted_sizes.clear();
+        }
+        self.gauge = gauge;
+    }
+
+    fn set_dpi(&mut self, dpi: u32) {
+        if dpi != self.dpi {
+            debug!("Icon DPI changed from {} to {}", self.dpi, dpi);
+            self.dpi = dpi;
+            // Rounding might differ at the new size
+            self.fitted_sizes.clear();
+        }
+    }
+
+    fn get_font(&mut self, size: f32) -> &Font<'gdip> {
+        let gdi_plus = self.gdi_plus;
+        let key = (size / FONT_SIZE_STEP) as u32;
+        self.fonts.entry(key).or_insert_with(|| {
+            // We expect the font to be found
+            Font::new(gdi_plus, FONT_NAME, size, UnitPoint, FontStyleBold).unwrap()
+        })
+    }
+
+    /// Shrinks the font until the text fits the icon, starting from the size
+    /// that fit the last text of the same length.
+    fn fit_font_size(&mut self, graphics: &Graphics, text: &str) -> f32 {
+        let len = text.chars().count();
+        let icon_size = self.icon_size() as f32;
+        let text_width = (self.icon_size() - self.gauge_width()) as f32;
+        let mut size = self
+            .fitted_sizes
+            .get(&len)
+            .copied()
+            .unwrap_or(DEFAULT_FONT_SIZE);
+        while size > MIN_FONT_SIZE {
+            match graphics.measure_string(text, self.get_font(size)) {
+                Ok((width, height)) if width > text_width || height > icon_size => {
+                    size -= FONT_SIZE_STEP;
+                }
+                Ok(_) => break,
+                Err(err) => {
+                    warn!("Failed to measure icon text: {}", err);
+                    break;
+                }
+            }
+        }
+        self.fitted_sizes.insert(len, size);
+        size
+    }
+
+    pub fn render_icon(&mut self, text: &str, color: Color) -> Owned<HICON> {
         // We don't expect errors since the provided size is valid
-        let mut bitmap = Bitmap::new(self.gdi_plus, 32, 32).unwrap();
-        Graphics::for_bitmap(&mut bitmap)
-            .draw_string(text, &self.font, color, 0.0, 0.0)
+        let pixels = self.icon_size();
+        let mut bitmap = Bitmap::new(self.gdi_plus, pixels, pixels).unwrap();
+        // Font sizes are in points, so they scale along with the icon
+        bitmap.set_resolution(self.dpi as f32).unwrap();
+        let mut graphics = Graphics::for_bitmap(&mut bitmap);
+        let size = self.fit_font_size(&graphics, text);
+        let color = match self.high_contrast {
+            Some(colors) => {
+                graphics.clear(colors.background).unwrap();
+                colors.text
+            }
+            None => {
+                if self.outline {
+                    // One pixel at 96 DPI, scaled like the icon
+                    let scale = self.dpi as f32 / DEFAULT_DPI as f32;
+                    for (x, y) in OUTLINE_OFFSETS {
+                        let font = self.get_font(size);
+                        graphics
+                            .draw_string(text, font, OUTLINE_COLOR, x * scale, y * scale)
+                            .unwrap();
+                    }
+                }
+                color
+            }
+        };
+        graphics
+            .draw_string(text, self.get_font(size), color, 0.0, 0.0)
             .unwrap();
+        if let Some((fill, gauge_color)) = self.gauge {
+            let gauge_color = self.high_contrast.map_or(gauge_color, |colors| colors.text);
+            let width = self.gauge_width() as f32;
+            let height = (pixels as f32 * fill.clamp(0.0, 1.0)).round();
+            let x = pixels as f32 - width;
+            let y = pixels as f32 - height;
+            graphics
+                .fill_rectangle(gauge_color, x, y, width, height)
+                .unwrap();
+        }
+        drop(graphics);
         bitmap.as_hicon()
     }
 }
@@ -46,7 +195,7 @@ impl<'gdip> NotifyIcon<'gdip> {
     /// Caller must guarantee that the provided window will stay valid
     /// for the entire lifetime of the returned instance.
     pub unsafe fn new(window: HWND, id: u32, gdi_plus: &'gdip GdiPlus) -> Result<NotifyIcon> {
-        let icon_factory = IconFactory::new(gdi_plus);
+        let mut icon_factory = IconFactory::new(gdi_plus);
         let icon = icon_factory.render_icon("⏳", Color::WHITE);
         let notify_icon_data = NOTIFYICONDATAW {
             cbSize: size_of::<NOTIFYICONDATAW>() as u32,
@@ -74,6 +223,27 @@ impl<'gdip> NotifyIcon<'gdip> {
         }
     }
 
+    /// Switches the icon to system colors. Takes effect on the next update.
+    pub fn set_high_contrast(&mut self, colors: Option<HighContrastColors>) {
+        self.icon_factory.high_contrast = colors;
+    }
+
+    /// Shows a bar filled to `fill` (0 to 1) next to the text. Takes effect on the next update.
+    pub fn set_gauge(&mut self, gauge: Option<(f32, Color)>) {
+        self.icon_factory.set_gauge(gauge);
+    }
+
+    /// Draws a dark outline around the text, unless high contrast colors are used.
+    /// Takes effect on the next update.
+    pub fn set_outline(&mut self, outline: bool) {
+        self.icon_factory.outline = outline;
+    }
+
+    /// Scales the icon for the DPI of the taskbar. Takes effect on the next update.
+    pub fn set_dpi(&mut self, dpi: u32) {
+        self.icon_factory.set_dpi(dpi);
+    }
+
     pub fn update(&mut self, tip: &str, icon: &str, color: Color) {
         let icon = self.icon_factory.render_icon(icon, color);
         let mut notify_icon_data = NOTIFYICONDATAW {
@@ -86,12 +256,34 @@ impl<'gdip> NotifyIcon<'gdip> {
         };
         let tip: Vec<u16> = tip.encode_utf16().collect();
         // ensure at least one character remains NULL
-        let len = min(notify_icon_data.szTip.len() - 1, tip.len());
-        notify_icon_data.szTip[..len].copy_from_slice(&tip[..len]);
+        let tip = truncate_utf16_at_boundary(&tip, notify_icon_data.szTip.len() - 1);
+        notify_icon_data.szTip[..tip.len()].copy_from_slice(tip);
         // SAFETY: Notify icon data is a local structure
         // This might fail occasionally depending on the Taskbar state, so ignore the return code
         _ = unsafe { Shell_NotifyIconW(NIM_MODIFY, &notify_icon_data) };
     }
+
+    /// Shows an informational balloon next to the icon.
+    pub fn show_balloon(&mut self, title: &str, text: &str) {
+        let mut notify_icon_data = NOTIFYICONDATAW {
+            cbSize: size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: self.window,
+            uID: self.id,
+            uFlags: NIF_INFO,
+            dwInfoFlags: NIIF_INFO,
+            ..Default::default()
+        };
+        let title: Vec<u16> = title.encode_utf16().collect();
+        let title = truncate_utf16_at_boundary(&title, notify_icon_data.szInfoTitle.len() - 1);
+        notify_icon_data.szInfoTitle[..title.len()].copy_from_slice(title);
+        let text: Vec<u16> = text.encode_utf16().collect();
+        let text = truncate_utf16_at_boundary(&text, notify_icon_data.szInfo.len() - 1);
+        notify_icon_data.szInfo[..text.len()].copy_from_slice(text);
+        // SAFETY: Notify icon data is a local structure
+        if unsafe { Shell_NotifyIconW(NIM_MODIFY, &notify_icon_data) }.0 == 0 {
+            error!("Failed to show notification balloon");
+        }
+    }
 }
 
 impl Drop for NotifyIcon<'_> {
@@ -109,3 +301,19 @@ impl Drop for NotifyIcon<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation_keeps_surrogate_pairs() {
+        let text: Vec<u16> = "ab😀c".encode_utf16().collect();
+        assert_eq!(truncate_utf16_at_boundary(&text, 10), &text[..]);
+        assert_eq!(truncate_utf16_at_boundary(&text, 4), &text[..4]);
+        // Cutting after the high surrogate drops the whole pair
+        assert_eq!(truncate_utf16_at_boundary(&text, 3), &text[..2]);
+        assert_eq!(truncate_utf16_at_boundary(&text, 2), &text[..2]);
+        assert!(truncate_utf16_at_boundary(&text, 0).is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use crate::winapi::Files;
 use log::{Metadata, Record};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{Error as IoError, Write};
+use std::io::{Error as IoError, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use windows::Win32::Foundation::SYSTEMTIME;
@@ -14,12 +15,88 @@ pub struct FileLogger {
 }
 
 struct Inner {
-    buffer: Vec<u8>,
+    /// Lines logged before `init`, oldest first.
+    buffer: VecDeque<String>,
+    /// Total length of the lines in `buffer`.
+    buffer_len: usize,
+    /// Number of lines dropped from `buffer` to keep it within `MAX_BUFFER_LEN`.
+    dropped: usize,
     file: Option<File>,
+    /// Full path of `file`.
+    path: Option<PathBuf>,
 }
 
 const MAX_LOG_FILES: usize = 10;
+/// Limit on the size of lines kept in memory until the log file is created.
+const MAX_BUFFER_LEN: usize = 256 * 1024;
 const LOG_FILENAME_PATTERN: &str = "LilPowerMan????????_???.log";
+/// Repetitions after which the count is logged even if the error persists.
+const MAX_SILENT_REPEATS: u32 = 60;
+
+/// Collapses consecutive identical error messages into a repetition count.
+#[derive(Default)]
+pub struct RepeatedErrors {
+    last: Option<String>,
+    repeats: u32,
+}
+
+impl RepeatedErrors {
+    fn take_repeats(&mut self) -> Option<String> {
+        if self.repeats == 0 {
+            return None;
+        }
+        let line = format!(
+            "{} (repeated {} times)",
+            self.last.as_deref().unwrap_or_default(),
+            self.repeats
+        );
+        self.repeats = 0;
+        Some(line)
+    }
+
+    /// Returns the lines to be logged for another occurrence of the message.
+    fn record(&mut self, message: String) -> Vec<String> {
+        if self.last.as_ref() == Some(&message) {
+            self.repeats += 1;
+            if self.repeats < MAX_SILENT_REPEATS {
+                return vec![];
+            }
+            return self.take_repeats().into_iter().collect();
+        }
+        let mut lines: Vec<_> = self.take_repeats().into_iter().collect();
+        lines.push(message.clone());
+        self.last = Some(message);
+        lines
+    }
+
+    pub fn error(&mut self, message: String) {
+        for line in self.record(message) {
+            error!("{}", line);
+        }
+    }
+
+    /// Logs the pending repetition count, if any. Call once the error condition is gone.
+    pub fn clear(&mut self) {
+        if let Some(line) = self.take_repeats() {
+            error!("{}", line);
+        }
+        self.last = None;
+    }
+}
+
+impl Inner {
+    fn push_buffered(&mut self, line: String) {
+        self.buffer_len += line.len();
+        self.buffer.push_back(line);
+        while self.buffer_len > MAX_BUFFER_LEN {
+            let Some(oldest) = self.buffer.pop_front() else {
+                break;
+            };
+            self.buffer_len -= oldest.len();
+            self.dropped += 1;
+        }
+    }
+}
 
 fn format_log_filename_prefix(time: &SYSTEMTIME) -> String {
     format!(
@@ -33,7 +110,7 @@ fn format_log_filename(prefix: &str, counter: i32) -> String {
 }
 
 impl FileLogger {
-    fn new_log_file(path: &Path) -> Result<File, IoError> {
+    fn new_log_file(path: &Path) -> Result<(File, PathBuf), IoError> {
         // find existing log files
         let mut path = PathBuf::from(path);
         path.push(LOG_FILENAME_PATTERN);
@@ -89,25 +166,88 @@ impl FileLogger {
         }
 
         path.push(format_log_filename(&prefix, counter));
-        Files::create(path.as_os_str())
+        let file = Files::create(path.as_os_str())?;
+        Ok((file, path))
     }
 
     pub fn new() -> Self {
         FileLogger {
             inner: Mutex::new(Inner {
-                buffer: Vec::new(),
+                buffer: VecDeque::new(),
+                buffer_len: 0,
+                dropped: 0,
                 file: None,
+                path: None,
             }),
         }
     }
 
     pub fn init(&self, path: &Path) -> Result<(), IoError> {
-        let mut new_log = Self::new_log_file(path)?;
+        let (mut new_log, new_path) = Self::new_log_file(path)?;
         let mut inner = self.inner.lock().unwrap();
-        new_log.write_all(&std::mem::replace(&mut inner.buffer, Vec::new()))?;
+        if inner.dropped > 0 {
+            writeln!(new_log, "[{} earlier messages dropped]", inner.dropped)?;
+        }
+        for line in &inner.buffer {
+            new_log.write_all(line.as_bytes())?;
+        }
+        inner.buffer = VecDeque::new();
+        inner.buffer_len = 0;
+        inner.dropped = 0;
         inner.file = Some(new_log);
+        inner.path = Some(new_path);
         Ok(())
     }
+
+    /// Reads up to `max_len` bytes from the end of the current log file, starting at a line
+    /// boundary. Returns `None` if the log file has not been created yet.
+    pub fn read_tail(&self, max_len: u64) -> Result<Option<String>, IoError> {
+        let Some(path) = self.inner.lock().unwrap().path.clone() else {
+            return Ok(None);
+        };
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let start = len.saturating_sub(max_len);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        if start > 0 {
+            // The first line is most likely cut
+            let first_line_end = tail.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+            tail.drain(..first_line_end);
+        }
+        Ok(Some(String::from_utf8_lossy(&tail).into_owned()))
+    }
+
+    /// Deletes all log files next to the current one, except for the current one itself.
+    /// Files that cannot be deleted, e.g. because another instance holds them open, are skipped.
+    /// Returns the number of deleted files.
+    pub fn clear(&self) -> Result<usize, IoError> {
+        let Some(current) = self.inner.lock().unwrap().path.clone() else {
+            return Ok(0);
+        };
+        let mut path = current.clone();
+        path.set_file_name(LOG_FILENAME_PATTERN);
+        let logs: Result<Vec<_>, _> = Files::find(path.as_os_str()).collect();
+        let mut deleted = 0;
+        for log in logs? {
+            path.set_file_name(&log);
+            if path == current {
+                continue;
+            }
+            if let Err(err) = Files::delete(path.as_os_str()) {
+                warn!(
+                    "Failed to delete log file {}: {}",
+                    log.to_string_lossy(),
+                    err
+                );
+            } else {
+                deleted += 1;
+            }
+        }
+        info!("Cleared {} log files", deleted);
+        Ok(deleted)
+    }
 }
 
 impl log::Log for FileLogger {
@@ -134,7 +274,7 @@ impl log::Log for FileLogger {
         if let Some(file) = &mut inner.file {
             _ = file.write_all(s.as_bytes());
         } else {
-            inner.buffer.extend_from_slice(s.as_bytes());
+            inner.push_buffered(s);
         }
     }
 
@@ -206,6 +346,26 @@ mod tests {
         assert_eq!(actual, contents);
     }
 
+    #[test]
+    fn repeated_errors_are_collapsed() {
+        let mut errors = RepeatedErrors::default();
+        assert_eq!(errors.record(String::from("A")), vec!["A"]);
+        assert!(errors.record(String::from("A")).is_empty());
+        assert!(errors.record(String::from("A")).is_empty());
+        assert_eq!(
+            errors.record(String::from("B")),
+            vec!["A (repeated 2 times)", "B"]
+        );
+        for _ in 1..MAX_SILENT_REPEATS {
+            assert!(errors.record(String::from("B")).is_empty());
+        }
+        assert_eq!(
+            errors.record(String::from("B")),
+            vec![format!("B (repeated {} times)", MAX_SILENT_REPEATS)]
+        );
+        assert!(errors.record(String::from("B")).is_empty());
+    }
+
     #[test]
     fn clean_start() {
         // Arrange
@@ -355,4 +515,101 @@ mod tests {
         assert!(std::fs::exists(&path).expect("Failed to check file existence"));
         drop(file); // Ensure the file is open during the entire test
     }
+
+    #[test]
+    fn buffer_drops_oldest_lines() {
+        // Arrange
+        let mut path = prepare_dir(vec![]);
+        let logger = FileLogger::new();
+        let line = "2025-05-10T23:15:46.788[INFO][tests] Hello, 0000000!\n";
+        let count = MAX_BUFFER_LEN / line.len() + 3;
+        for i in 0..count {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("tests")
+                    .args(format_args!("Hello, {:07}!", i))
+                    .build(),
+            );
+        }
+
+        // Act
+        logger.init(&path).expect("Failed to initialize logger");
+
+        // Assert
+        path.push("LilPowerMan20250510_000.log");
+        let actual = std::fs::read_to_string(&path).expect("Failed to read file");
+        let kept = MAX_BUFFER_LEN / line.len();
+        let mut lines = actual.lines();
+        assert_eq!(
+            lines.next(),
+            Some(format!("[{} earlier messages dropped]", count - kept).as_str())
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with(&format!("Hello, {:07}!", count - kept)));
+        assert_eq!(lines.count(), kept - 1);
+    }
+
+    #[test]
+    fn tail_starts_at_line_boundary() {
+        // Arrange
+        let path = prepare_dir(vec![]);
+        let logger = FileLogger::new();
+        assert!(logger.read_tail(100).expect("Failed to read log").is_none());
+        logger.init(&path).expect("Failed to initialize logger");
+        for s in ["first", "second", "third"] {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("tests")
+                    .args(format_args!("Hello, {}!", s))
+                    .build(),
+            );
+        }
+
+        // Act
+        let tail = logger.read_tail(70).expect("Failed to read log");
+
+        // Assert
+        assert_eq!(
+            tail.as_deref(),
+            Some("2025-05-10T23:15:46.788[INFO][tests] Hello, third!\n")
+        );
+    }
+
+    #[test]
+    fn clear_keeps_current_file() {
+        // Arrange
+        let mut path = prepare_dir(vec![
+            "LilPowerMan20250509_000.log",
+            "LilPowerMan20250510_000.log",
+            "unrelated.log",
+        ]);
+        path.push("LilPowerMan20250509_001.log");
+        let file = Files::create(path.as_os_str()).expect("Failed to create test file");
+        path.pop();
+        let logger = FileLogger::new();
+        logger.init(&path).expect("Failed to initialize logger");
+
+        // Act
+        let deleted = logger.clear().expect("Failed to clear logs");
+
+        // Assert
+        assert_eq!(deleted, 2);
+        for (name, exists) in [
+            ("LilPowerMan20250509_000.log", false),
+            ("LilPowerMan20250509_001.log", true),
+            ("LilPowerMan20250510_000.log", false),
+            ("LilPowerMan20250510_001.log", true),
+            ("unrelated.log", true),
+        ] {
+            path.push(name);
+            let actual = std::fs::exists(&path).expect("Failed to check file existence");
+            assert_eq!(actual, exists, "{}", name);
+            path.pop();
+        }
+        drop(file); // Ensure the file is open during the entire test
+    }
 }
@@ -9,10 +9,16 @@ mod icons;
 mod logging;
 mod main_window;
 mod menu;
+mod metrics_log;
+#[cfg(feature = "rtss")]
+mod overlay_preview;
+#[cfg(feature = "rtss")]
 mod rtss;
 mod ryzenadj;
 mod settings;
+mod settings_dialog;
 mod singleton;
+mod update;
 mod versioned;
 mod winapi;
 
@@ -20,16 +26,55 @@ use gdip::GdiPlus;
 use log::{LevelFilter, Log};
 use logging::FileLogger;
 use main_window::MainWindow;
+use settings::{format_settings_json, SettingsStorage};
 use singleton::Singleton;
-use std::panic;
+use std::io::Write;
+use std::path::PathBuf;
+use std::{fs, io, panic, process};
 use winapi::show_error_message_box;
-use winapi::windows_message_loop;
+use winapi::{attach_parent_console, windows_message_loop};
+
+/// Writes the settings as JSON into the file, or to the console if there is none.
+/// Returns the exit code.
+fn dump_settings(path: Option<&String>) -> i32 {
+    let storage = match SettingsStorage::new() {
+        Ok(storage) => storage,
+        Err(err) => {
+            error!("Failed to open the settings: {}", err);
+            return 1;
+        }
+    };
+    let json = format_settings_json(&storage.load());
+    let result = match path {
+        Some(path) => fs::write(path, json),
+        None => {
+            if let Err(err) = attach_parent_console() {
+                // The output might still be redirected
+                debug!("Failed to attach to the parent console: {}", err);
+            }
+            io::stdout().write_all(json.as_bytes())
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("Failed to dump the settings: {}", err);
+            1
+        }
+    }
+}
 
 fn main() {
     let logger = FileLogger::new();
     // SAFETY: This is the first time we set a logger
     log::set_boxed_logger(Box::new(logger)).unwrap();
-    let last_arg = std::env::args().last().unwrap_or_else(|| String::from(""));
+    let args: Vec<String> = std::env::args().collect();
+    let csv_path = args
+        .iter()
+        .position(|a| a.eq_ignore_ascii_case("/csv"))
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from);
+    let last_arg = args.last().cloned().unwrap_or_else(|| String::from(""));
     if last_arg.eq_ignore_ascii_case("/debug") {
         log::set_max_level(LevelFilter::Debug);
     } else if last_arg.eq_ignore_ascii_case("/trace") {
@@ -47,13 +92,28 @@ fn main() {
     // SAFETY: We are sure that current logger is indeed a FileLogger
     let logger = unsafe { &*(log::logger() as *const dyn Log as *const FileLogger) };
     logger.init(&std::env::temp_dir()).unwrap();
+    if let Some(i) = args
+        .iter()
+        .position(|a| a.eq_ignore_ascii_case("/dump-settings"))
+    {
+        let path = args.get(i + 1).filter(|a| !a.starts_with('/'));
+        process::exit(dump_settings(path));
+    }
     if !Singleton::is_first_instance() {
         info!("Another instance found. Shutting down");
         show_error_message_box("The application is already running on this computer");
         return;
     }
     let gdi_plus = GdiPlus::new();
-    let _window = MainWindow::new(&gdi_plus);
+    let _window = match MainWindow::new(&gdi_plus, logger, csv_path) {
+        Ok(window) => window,
+        Err(err) => {
+            let message = format!("Failed to create application window: {}", err);
+            error!("{}", message);
+            show_error_message_box(&message);
+            return;
+        }
+    };
     windows_message_loop();
     info!("Graceful shutdown");
 }
@@ -1,43 +1,88 @@
+mod command_queue;
 mod commands;
 mod controller;
+mod hotkeys;
 mod id;
 mod model;
 mod view;
 
+use self::command_queue::{CommandQueue, WM_QUEUED_COMMAND};
+use self::commands::Command;
 use self::controller::Controller;
+use self::hotkeys::{HotkeyBindings, Hotkeys};
 use self::view::View;
 use crate::gdip::GdiPlus;
 use crate::icons::WM_NOTIFY_ICON;
-use crate::winapi::get_instance_handle;
+use crate::logging::FileLogger;
+use crate::settings_dialog;
+use crate::winapi::{ask_confirmation, get_instance_handle, set_clipboard_text};
 use std::marker::PhantomData;
 use std::mem::take;
 use std::ops::DerefMut;
+use std::path::PathBuf;
 use std::pin::Pin;
-use windows::core::{w, Error};
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use std::thread::sleep;
+use std::time::Duration;
+use windows::core::{w, Error, Owned};
+use windows::Win32::Foundation::{E_UNEXPECTED, HANDLE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::Power::{
+    RegisterPowerSettingNotification, HPOWERNOTIFY, POWERBROADCAST_SETTING,
+};
+use windows::Win32::System::SystemServices::GUID_CONSOLE_DISPLAY_STATE;
+use windows::Win32::UI::HiDpi::{
+    SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+};
+use windows::Win32::UI::Shell::NIN_BALLOONUSERCLICK;
 use windows::Win32::UI::WindowsAndMessaging::{
-    CreateWindowExW, DefWindowProcW, GetWindowLongPtrW, KillTimer, PostQuitMessage,
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetWindowLongPtrW, KillTimer, PostQuitMessage,
     RegisterClassExW, SetProcessDPIAware, SetTimer, SetWindowLongPtrW, CREATESTRUCTW,
-    CW_USEDEFAULT, GWLP_USERDATA, HWND_MESSAGE, WINDOW_EX_STYLE, WM_COMMAND, WM_CREATE, WM_DESTROY,
-    WM_EXITMENULOOP, WM_NCCREATE, WM_RBUTTONUP, WM_TIMER, WNDCLASSEXW, WS_OVERLAPPED,
+    CW_USEDEFAULT, DEVICE_NOTIFY_WINDOW_HANDLE, GWLP_USERDATA, HWND_MESSAGE,
+    PBT_POWERSETTINGCHANGE, WINDOW_EX_STYLE, WM_COMMAND, WM_CREATE, WM_DESTROY, WM_EXITMENULOOP,
+    WM_NCCREATE, WM_POWERBROADCAST, WM_RBUTTONUP, WM_TIMER, WNDCLASSEXW, WS_OVERLAPPED,
 };
 
+const MAIN_TIMER_PERIOD_MS: u32 = 1000;
+/// Main timer period while the display is off.
+const IDLE_TIMER_PERIOD_MS: u32 = 30000;
+/// How much of the log file is copied for bug reports.
+const LOG_TAIL_LEN: u64 = 16 * 1024;
+const SET_TIMER_ATTEMPTS: u32 = 3;
+const SET_TIMER_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 pub struct MainWindow<'gdip> {
     handle: HWND,
     gdi_plus: &'gdip GdiPlus,
     controller: Option<Controller>,
     view: Option<View<'gdip>>,
+    command_queue: Option<CommandQueue>,
     live_timers: Vec<id::Timer>,
+    settings_dialog_open: bool,
+    display_notification: Option<Owned<HPOWERNOTIFY>>,
+    hotkeys: Option<Hotkeys>,
+    /// Whether polling is slowed down because the display is off.
+    idle: bool,
+    csv_path: Option<PathBuf>,
+    logger: &'static FileLogger,
     // This marks MainWindow as !Send and !Sync
     _marker: PhantomData<*const ()>,
 }
 
 impl<'gdip> MainWindow<'gdip> {
-    pub fn new(gdi_plus: &'gdip GdiPlus) -> Pin<Box<Self>> {
-        // SAFETY: The call does not have any preconditions and is always sound
-        let result = unsafe { SetProcessDPIAware() };
-        if result.0 == 0 {
-            warn!("SetProcessDPIAware failed");
+    /// Fails if the window cannot be created, e.g. when the desktop heap is exhausted.
+    pub fn new(
+        gdi_plus: &'gdip GdiPlus,
+        logger: &'static FileLogger,
+        csv_path: Option<PathBuf>,
+    ) -> Result<Pin<Box<Self>>, Error> {
+        // Per-monitor awareness lets the icons follow the taskbar to a display with another scale
+        // SAFETY: The calls do not have any preconditions and are always sound
+        if let Err(err) =
+            unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) }
+        {
+            debug!("Per-monitor DPI awareness is not available: {}", err);
+            if unsafe { SetProcessDPIAware() }.0 == 0 {
+                warn!("SetProcessDPIAware failed");
+            }
         }
         let window_class_name = w!("MainWindow");
         let instance = get_instance_handle();
@@ -59,7 +104,14 @@ impl<'gdip> MainWindow<'gdip> {
             gdi_plus,
             controller: None,
             view: None,
+            command_queue: None,
             live_timers: vec![],
+            settings_dialog_open: false,
+            display_notification: None,
+            hotkeys: None,
+            idle: false,
+            csv_path,
+            logger,
             _marker: PhantomData,
         });
         // SAFETY: The function is sound as long as all arguments are valid
@@ -78,13 +130,22 @@ impl<'gdip> MainWindow<'gdip> {
                 instance,
                 Some(window.deref_mut() as *mut _ as _),
             )
+        }?;
+        if handle != window.handle {
+            error!(
+                "Window creation did not set the handle: got {:?}, expected {:?}",
+                window.handle, handle
+            );
+            // SAFETY: The handle has just been created by this thread
+            if let Err(err) = unsafe { DestroyWindow(handle) } {
+                error!("Failed to destroy the window: {}", err);
+            }
+            return Err(Error::new(
+                E_UNEXPECTED,
+                "Window creation did not set the handle",
+            ));
         }
-        .unwrap();
-        assert_eq!(
-            handle, window.handle,
-            "Window creation did not set the handle"
-        );
-        window
+        Ok(window)
     }
 
     fn with_controller(&mut self, f: impl FnOnce(&mut Controller)) {
@@ -97,35 +158,239 @@ impl<'gdip> MainWindow<'gdip> {
         }
     }
 
+    fn start_timer(&mut self, timer: id::Timer, period_ms: u32) -> Result<(), Error> {
+        let timer_id = timer as usize;
+        let mut attempt = 1;
+        loop {
+            // SAFETY: The window handle is valid, no callback is provided
+            let result = unsafe { SetTimer(self.handle, timer_id, period_ms, None) };
+            if result != 0 {
+                // Restarting a live timer only changes its period
+                if !self.live_timers.contains(&timer) {
+                    self.live_timers.push(timer);
+                }
+                return Ok(());
+            }
+            let err = Error::from_win32();
+            if attempt == SET_TIMER_ATTEMPTS {
+                return Err(err);
+            }
+            warn!("Set timer failed (attempt {}): {}", attempt, err);
+            attempt += 1;
+            sleep(SET_TIMER_RETRY_DELAY);
+        }
+    }
+
+    fn stop_timer(&mut self, timer: id::Timer) {
+        if !self.live_timers.contains(&timer) {
+            return;
+        }
+        self.live_timers.retain(|&t| t != timer);
+        // SAFETY: The timer was created before its id got into live timers
+        if let Err(err) = unsafe { KillTimer(self.handle, timer as usize) } {
+            warn!("Failed to stop the timer: {}", err);
+        }
+    }
+
+    fn start_overlay_timer(&mut self) {
+        let overlay_period = self
+            .controller
+            .as_ref()
+            .and_then(|c| c.overlay_timer_period());
+        if let Some(period) = overlay_period {
+            if let Err(err) = self.start_timer(id::Timer::Overlay, period) {
+                warn!("Overlay timer failed, using the main timer: {}", err);
+                self.with_controller(|c| c.on_overlay_timer_failed());
+            }
+        }
+    }
+
+    fn register_display_notification(&mut self) {
+        // SAFETY: The window handle is valid, the notification is unregistered on drop
+        let result = unsafe {
+            RegisterPowerSettingNotification(
+                HANDLE(self.handle.0),
+                &GUID_CONSOLE_DISPLAY_STATE,
+                DEVICE_NOTIFY_WINDOW_HANDLE,
+            )
+        };
+        match result {
+            // SAFETY: We own the returned handle
+            Ok(handle) => self.display_notification = Some(unsafe { Owned::new(handle) }),
+            Err(err) => warn!("Failed to subscribe to display state changes: {}", err),
+        }
+    }
+
+    fn install_hotkeys(&mut self) {
+        let (Some(controller), Some(queue)) = (&self.controller, &self.command_queue) else {
+            return;
+        };
+        let settings = &controller.get_model().settings;
+        let bindings = HotkeyBindings {
+            push_to_boost: settings.get_push_to_boost_key(),
+            step_app_tdp_up: settings.get_step_app_tdp_up_key(),
+            step_app_tdp_down: settings.get_step_app_tdp_down_key(),
+        };
+        match Hotkeys::install(bindings, queue.sender()) {
+            Ok(hotkeys) => self.hotkeys = hotkeys,
+            Err(err) => warn!("Failed to set up the hotkeys: {}", err),
+        }
+    }
+
+    fn on_display_state_changed(&mut self, display_on: bool) {
+        if !display_on && !self.idle {
+            let enabled = self
+                .controller
+                .as_ref()
+                .is_some_and(|c| c.get_model().settings.is_pause_when_display_off_enabled());
+            if !enabled {
+                return;
+            }
+            debug!("Display is off, slowing down polling");
+            if let Err(err) = self.start_timer(id::Timer::Main, IDLE_TIMER_PERIOD_MS) {
+                warn!("Failed to slow down the timer: {}", err);
+                return;
+            }
+            self.stop_timer(id::Timer::Overlay);
+            self.idle = true;
+        } else if display_on && self.idle {
+            debug!("Display is on, resuming polling");
+            self.idle = false;
+            if let Err(err) = self.start_timer(id::Timer::Main, MAIN_TIMER_PERIOD_MS) {
+                error!("Failed to restore the timer: {}", err);
+            }
+            self.start_overlay_timer();
+            // Limits might have been changed while nobody was watching
+            self.with_controller(|c| c.on_timer());
+        }
+    }
+
+    fn show_settings_dialog(&mut self) {
+        if self.settings_dialog_open {
+            return;
+        }
+        let Some(mut values) = self
+            .controller
+            .as_ref()
+            .map(Controller::get_settings_dialog_values)
+        else {
+            return;
+        };
+        self.settings_dialog_open = true;
+        let accepted = settings_dialog::show(&mut values);
+        self.settings_dialog_open = false;
+        if !accepted {
+            return;
+        }
+        self.with_controller(|c| c.on_settings_dialog_accepted(values));
+        // The overlay update rate might have changed
+        self.stop_timer(id::Timer::Overlay);
+        if !self.idle {
+            self.start_overlay_timer();
+        }
+    }
+
+    fn clear_logs(&mut self) {
+        let text = "Delete the log files of previous sessions?";
+        if !ask_confirmation(self.handle, text) {
+            return;
+        }
+        let result = self.logger.clear();
+        self.with_controller(|c| c.on_logs_cleared(result));
+    }
+
+    fn copy_log_tail(&mut self) {
+        let result = self.logger.read_tail(LOG_TAIL_LEN).and_then(|tail| {
+            let Some(tail) = tail else {
+                return Ok(false);
+            };
+            set_clipboard_text(self.handle, &tail)?;
+            Ok(true)
+        });
+        self.with_controller(|c| c.on_log_tail_copied(result));
+    }
+
+    /// Applies commands queued by any thread, including menu commands.
+    fn apply_queued_commands(&mut self) {
+        let Some(queue) = &self.command_queue else {
+            return;
+        };
+        let commands: Vec<_> = queue.drain().collect();
+        for command in commands {
+            if command == Command::OpenSettings {
+                self.show_settings_dialog();
+            } else if command == Command::ClearLogs {
+                self.clear_logs();
+            } else if command == Command::CopyLogTail {
+                self.copy_log_tail();
+            } else if let Command::SetAllAppsTdp(limit) = command {
+                let text = format!(
+                    "Set the limit of all known applications to {} W?",
+                    limit as f32 / 1000.0
+                );
+                if ask_confirmation(self.handle, &text) {
+                    self.with_controller(|c| c.on_command(command));
+                }
+            } else {
+                self.with_controller(|c| c.on_command(command));
+            }
+        }
+    }
+
     fn process_message(&mut self, message: u32, w_param: WPARAM, l_param: LPARAM) -> Option<isize> {
         match message {
             WM_CREATE => {
                 // SAFETY: The window handle is valid now and will stay valid
                 //   until view and controller are dropped
                 self.view = Some(unsafe { View::new(self.handle, self.gdi_plus) });
-                self.controller = Some(unsafe { Controller::new(self.handle) });
-                let result = unsafe { SetTimer(self.handle, id::Timer::Main as usize, 1000, None) };
-                if result == 0 {
-                    panic!("Set timer failed: {}", Error::from_win32());
+                let command_queue = CommandQueue::new(self.handle);
+                let commands = command_queue.sender();
+                self.command_queue = Some(command_queue);
+                self.controller =
+                    Some(unsafe { Controller::new(self.handle, commands, self.csv_path.take()) });
+                self.register_display_notification();
+                self.install_hotkeys();
+                if let Err(err) = self.start_timer(id::Timer::Main, MAIN_TIMER_PERIOD_MS) {
+                    error!("Set timer failed: {}", err);
+                    // Makes CreateWindowExW fail, which is reported by the caller
+                    return Some(-1);
                 }
-                self.live_timers.push(id::Timer::Main);
+                self.start_overlay_timer();
             }
             WM_TIMER => {
                 if w_param.0 == id::Timer::Main as usize {
+                    // Catches commands whose notification could not be posted
+                    self.apply_queued_commands();
                     self.with_controller(|c| c.on_timer());
+                } else if w_param.0 == id::Timer::Overlay as usize {
+                    self.with_controller(|c| c.on_overlay_timer());
                 }
             }
             WM_COMMAND => {
                 let msg_source = w_param.0 as u32 >> 16;
                 let id = w_param.0 as u16 as u32;
                 if msg_source == 0 {
-                    if let Some(view) = &self.view {
-                        if let Some(cmd) = view.get_command_for_menu_item(id) {
-                            self.with_controller(|c| c.on_command(cmd));
-                        }
+                    let cmd = self
+                        .view
+                        .as_ref()
+                        .and_then(|v| v.get_command_for_menu_item(id));
+                    if let (Some(cmd), Some(queue)) = (cmd, &self.command_queue) {
+                        queue.sender().send(cmd);
                     }
                 }
             }
+            WM_QUEUED_COMMAND => {
+                self.apply_queued_commands();
+            }
+            WM_POWERBROADCAST if w_param.0 as u32 == PBT_POWERSETTINGCHANGE => {
+                // SAFETY: The OS provides a valid structure with this event
+                let setting = unsafe { &*(l_param.0 as *const POWERBROADCAST_SETTING) };
+                if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE {
+                    // 0 is off, 1 is on, 2 is dimmed
+                    self.on_display_state_changed(setting.Data[0] != 0);
+                }
+                return Some(1);
+            }
             WM_EXITMENULOOP => {
                 self.with_controller(|c| c.on_menu_dismissed());
             }
@@ -136,14 +401,22 @@ impl<'gdip> MainWindow<'gdip> {
                     let x = w_param.0 as i16 as i32;
                     let y = (w_param.0 >> 16) as i16 as i32;
                     self.with_controller(|c| c.on_notify_icon_click(id, x, y));
+                } else if event == NIN_BALLOONUSERCLICK {
+                    self.with_controller(|c| c.on_notify_balloon_click());
                 }
             }
             WM_DESTROY => {
+                self.hotkeys = None;
+                self.display_notification = None;
+                self.command_queue = None;
                 self.view = None;
                 self.controller = None;
                 for timer in take(&mut self.live_timers) {
                     // SAFETY: The timer was created before its id got into live timers
-                    unsafe { KillTimer(self.handle, timer as usize).unwrap() }
+                    if let Err(err) = unsafe { KillTimer(self.handle, timer as usize) } {
+                        // The window is going away anyway, so the timer cannot fire again
+                        warn!("Failed to stop the timer on exit: {}", err);
+                    }
                 }
                 // SAFETY: This is a typical response to WM_DESTROY message
                 unsafe { PostQuitMessage(0) }
@@ -0,0 +1,64 @@
+use super::commands::Command;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{PostMessageW, WM_APP};
+
+/// Posted to the window whenever a command is queued.
+pub const WM_QUEUED_COMMAND: u32 = WM_APP + 2;
+
+/// Queues commands from any thread to be applied on the thread that owns the window.
+#[derive(Clone)]
+pub struct CommandSender {
+    sender: Sender<Command>,
+    // HWND is not `Send`, but posting messages to it from other threads is fine
+    window: isize,
+}
+
+impl CommandSender {
+    /// Returns `false` if the window is gone and the command will never be applied.
+    pub fn send(&self, command: Command) -> bool {
+        if self.sender.send(command).is_err() {
+            debug!("Command queue is closed, dropping the command");
+            return false;
+        }
+        let window = HWND(self.window as _);
+        // SAFETY: Posting a message is sound for any window handle, even a stale one
+        let result = unsafe { PostMessageW(window, WM_QUEUED_COMMAND, WPARAM(0), LPARAM(0)) };
+        if let Err(err) = result {
+            // The command will be picked up on the next timer tick
+            warn!(
+                "Failed to notify the window about a queued command: {}",
+                err
+            );
+        }
+        true
+    }
+}
+
+/// Receiving end of the command queue, owned by the window.
+pub struct CommandQueue {
+    sender: CommandSender,
+    receiver: Receiver<Command>,
+}
+
+impl CommandQueue {
+    pub fn new(window: HWND) -> Self {
+        let (sender, receiver) = channel();
+        CommandQueue {
+            sender: CommandSender {
+                sender,
+                window: window.0 as isize,
+            },
+            receiver,
+        }
+    }
+
+    pub fn sender(&self) -> CommandSender {
+        self.sender.clone()
+    }
+
+    /// Returns the queued commands without blocking.
+    pub fn drain(&self) -> impl Iterator<Item = Command> + '_ {
+        self.receiver.try_iter()
+    }
+}
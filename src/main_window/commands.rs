@@ -1,10 +1,43 @@
+use crate::settings::{ChargeColorScheme, TdpLimitKind};
+use crate::update::Release;
 use std::ffi::OsString;
+use std::time::Duration;
 
 #[derive(Clone, PartialEq)]
 pub enum Command {
     Observe,
     ResetApplicationTdp(OsString),
     SetApplicationTdp(OsString, u32),
+    /// Moves the foreground application limit to the next option up (`true`) or down (`false`).
+    StepApplicationTdp(bool),
+    /// Sets the same limit for every recent application and every one that has a limit already.
+    SetAllAppsTdp(u32),
+    /// Toggles whether the application limit only applies while it is full-screen.
+    ToggleApplicationFullscreenOnly(OsString),
     SetTdp(u32),
+    BoostFor(Duration),
+    /// The push-to-boost key was pressed (`true`) or released (`false`).
+    PushToBoost(bool),
+    SetTrayLimit(TdpLimitKind),
+    SetChargeColorScheme(ChargeColorScheme),
+    /// Forgets the highest readings seen so far this session.
+    ResetSessionPeaks,
+    OpenSettings,
+    /// Deletes the log files of previous sessions, after asking the user.
+    ClearLogs,
+    /// Copies the end of the current log file to the clipboard, for bug reports.
+    CopyLogTail,
+    #[cfg(feature = "rtss")]
+    PreviewOverlay,
+    #[cfg(feature = "rtss")]
+    CloseOverlayPreview,
+    /// Copies the overlay layout to the clipboard as a snippet that can be shared.
+    #[cfg(feature = "rtss")]
+    CopyOverlayLayout,
+    /// Applies an overlay layout snippet from the clipboard.
+    #[cfg(feature = "rtss")]
+    PasteOverlayLayout,
+    /// A newer version has been found by the background update check.
+    ShowUpdate(Release),
     Exit,
 }
@@ -1,32 +1,134 @@
+use super::command_queue::CommandSender;
 use super::commands::Command;
 use super::id;
-use super::model::{Model, PopupMenuModel, PopupMenuType, TdpModel, TdpState};
-use crate::battery::{BatteriesIterator, Battery, BatteryStatus, Error as BatteryError};
-use crate::rtss::{Error as RtssError, Rtss};
-use crate::ryzenadj::RyzenAdj;
-use crate::settings::{SettingsStorage, TdpSetting};
-use crate::winapi::{get_fg_application_pid, get_self_pid, show_error_message_box};
+use super::model::{
+    BatteryRuntime, Model, PopupMenuModel, PopupMenuType, PowerSourceModel, TdpLimits, TdpModel,
+    TdpState,
+};
+use crate::battery::{
+    get_power_source, is_battery_saver_on, BatteriesIterator, Battery, BatteryDetails,
+    BatteryStatus, Error as BatteryError, PowerSource,
+};
+use crate::gdip::Color;
+use crate::icons::HighContrastColors;
+use crate::logging::RepeatedErrors;
+use crate::metrics_log::{MetricsLog, MetricsRow};
+#[cfg(feature = "rtss")]
+use crate::rtss::{
+    format_layout_snippet, parse_layout_snippet, ActiveProfile, Error as RtssError, OverlayMetrics,
+    Rtss,
+};
+use crate::ryzenadj::{ApplyStats, Error as RyzenAdjError, RyzenAdj, TdpSnapshot, ThrottleStatus};
+use crate::settings::{
+    get_package_name, AppliedTdp, SettingsStorage, StockTdp, TdpSetting, TemperatureUnit,
+};
+use crate::settings_dialog::SettingsDialogValues;
+use crate::update::{self, Release};
+#[cfg(feature = "rtss")]
+use crate::winapi::{get_clipboard_text, get_local_time, set_clipboard_text};
+use crate::winapi::{
+    get_fg_application_pid, get_fg_window_kind, get_package_app_id, get_process_names,
+    get_self_pid, get_sys_color, get_taskbar_dpi, is_elevated, is_high_contrast, open_url,
+    play_alert_sound, show_error_message_box, FgWindowKind,
+};
 use std::collections::VecDeque;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
+use std::io::Error as IoError;
 use std::mem::take;
 use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use windows::core::{Error, Owned, PWSTR};
-use windows::Win32::Foundation::{ERROR_NO_SUCH_DEVICE, HWND, MAX_PATH};
+use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_NO_SUCH_DEVICE, HWND, MAX_PATH};
+use windows::Win32::Graphics::Gdi::{COLOR_WINDOW, COLOR_WINDOWTEXT};
 use windows::Win32::System::Threading::{
     OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
 };
 use windows::Win32::UI::WindowsAndMessaging::DestroyWindow;
 
 const MAX_RECENT_APPLICATIONS: usize = 5;
+/// Menu options offered on every chip, the stock limits can only add higher ones.
+const DEFAULT_TDP_OPTIONS: [u32; 7] = [5000, 7500, 10000, 15000, 20000, 24000, 28000];
+const MIN_TDP_OPTION: u32 = 5000;
+/// Highest limit ever offered, saved limits above it are clamped.
+const MAX_TDP_OPTION: u32 = 65000;
+/// Step between the menu options when fine options are enabled.
+const FINE_TDP_OPTION_STEP: u32 = 1000;
+/// Deviation (mW) from an applied limit that is not considered an external change.
+const EXTERNAL_CHANGE_TOLERANCE: u32 = 500;
+/// Shortest boost for a tap of the push-to-boost key.
+const PUSH_BOOST_MIN_DURATION: Duration = Duration::from_secs(3);
+/// Limit changes that follow an applied one sooner than this wait for the next poll.
+const LIMIT_CHANGE_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Delay before looking for a lost battery again after the search has failed.
+const BATTERY_SEARCH_COOLDOWN: Duration = Duration::from_secs(30);
+/// How often the limit write statistics are logged, if there were any writes.
+const APPLY_STATS_LOG_PERIOD: Duration = Duration::from_secs(10 * 60);
+/// Consecutive failures to apply a limit after which the user is alerted.
+const APPLY_FAILURE_ALERT_COUNT: u32 = 3;
+/// Samples of the measured limit kept for the tooltip history, one minute in total.
+const TDP_HISTORY_LEN: usize = 20;
+const TDP_HISTORY_PERIOD: Duration = Duration::from_secs(3);
+/// Listing the processes takes a while, so it is done less often than polling.
+const COMPETING_TOOLS_CHECK_PERIOD: Duration = Duration::from_secs(10);
 
 /// Controller owns the model and processes events coming from the window.
 pub struct Controller {
     window: HWND,
+    commands: CommandSender,
     ryzen_adj: Option<RyzenAdj>,
     battery: Option<Battery>,
+    /// When a lost battery may be looked for again, after the last search has failed.
+    battery_search_after: Option<Instant>,
+    #[cfg(feature = "rtss")]
     rtss: Rtss,
+    #[cfg(feature = "rtss")]
+    last_battery: Option<BatteryStatus>,
+    #[cfg(feature = "rtss")]
+    fps_errors: RepeatedErrors,
+    #[cfg(feature = "rtss")]
+    overlay_errors: RepeatedErrors,
+    /// Framerate of the foreground application, if RTSS reports it.
+    fps: Option<f32>,
+    /// Period of the dedicated overlay timer, `None` if the overlay rides the main timer.
+    overlay_timer_period: Option<u32>,
     settings_storage: SettingsStorage,
     self_path: Option<OsString>,
+    boost_until: Option<Instant>,
+    /// When the push-to-boost key was pressed, `None` if it is not held.
+    push_boost_since: Option<Instant>,
+    /// When a limit change command was last applied without waiting for the poll.
+    limit_change_applied: Option<Instant>,
+    last_fg_app: Option<OsString>,
+    fg_app_protected: bool,
+    fg_app_fullscreen: bool,
+    forced_target: Option<u32>,
+    forced_since: Instant,
+    /// Target that has been applied successfully and is expected to stay in effect.
+    applied_target: Option<u32>,
+    tdp_errors: RepeatedErrors,
+    /// Failed attempts to apply a limit since the last successful one.
+    apply_failures: u32,
+    /// Measured limits of the kind shown in the tray, oldest first.
+    tdp_history: VecDeque<u32>,
+    tdp_history_sampled: Option<Instant>,
+    /// `false` if the chip rejects limit changes as unsupported, so they are not attempted.
+    tdp_supported: bool,
+    /// Running tool that changes the limits as well, no limits are written while it runs.
+    competing_tool: Option<OsString>,
+    competing_tools_checked: Option<Instant>,
+    /// Limit writes since the last explicit TDP choice.
+    apply_stats: ApplyStats,
+    apply_stats_logged: Instant,
+    /// Power source and the moment it was switched to, unknown if it was in use at startup.
+    power_source: Option<(PowerSource, Option<Instant>)>,
+    metrics_log: Option<MetricsLog>,
+    /// Used to compute the charge percentage in the metrics log.
+    full_charged_capacity: Option<u32>,
+    /// Kept in the settings, so that restarts do not check more often.
+    last_update_check: Option<SystemTime>,
+    /// Text of the update notification and the download page it leads to.
+    update_balloon: Option<(String, String)>,
     model: Model,
 }
 
@@ -34,18 +136,31 @@ impl Controller {
     /// # Safety
     ///
     /// The window handle should stay valid for the entire lifetime of the retutned instance.
-    pub unsafe fn new(window: HWND) -> Self {
-        let ryzen_adj = RyzenAdj::new().map_or_else(
-            |err| {
-                show_error_message_box(format!("Failed to initialize RyzenAdj: {}", err).as_str());
-                None
-            },
-            |r| {
-                trace!("RyzenAdj initialized");
-                Some(r)
-            },
-        );
-        let battery = BatteriesIterator::new().next().and_then(|r| {
+    pub unsafe fn new(window: HWND, commands: CommandSender, csv_path: Option<PathBuf>) -> Self {
+        let ryzen_adj = RyzenAdj::new()
+            .and_then(|r| {
+                // Some systems allow the driver to be used unprivileged, so try it before nagging
+                match r.get_table().err() {
+                    Some(err) if err.is_access_error() => Err(err),
+                    // Reported as the TDP icon, the table may become readable later
+                    Some(err) => {
+                        warn!("Failed to read the RyzenAdj table: {}", err);
+                        Ok(r)
+                    }
+                    None => Ok(r),
+                }
+            })
+            .map_or_else(
+                |err| {
+                    show_error_message_box(&Self::get_ryzen_adj_error_message(&err));
+                    None
+                },
+                |r| {
+                    trace!("RyzenAdj initialized");
+                    Some(r)
+                },
+            );
+        let battery = Self::find_battery().and_then(|r| {
             r.map_or_else(
                 |err| {
                     show_error_message_box(format!("Failed to get battery info: {}", err).as_str());
@@ -62,33 +177,387 @@ impl Controller {
             "All subsystems failed to initialize"
         );
 
-        let rtss = Rtss::new();
-        let settings_storage = SettingsStorage::new();
-        let model = Model::new(&settings_storage);
-        Controller {
+        let (settings_storage, storage_error) = match SettingsStorage::new() {
+            Ok(storage) => (storage, None),
+            Err(err) => {
+                error!("Failed to open the settings key: {}", err);
+                (SettingsStorage::in_memory(), Some(err))
+            }
+        };
+        let mut model = Model::new(&settings_storage);
+        let last_update_check = settings_storage.load_last_update_check();
+        if let Some(err) = storage_error {
+            model.notification = Some(format!(
+                "Settings cannot be saved and will be lost on exit: {}",
+                err
+            ));
+        } else if settings_storage.is_read_only() {
+            model.notification = Some(String::from(
+                "Settings were saved by a newer version of LilPowerMan, changes will be lost on exit",
+            ));
+        }
+        let overlay_timer_period = Self::get_overlay_timer_period(&model);
+        let metrics_log = csv_path
+            .or_else(|| model.settings.get_csv_log_path().map(PathBuf::from))
+            .and_then(|path| {
+                MetricsLog::open(&path)
+                    .map_err(|err| error!("Failed to open {}: {}", path.display(), err))
+                    .ok()
+            });
+        let full_charged_capacity = metrics_log.as_ref().and_then(|_| {
+            battery
+                .as_ref()
+                .and_then(|b| b.get_details().ok())
+                .and_then(|d| d.full_charged_capacity)
+        });
+        let mut controller = Controller {
             window,
+            commands,
             ryzen_adj,
             battery,
-            rtss,
+            battery_search_after: None,
+            #[cfg(feature = "rtss")]
+            rtss: Rtss::new(settings_storage.load_battery_graph_peak()),
+            #[cfg(feature = "rtss")]
+            last_battery: None,
+            #[cfg(feature = "rtss")]
+            fps_errors: RepeatedErrors::default(),
+            #[cfg(feature = "rtss")]
+            overlay_errors: RepeatedErrors::default(),
+            fps: None,
+            overlay_timer_period,
             settings_storage,
             model,
             self_path: Self::get_self_path().ok(),
+            boost_until: None,
+            push_boost_since: None,
+            limit_change_applied: None,
+            last_fg_app: None,
+            fg_app_protected: false,
+            fg_app_fullscreen: false,
+            forced_target: None,
+            forced_since: Instant::now(),
+            applied_target: None,
+            tdp_errors: RepeatedErrors::default(),
+            apply_failures: 0,
+            tdp_history: VecDeque::new(),
+            tdp_history_sampled: None,
+            tdp_supported: true,
+            competing_tool: None,
+            competing_tools_checked: None,
+            apply_stats: ApplyStats::default(),
+            apply_stats_logged: Instant::now(),
+            power_source: None,
+            metrics_log,
+            full_charged_capacity,
+            last_update_check,
+            update_balloon: None,
+        };
+        controller.validate_settings();
+        controller.check_competing_tools();
+        controller.probe_tdp_support();
+        controller.record_stock_tdp();
+        controller.reconcile_applied_tdp();
+        controller.apply_on_startup();
+        controller.model.high_contrast = Self::get_high_contrast_colors();
+        controller.model.dpi = get_taskbar_dpi();
+        // Battery health can be shown in the charge icon tooltip
+        controller.model.battery_details = controller.read_battery_details();
+        controller
+    }
+
+    fn read_battery_details(&self) -> Option<BatteryDetails> {
+        self.battery.as_ref().and_then(|b| {
+            b.get_details()
+                .map_err(|err| error!("Failed to get battery details: {}", err))
+                .ok()
+        })
+    }
+
+    /// Writes the current limits back, to find out whether the chip allows changing them
+    /// before the user tries to. Nothing is written while limit changes are paused,
+    /// or while observing without a limit to apply at startup.
+    fn probe_tdp_support(&mut self) {
+        let settings = &self.model.settings;
+        let observing = settings.get_tdp_setting() == TdpSetting::Tracking
+            && !settings.is_apply_on_startup_enabled();
+        if observing || self.competing_tool.is_some() {
+            debug!("Not probing TDP support");
+            return;
+        }
+        let Some(ryzen_adj) = &mut self.ryzen_adj else {
+            return;
+        };
+        let limits = match ryzen_adj.get_table() {
+            Ok(t) => (t.get_fast_limit(), t.get_slow_limit(), t.get_stapm_limit()),
+            // Reading errors are reported as the TDP icon
+            Err(_) => return,
+        };
+        match ryzen_adj.set_limits(limits.0, limits.1, limits.2) {
+            Err(RyzenAdjError::FamilyNotSupported) => {
+                warn!(
+                    "Changing TDP limits is not supported on this chip: {}",
+                    ryzen_adj.describe()
+                );
+                self.tdp_supported = false;
+            }
+            Err(err) => {
+                debug!("Failed to write back the current TDP limits: {}", err);
+                // Reading works unprivileged on some systems, while writing never does
+                if let Some(hint) = Self::get_elevation_hint(&err) {
+                    // A balloon rather than a message box, the window is still being created
+                    let message = format!("Failed to change the TDP limits: {}\n{}", err, hint);
+                    self.model.notification.get_or_insert(message);
+                }
+            }
+            Ok(()) => {}
+        }
+    }
+
+    fn get_ryzen_adj_error_message(err: &RyzenAdjError) -> String {
+        let message = format!("Failed to initialize RyzenAdj: {}", err);
+        match Self::get_elevation_hint(err) {
+            Some(hint) => format!("{}\n\n{}", message, hint),
+            None => message,
+        }
+    }
+
+    /// Returns guidance for the user if the error is caused by the lack of elevation.
+    fn get_elevation_hint(err: &RyzenAdjError) -> Option<&'static str> {
+        if !err.is_access_error() {
+            return None;
+        }
+        match is_elevated() {
+            Ok(false) => {
+                info!("RyzenAdj cannot access the hardware without elevation");
+                Some(
+                    "TDP control on this system requires administrator privileges. \
+                    Run LilPowerMan as administrator to enable it.",
+                )
+            }
+            Ok(true) => None,
+            Err(err) => {
+                warn!("Failed to check for elevation: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Remembers the limits the system has before the application changes them.
+    fn record_stock_tdp(&mut self) {
+        let Some(ryzen_adj) = &self.ryzen_adj else {
+            return;
+        };
+        // Even if it does not match the current limit, a limit from the previous session
+        // may be in effect since a crash or may have been changed again by another tool
+        if self.settings_storage.load_applied_tdp().is_some() {
+            trace!("Limit from the previous session may be in effect, stock limits are unknown");
+            return;
+        }
+        if let Some(tool) = &self.competing_tool {
+            trace!(
+                "{:?} may have changed the limits, stock limits are unknown",
+                tool
+            );
+            return;
+        }
+        let Ok(table) = ryzen_adj.get_table() else {
+            // Reported by the reconciliation that follows
+            return;
+        };
+        let stock = StockTdp {
+            fast: table.get_fast_limit(),
+            slow: table.get_slow_limit(),
+            stapm: table.get_stapm_limit(),
+        };
+        if self.settings_storage.load_stock_tdp() == Some(stock) {
+            return;
+        }
+        info!(
+            "Stock TDP limits: {} mW fast, {} mW slow, {} mW STAPM",
+            stock.fast, stock.slow, stock.stapm
+        );
+        if let Err(err) = self.settings_storage.save_stock_tdp(stock) {
+            warn!("Failed to record the stock TDP limits: {}", err);
+        }
+    }
+
+    /// Brings back the limits recorded by `record_stock_tdp`.
+    fn reset_to_stock(&mut self) {
+        let Some(stock) = self.settings_storage.load_stock_tdp() else {
+            warn!("Stock TDP limits are unknown, keeping the current limit");
+            return;
+        };
+        if let Some(tool) = &self.competing_tool {
+            warn!(
+                "Not resetting TDP limits to stock while {:?} is running",
+                tool
+            );
+            return;
+        }
+        let Some(ryzen_adj) = &mut self.ryzen_adj else {
+            return;
+        };
+        match ryzen_adj.set_limits(stock.fast, stock.slow, stock.stapm) {
+            Ok(()) => {
+                info!("Reset TDP limits to stock");
+                self.applied_target = None;
+                Self::save_applied_tdp(&mut self.settings_storage, None);
+            }
+            Err(err) => {
+                error!("Failed to reset TDP limits to stock: {}", err);
+                self.model.notification = Some(format!("Failed to reset TDP limits: {}", err));
+            }
+        }
+        self.model.tdp = self.refresh_tdp();
+    }
+
+    /// Brings back the intended limit if the previous session ended without restoring
+    /// the one it had applied.
+    fn reconcile_applied_tdp(&mut self) {
+        let Some(applied) = self.settings_storage.load_applied_tdp() else {
+            return;
+        };
+        if self.competing_tool.is_some() {
+            // The other tool owns the limits now, whatever they are
+            return;
+        }
+        let Some(ryzen_adj) = &mut self.ryzen_adj else {
+            return;
+        };
+        let current = match ryzen_adj.get_table() {
+            Ok(table) => table.get_fast_limit(),
+            Err(err) => {
+                warn!("Failed to check the last applied TDP limit: {}", err);
+                return;
+            }
+        };
+        if current != applied.limit {
+            info!(
+                "TDP limit {} mW from the previous session is no longer in effect",
+                applied.limit
+            );
+            Self::save_applied_tdp(&mut self.settings_storage, None);
+            return;
+        }
+        match (self.model.settings.get_tdp_setting(), applied.restore) {
+            (TdpSetting::Forcing(target), _) => {
+                // The first poll applies the setting
+                info!(
+                    "Stale TDP limit {} mW from the previous session, TDP setting is {} mW",
+                    applied.limit, target
+                );
+            }
+            (TdpSetting::Tracking, Some(restore)) => match ryzen_adj.set_all_limits(restore) {
+                Ok(()) => {
+                    info!(
+                        "Restored TDP limit {} mW over stale {} mW from the previous session",
+                        restore, applied.limit
+                    );
+                    Self::save_applied_tdp(&mut self.settings_storage, None);
+                }
+                Err(err) => warn!("Failed to restore TDP limit {} mW: {}", restore, err),
+            },
+            (TdpSetting::Tracking, None) => {
+                info!(
+                    "TDP limit {} mW from the previous session has nothing to restore to",
+                    applied.limit
+                );
+                Self::save_applied_tdp(&mut self.settings_storage, None);
+            }
+        }
+    }
+
+    fn save_applied_tdp(storage: &mut SettingsStorage, applied: Option<AppliedTdp>) {
+        if let Err(err) = storage.save_applied_tdp(applied) {
+            warn!("Failed to record the applied TDP limit: {}", err);
+        }
+    }
+
+    fn apply_on_startup(&mut self) {
+        if !self.model.settings.is_apply_on_startup_enabled() {
+            return;
+        }
+        let TdpSetting::Forcing(target) = self.model.settings.get_tdp_setting() else {
+            return;
+        };
+        if self.competing_tool.is_some() {
+            return;
+        }
+        let Some(ryzen_adj) = &mut self.ryzen_adj else {
+            debug!("RyzenAdj is not available, deferring startup TDP to the first poll");
+            return;
+        };
+        match ryzen_adj.set_all_limits(target) {
+            Ok(()) => {
+                info!("Applied TDP setting at startup: {} mW", target);
+                self.applied_target = Some(target);
+                Self::save_applied_tdp(
+                    &mut self.settings_storage,
+                    Some(AppliedTdp {
+                        limit: target,
+                        restore: None,
+                    }),
+                );
+            }
+            Err(err) => warn!("Failed to apply TDP setting at startup: {}", err),
+        }
+    }
+
+    fn validate_settings(&mut self) {
+        // Not narrowed by the stock limits, those may be lower than what the chip sustains
+        let range = MIN_TDP_OPTION..=MAX_TDP_OPTION;
+        let repairs = self.model.settings.find_repairs(&range, |app| {
+            // Packaged applications are not looked up, their AUMID is not a path
+            get_package_name(app).is_some() || Path::new(app).exists()
+        });
+        if repairs.is_empty() {
+            trace!("Settings are valid");
+            return;
+        }
+        for repair in &repairs {
+            info!("Repairing settings: {:?}", repair);
+        }
+        info!("Repaired {} invalid setting(s)", repairs.len());
+        for repair in repairs.iter().cloned() {
+            if let Err(err) = self
+                .settings_storage
+                .apply_repair(&mut self.model.settings, repair)
+            {
+                error!("Failed to repair settings: {}", err);
+            }
         }
+        self.model.notification = Some(format!(
+            "{} invalid setting(s) have been repaired",
+            repairs.len()
+        ));
     }
 
-    fn get_tdp_limit(&self) -> Option<Result<u32, String>> {
+    fn get_tdp_limit(&self) -> Option<Result<TdpSnapshot, String>> {
         self.ryzen_adj.as_ref().map(|r| {
             r.get_table()
-                .map(|t| t.get_fast_limit())
+                .map(|t| t.snapshot())
                 .map_err(|e| e.to_string())
         })
     }
 
-    fn get_battery_status(&mut self) -> Option<Result<BatteryStatus, String>> {
+    /// Returns the first battery, `None` if there is none.
+    fn find_battery() -> Option<Result<Battery, BatteryError>> {
+        match BatteriesIterator::new() {
+            Ok(mut batteries) => batteries.next(),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    fn get_battery_status(&mut self) -> Option<Result<BatteryStatus, BatteryError>> {
         let mut result = self.battery.as_ref().map(Battery::get_status);
         if let Some(Err(BatteryError::WindowsError(err))) = &result {
-            if err == &Error::from(ERROR_NO_SUCH_DEVICE) {
-                match BatteriesIterator::new().next() {
+            let cooling_down = self
+                .battery_search_after
+                .is_some_and(|after| Instant::now() < after);
+            if err == &Error::from(ERROR_NO_SUCH_DEVICE) && !cooling_down {
+                self.battery_search_after = None;
+                match Self::find_battery() {
                     None => {
                         show_error_message_box("Battery disconnected");
                         result = None;
@@ -99,17 +568,31 @@ impl Controller {
                         self.battery = Some(new_battery);
                     }
                     Some(Err(e)) => {
+                        warn!("Failed to find the battery again: {}", e);
+                        self.battery_search_after = Some(Instant::now() + BATTERY_SEARCH_COOLDOWN);
                         result = Some(Err(e));
                     }
                 }
             }
         }
-        result.map(|r| r.map_err(|e| e.to_string()))
+        result
     }
 
+    /// Packaged applications are identified by their AUMID instead, since the executable
+    /// might be shared by several of them.
     fn get_application_path(pid: u32) -> Result<OsString, Error> {
+        let (path, app_id) = Self::get_application_ids(pid)?;
+        Ok(app_id.unwrap_or(path))
+    }
+
+    /// Returns the executable path and, for packaged applications, the AUMID.
+    fn get_application_ids(pid: u32) -> Result<(OsString, Option<OsString>), Error> {
         // SAFETY: The call is always sound, we own the returned handle
         let p = unsafe { Owned::new(OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)?) };
+        let app_id = get_package_app_id(*p).unwrap_or_else(|err| {
+            warn!("Failed to get the AUMID of process {}: {}", pid, err);
+            None
+        });
         let mut path = [0u16; MAX_PATH as usize];
         let mut len = MAX_PATH - 1;
         // SAFETY: The provided pointer is pointing to an allocated area of the specified size
@@ -121,132 +604,1048 @@ impl Controller {
                 &mut len,
             )?
         };
-        Ok(OsString::from_wide(&path[..len as usize]).to_ascii_lowercase())
+        let path = OsString::from_wide(&path[..len as usize]).to_ascii_lowercase();
+        Ok((path, app_id))
     }
 
     fn get_self_path() -> Result<OsString, Error> {
         Self::get_application_path(get_self_pid())
     }
 
-    fn get_fg_application() -> Result<OsString, Error> {
-        get_fg_application_pid().and_then(Self::get_application_path)
+    fn is_self(&self, app: &OsString) -> bool {
+        self.self_path.as_ref() == Some(app)
+    }
+
+    fn get_fg_application() -> Result<(OsString, Option<OsString>), Error> {
+        get_fg_application_pid().and_then(Self::get_application_ids)
+    }
+
+    /// Packaged applications used to be identified by their executable, so their limits
+    /// are moved over to the AUMID the first time they are seen.
+    fn migrate_app_limit(&mut self, path: &OsStr, app_id: &OsStr) {
+        let settings = &mut self.model.settings;
+        let Some(limit) = settings.get_app_limit(path) else {
+            return;
+        };
+        if settings.get_app_limit(app_id).is_some() {
+            return;
+        }
+        info!("Moving the limit of {:?} to {:?}", path, app_id);
+        let fullscreen_only = settings.is_app_fullscreen_only(path);
+        let storage = &mut self.settings_storage;
+        let result = storage
+            .set_app_limit(settings, app_id.to_os_string(), limit)
+            .and_then(|()| storage.set_app_fullscreen_only(settings, app_id, fullscreen_only))
+            .and_then(|()| storage.remove_app_limit(settings, path));
+        if let Err(err) = result {
+            error!("Failed to move the application limit: {}", err);
+        }
+    }
+
+    /// Returns the foreground application, or the previous one if the current one is a protected
+    /// process that we are not allowed to identify, or if the desktop has briefly replaced
+    /// a full-screen application.
+    fn identify_fg_application(&mut self) -> Option<OsString> {
+        // Full-screen state is also needed for full-screen-only application limits
+        let window_kind = get_fg_window_kind();
+        if window_kind == FgWindowKind::Desktop
+            && self.fg_app_fullscreen
+            && self.model.settings.is_fullscreen_detection_enabled()
+        {
+            trace!("Desktop replaced a full-screen application, assuming it has not changed");
+            return self.last_fg_app.clone();
+        }
+        match Self::get_fg_application() {
+            Ok((path, app_id)) => {
+                let app = match app_id {
+                    Some(app_id) => {
+                        self.migrate_app_limit(&path, &app_id);
+                        app_id
+                    }
+                    None => path,
+                };
+                self.fg_app_protected = false;
+                self.fg_app_fullscreen = window_kind == FgWindowKind::Fullscreen;
+                self.last_fg_app = Some(app.clone());
+                Some(app)
+            }
+            Err(err) if err == Error::from(ERROR_ACCESS_DENIED) => {
+                if !self.fg_app_protected {
+                    debug!("Foreground application is protected, assuming it has not changed");
+                    self.fg_app_protected = true;
+                }
+                self.last_fg_app.clone()
+            }
+            Err(_) => {
+                self.fg_app_protected = false;
+                self.fg_app_fullscreen = false;
+                self.last_fg_app = None;
+                None
+            }
+        }
     }
 
+    fn get_high_contrast_colors() -> Option<HighContrastColors> {
+        is_high_contrast().then(|| HighContrastColors {
+            text: Color::from_colorref(get_sys_color(COLOR_WINDOWTEXT)),
+            background: Color::from_colorref(get_sys_color(COLOR_WINDOW)),
+        })
+    }
+
+    /// RyzenAdj does not report the highest limit of the chip. The stock fast limit set
+    /// by the firmware is often well below it, so it only extends the default options.
     fn get_tdp_options(&self) -> Vec<u32> {
-        // TODO: Determine based on chip's max TDP
-        vec![5000, 7500, 10000, 15000, 20000, 24000, 28000]
+        let ceiling = tdp_ceiling(self.settings_storage.load_stock_tdp().map(|s| s.fast));
+        if self.model.settings.is_fine_tdp_options_enabled() {
+            fine_tdp_options(ceiling)
+        } else {
+            tdp_options(ceiling)
+        }
+    }
+
+    fn get_boost_remaining(&mut self) -> Option<Duration> {
+        let remaining = self
+            .boost_until?
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero());
+        if remaining.is_none() {
+            trace!("Boost expired");
+            self.boost_until = None;
+        }
+        remaining
+    }
+
+    fn get_battery_saver_limit(&self) -> Option<u32> {
+        let limit = self.model.settings.get_battery_saver_limit()?;
+        match is_battery_saver_on() {
+            Ok(true) => Some(limit),
+            Ok(false) => None,
+            Err(err) => {
+                error!("Failed to get battery saver state: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Decides whether the target has to be applied over the current limit.
+    fn should_apply(&self, target: u32, current: u32, throttle: Option<ThrottleStatus>) -> bool {
+        if target == current {
+            return false;
+        }
+        if self.applied_target != Some(target) {
+            return true;
+        }
+        // The target was in effect before, so something else has changed the limit
+        if current.abs_diff(target) <= EXTERNAL_CHANGE_TOLERANCE {
+            return false;
+        }
+        if throttle.is_some_and(|t| t.is_throttling()) {
+            debug!(
+                "Limit is {} mW instead of {} mW while throttling, not re-applying",
+                current, target
+            );
+            return false;
+        }
+        warn!(
+            "External change detected ({} mW instead of {} mW), re-applying",
+            current, target
+        );
+        true
+    }
+
+    fn get_applied_tdp(state: TdpState, target: u32) -> Option<AppliedTdp> {
+        let restore = match state {
+            // Going back to the system limit, nothing is left to restore
+            TdpState::Tracking => return None,
+            TdpState::Forcing => None,
+            TdpState::ForcingApplication { fallback }
+            | TdpState::Boosting { fallback, .. }
+            | TdpState::BatterySaver { fallback } => fallback,
+        };
+        Some(AppliedTdp {
+            limit: target,
+            restore,
+        })
     }
 
     fn refresh_tdp(&mut self) -> Option<TdpModel> {
-        let Some(mut value) = self.get_tdp_limit() else {
+        let Some(table) = self.get_tdp_limit() else {
             trace!("Bypassing TDP refresh");
             return None;
         };
+        let snapshot = table.as_ref().ok().copied();
+        let throttle = snapshot.and_then(|s| s.throttle);
+        let temperature = snapshot.map(|s| s.temperature).filter(|t| !t.is_nan());
+        // Some firmwares report garbage instead of the power draw
+        let current_power = snapshot.map(|s| s.current_power).filter(|&p| p > 0.0);
+        let temperature_limit = snapshot
+            .map(|s| s.temperature_limit)
+            .filter(|t| !t.is_nan());
+        let mut value = table.map(|s| TdpLimits {
+            fast: s.fast_limit,
+            slow: s.slow_limit,
+            stapm: s.stapm_limit,
+        });
         trace!("Refreshing TDP model");
         let (options, mut applications, old_state) = take(&mut self.model.tdp)
             .map(|m| (m.options, m.applications, m.state))
             .unwrap_or_else(|| (self.get_tdp_options(), VecDeque::new(), TdpState::Tracking));
-        let target;
-        let state;
-        let fg_app = Self::get_fg_application().ok();
+        let fg_app = self.identify_fg_application();
+        let settings = &self.model.settings;
+        // A limit of our own would kick in every time the menu is open
         let app_limit = fg_app
             .as_ref()
-            .and_then(|s| self.model.settings.get_app_limit(s));
-        if let Some(app_limit) = app_limit {
-            target = Some(app_limit);
-            state = match old_state {
-                TdpState::ForcingApplication { .. } => old_state,
-                TdpState::Forcing => TdpState::ForcingApplication { fallback: None },
-                TdpState::Tracking => TdpState::ForcingApplication {
-                    fallback: match value {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    },
-                },
-            };
+            .filter(|&app| !self.is_self(app))
+            .and_then(|app| {
+                let limit = settings.get_app_limit(app)?;
+                // Windowed full-screen-only applications fall through to the global setting
+                (self.fg_app_fullscreen || !settings.is_app_fullscreen_only(app)).then_some(limit)
+            });
+        let boost = if self.push_boost_since.is_some() {
+            Some(None)
         } else {
-            // should stop forcing app
-            match self.model.settings.get_tdp_setting() {
-                TdpSetting::Forcing(x) => {
-                    target = Some(x);
-                    state = TdpState::Forcing;
-                }
-                TdpSetting::Tracking => {
-                    if let TdpState::ForcingApplication { fallback } = old_state {
-                        target = fallback;
-                    } else {
-                        target = None;
-                    }
-                    state = TdpState::Tracking;
-                }
-            }
-        }
+            self.get_boost_remaining().map(|d| Some(d.as_secs()))
+        };
+        let sources = TdpSources {
+            boost,
+            max_option: options.iter().max().copied(),
+            battery_saver: self.get_battery_saver_limit(),
+            application: app_limit,
+            setting: self.model.settings.get_tdp_setting(),
+        };
+        let current = value.as_ref().ok().map(|l| l.fast);
+        let (target, state) = select_tdp_target(&sources, old_state, current);
         if let Some(fg_app) = fg_app {
-            if Some(&fg_app) != self.self_path.as_ref() && !applications.contains(&fg_app) {
+            if !self.is_self(&fg_app) && !applications.contains(&fg_app) {
                 applications.push_front(fg_app);
                 while applications.len() > MAX_RECENT_APPLICATIONS {
                     applications.pop_back();
                 }
             }
         }
+        let forced_target = if state == TdpState::Tracking {
+            None
+        } else {
+            target
+        };
+        if forced_target != self.forced_target {
+            trace!(
+                "Effective TDP target changed after {} s",
+                self.forced_since.elapsed().as_secs()
+            );
+            self.forced_target = forced_target;
+            self.forced_since = Instant::now();
+        }
+        let forced_secs = forced_target.map(|_| self.forced_since.elapsed().as_secs());
+        if target.is_none() {
+            self.applied_target = None;
+        }
         if let Some(target) = target {
-            if let Some(ryzen_adj) = &mut self.ryzen_adj {
-                if let Ok(current) = &value {
-                    if target != *current {
-                        value = match ryzen_adj.set_all_limits(target) {
-                            Ok(()) => Ok(target),
-                            Err(err) => Err(err.to_string()),
-                        }
+            let should_apply = match &value {
+                Ok(current) => self.should_apply(target, current.fast, throttle),
+                Err(_) => false,
+            };
+            let allowed = self.tdp_supported && self.competing_tool.is_none();
+            if let (true, true, Some(ryzen_adj)) = (should_apply, allowed, &mut self.ryzen_adj) {
+                let result = ryzen_adj.set_all_limits(target);
+                self.apply_stats.record(&result);
+                value = match result {
+                    Ok(()) => {
+                        self.tdp_errors.clear();
+                        self.apply_failures = 0;
+                        self.applied_target = Some(target);
+                        let applied = Self::get_applied_tdp(state, target);
+                        Self::save_applied_tdp(&mut self.settings_storage, applied);
+                        Ok(TdpLimits::all(target))
+                    }
+                    Err(err) => {
+                        self.applied_target = None;
+                        self.tdp_errors
+                            .error(format!("Failed to apply TDP limit: {}", err));
+                        self.on_apply_failed(&err);
+                        Err(err.to_string())
                     }
                 }
             }
         }
+        let history = self.sample_tdp_history(&value);
         Some(TdpModel {
             value,
+            history,
+            throttle,
+            temperature,
+            temperature_limit,
+            current_power,
+            forced_secs,
+            target: forced_target,
+            unsupported: !self.tdp_supported,
+            paused_by: self
+                .competing_tool
+                .as_ref()
+                .map(|t| t.to_string_lossy().into_owned()),
             options,
             applications,
             state,
         })
     }
 
-    fn update_rtss(&mut self, battery_status: &BatteryStatus) {
-        match self.rtss.update(battery_status) {
-            Ok(()) => {}
+    /// Records the measured limit every `TDP_HISTORY_PERIOD` and returns the history
+    /// for the tooltip, empty if it is disabled.
+    fn sample_tdp_history(&mut self, value: &Result<TdpLimits, String>) -> Vec<u32> {
+        let settings = &self.model.settings;
+        if !settings.is_tdp_history_enabled() {
+            self.tdp_history.clear();
+            return vec![];
+        }
+        let due = self
+            .tdp_history_sampled
+            .is_none_or(|t| t.elapsed() >= TDP_HISTORY_PERIOD);
+        if let (true, Ok(limits)) = (due, value) {
+            self.tdp_history_sampled = Some(Instant::now());
+            self.tdp_history
+                .push_back(limits.get(settings.get_tray_limit()));
+            if self.tdp_history.len() > TDP_HISTORY_LEN {
+                self.tdp_history.pop_front();
+            }
+        }
+        self.tdp_history.iter().copied().collect()
+    }
+
+    #[cfg(feature = "rtss")]
+    fn get_overlay_timer_period(model: &Model) -> Option<u32> {
+        let rate = model.settings.get_overlay_settings().update_rate;
+        if rate > 1 {
+            Some(1000 / rate)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(feature = "rtss"))]
+    fn get_overlay_timer_period(_model: &Model) -> Option<u32> {
+        None
+    }
+
+    #[cfg(feature = "rtss")]
+    fn push_rtss_battery(&mut self, battery_status: &BatteryStatus) {
+        let peak = self.rtss.get_battery_graph_peak();
+        self.rtss.push_battery(battery_status);
+        self.last_battery = Some(*battery_status);
+        let new_peak = self.rtss.get_battery_graph_peak();
+        if new_peak > peak {
+            debug!("Battery graph peak grew to {} W", new_peak);
+            if let Err(err) = self.settings_storage.save_battery_graph_peak(new_peak) {
+                warn!("Failed to save the battery graph peak: {}", err);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "rtss"))]
+    fn push_rtss_battery(&mut self, _battery_status: &BatteryStatus) {}
+
+    #[cfg(feature = "rtss")]
+    fn update_rtss(&mut self) {
+        let battery = match (&self.battery, self.last_battery) {
+            // Desktops have no battery, the rest of the overlay still applies
+            (None, _) => None,
+            (Some(_), Some(battery)) => self.is_charge_icon_shown().then_some(battery),
+            // Wait for the first reading, so that the overlay does not jump around
+            (Some(_), None) => return,
+        };
+        let fps = Rtss::read_fps();
+        self.fps = fps.as_ref().ok().copied().flatten();
+        let rtss_running = match fps {
+            Ok(_) => {
+                self.fps_errors.clear();
+                true
+            }
+            Err(RtssError::RtssV2NotRunning) => false,
+            Err(err) => {
+                self.fps_errors
+                    .error(format!("Failed to read framerate from RTSS: {}", err));
+                true
+            }
+        };
+        let metrics = OverlayMetrics {
+            battery,
+            tdp_limit: self
+                .model
+                .tdp
+                .as_ref()
+                .and_then(|t| t.value.as_ref().ok())
+                .map(|l| l.get(self.model.settings.get_tray_limit())),
+            fps: self.fps,
+            time: get_local_time(),
+            profile: self.get_active_profile(),
+        };
+        let settings = self.model.settings.get_overlay_settings();
+        if self.model.overlay_preview.is_some() {
+            // The preview works without RTSS, so templates can be written beforehand
+            self.model.overlay_preview = Some(self.rtss.layout(&metrics, settings));
+        }
+        if !rtss_running {
+            return;
+        }
+        match self.rtss.update(&metrics, settings) {
+            Ok(()) => self.overlay_errors.clear(),
             Err(RtssError::RtssV2NotRunning) => {}
-            Err(err) => error!("Failed to update RTSS shared memory: {}", err),
+            Err(err) => self
+                .overlay_errors
+                .error(format!("Failed to update RTSS shared memory: {}", err)),
+        }
+    }
+
+    #[cfg(not(feature = "rtss"))]
+    fn update_rtss(&mut self) {}
+
+    #[cfg(feature = "rtss")]
+    fn get_active_profile(&self) -> Option<ActiveProfile> {
+        let tdp = self.model.tdp.as_ref()?;
+        let TdpState::ForcingApplication { .. } = tdp.state else {
+            return None;
+        };
+        let app = self.last_fg_app.as_ref()?;
+        let application = match get_package_name(app) {
+            Some(name) => String::from(name),
+            None => Path::new(app)
+                .file_stem()
+                .unwrap_or(app)
+                .to_string_lossy()
+                .into_owned(),
+        };
+        Some(ActiveProfile {
+            application,
+            tdp_limit: tdp.value.as_ref().ok()?.fast,
+        })
+    }
+
+    fn log_metrics(&mut self, battery_status: Option<&BatteryStatus>) {
+        if self.metrics_log.is_none() {
+            return;
+        }
+        let tdp = self.model.tdp.as_ref();
+        let row = MetricsRow {
+            tdp_measured: tdp.and_then(|t| t.value.as_ref().ok()).map(|l| l.fast),
+            tdp_target: self.forced_target,
+            state: tdp.map(|t| match t.state {
+                TdpState::Tracking => "tracking",
+                TdpState::Forcing => "forcing",
+                TdpState::ForcingApplication { .. } => "application",
+                TdpState::Boosting { .. } => "boosting",
+                TdpState::BatterySaver { .. } => "battery_saver",
+            }),
+            charge_rate: battery_status.map(|s| s.charge_rate),
+            percent: battery_status
+                .zip(self.full_charged_capacity)
+                .filter(|(_, full)| *full > 0)
+                .map(|(s, full)| (s.capacity as u64 * 100 / full as u64) as u32),
+            temp: tdp.and_then(|t| t.temperature),
+            fps: self.fps,
+        };
+        let Some(metrics_log) = &mut self.metrics_log else {
+            return;
+        };
+        if let Err(err) = metrics_log.append(&row) {
+            error!("Failed to write metrics log, disabling it: {}", err);
+            self.metrics_log = None;
+        }
+    }
+
+    /// Returns the period of the dedicated overlay timer in milliseconds, if one is needed.
+    pub fn overlay_timer_period(&self) -> Option<u32> {
+        self.overlay_timer_period
+    }
+
+    /// Moves overlay updates back to the main timer.
+    pub fn on_overlay_timer_failed(&mut self) {
+        self.overlay_timer_period = None;
+    }
+
+    fn get_battery_runtime(status: &BatteryStatus) -> BatteryRuntime {
+        BatteryRuntime {
+            os_secs: status.os_runtime_secs,
+            instant_secs: (status.charge_rate < 0).then(|| {
+                (status.capacity as u64 * 3600 / status.charge_rate.unsigned_abs() as u64) as u32
+            }),
+        }
+    }
+
+    fn refresh_power_source(&mut self) -> Option<PowerSourceModel> {
+        let source = match get_power_source() {
+            Ok(source) => source,
+            Err(err) => {
+                error!("Failed to get power source: {}", err);
+                None
+            }
+        };
+        // A transient unknown status does not reset the elapsed time
+        let source = source?;
+        match self.power_source {
+            Some((last, _)) if last == source => {}
+            Some(_) => {
+                debug!("Power source changed to {:?}", source);
+                self.power_source = Some((source, Some(Instant::now())));
+            }
+            None => {
+                debug!("Power source is {:?}", source);
+                self.power_source = Some((source, None));
+            }
+        }
+        self.power_source.map(|(source, since)| PowerSourceModel {
+            source,
+            secs: since.map(|s| s.elapsed().as_secs()),
+        })
+    }
+
+    /// Logs the statistics collected so far and starts over.
+    fn reset_apply_stats(&mut self) {
+        if self.apply_stats.total() > 0 {
+            debug!("{}", self.apply_stats);
+        }
+        self.apply_stats = ApplyStats::default();
+        self.apply_stats_logged = Instant::now();
+    }
+
+    fn log_apply_stats(&mut self) {
+        if self.apply_stats.total() > 0
+            && self.apply_stats_logged.elapsed() >= APPLY_STATS_LOG_PERIOD
+        {
+            debug!("{}", self.apply_stats);
+            self.apply_stats_logged = Instant::now();
         }
     }
 
     pub fn on_timer(&mut self) {
+        // Message-only windows do not receive broadcast WM_SETTINGCHANGE or WM_DPICHANGED,
+        // so the theme and the scaling are polled
+        self.model.high_contrast = Self::get_high_contrast_colors();
+        self.model.dpi = get_taskbar_dpi();
+        self.check_competing_tools();
         self.model.tdp = self.refresh_tdp();
         let battery_status = self.get_battery_status();
+        self.model.power_source = self.refresh_power_source();
+        self.model.battery_runtime = battery_status
+            .as_ref()
+            .and_then(|r| r.as_ref().ok())
+            .map(Self::get_battery_runtime);
+        let status = battery_status.as_ref().and_then(|r| r.as_ref().ok());
+        self.model.battery_percent = status.and_then(|s| s.percent);
+        self.model.battery_voltage = status.and_then(|s| s.voltage);
+        if let Some(discharge) = status.filter(|s| s.charge_rate < 0) {
+            let rate = discharge.charge_rate.unsigned_abs();
+            if self.model.peak_discharge.is_none_or(|peak| peak < rate) {
+                self.model.peak_discharge = Some(rate);
+            }
+        }
         if let Some(Ok(status)) = &battery_status {
-            self.update_rtss(&status);
+            self.push_rtss_battery(status);
+        }
+        if self.overlay_timer_period.is_none() {
+            self.update_rtss();
+        }
+        self.log_metrics(battery_status.as_ref().and_then(|r| r.as_ref().ok()));
+        self.model.charge_icon = battery_status
+            .filter(|_| self.is_charge_icon_shown())
+            .map(|r| match r {
+                Ok(status) => Ok(Some(status.charge_rate)),
+                Err(BatteryError::RateUnavailable) => Ok(None),
+                Err(err) => Err(err.to_string()),
+            });
+        self.log_apply_stats();
+        self.check_for_updates();
+    }
+
+    /// Alerts the user once the limit has failed to apply several times in a row,
+    /// so that a stuck limit is noticed without watching the tray.
+    fn on_apply_failed(&mut self, err: &RyzenAdjError) {
+        if let RyzenAdjError::FamilyNotSupported = err {
+            // The startup probe is skipped while observing
+            warn!("Changing TDP limits is not supported on this chip");
+            self.tdp_supported = false;
+        }
+        self.apply_failures += 1;
+        if self.apply_failures != APPLY_FAILURE_ALERT_COUNT {
+            return;
+        }
+        let message = format!("TDP limit cannot be applied: {}", err);
+        self.model.notification = Some(match Self::get_elevation_hint(err) {
+            Some(hint) => format!("{}\n{}", message, hint),
+            None => message,
+        });
+        if self.model.settings.is_apply_failure_sound_enabled() {
+            play_alert_sound();
+        }
+    }
+
+    /// Pauses limit changes while another tool that changes them is running,
+    /// so that the two do not keep overriding each other.
+    fn check_competing_tools(&mut self) {
+        let settings = &self.model.settings;
+        let enabled = settings.is_pause_for_competing_tools_enabled();
+        let found = if !enabled || self.ryzen_adj.is_none() {
+            None
+        } else if self
+            .competing_tools_checked
+            .is_some_and(|t| t.elapsed() < COMPETING_TOOLS_CHECK_PERIOD)
+        {
+            return;
+        } else {
+            self.competing_tools_checked = Some(Instant::now());
+            let names = match get_process_names() {
+                Ok(names) => names,
+                Err(err) => {
+                    warn!("Failed to list the running processes: {}", err);
+                    return;
+                }
+            };
+            let tools = settings.get_competing_tools();
+            names
+                .into_iter()
+                .find(|name| tools.contains(&name.to_ascii_lowercase()))
+        };
+        if found == self.competing_tool {
+            return;
         }
-        self.model.charge_icon = battery_status.map(|r| r.map(|s| s.charge_rate));
+        if let Some(tool) = &found {
+            warn!("{:?} is running, pausing TDP changes", tool);
+            self.model.notification = Some(format!(
+                "TDP changes are paused while {} is running",
+                tool.to_string_lossy()
+            ));
+        } else if !enabled {
+            // Turned off by the user, who knows the tool is still running
+            info!("Pausing for competing tools is disabled, resuming TDP changes");
+        } else if let Some(tool) = &self.competing_tool {
+            info!("{:?} is no longer running, resuming TDP changes", tool);
+            self.model.notification = Some(format!(
+                "{} has exited, TDP changes are resumed",
+                tool.to_string_lossy()
+            ));
+        }
+        self.competing_tool = found;
+        // Whatever was applied before might have been overridden by now
+        self.applied_target = None;
+    }
+
+    /// The setting is ignored without TDP control, so that there is always an icon to click.
+    fn is_charge_icon_shown(&self) -> bool {
+        self.model.settings.is_charge_icon_enabled() || self.ryzen_adj.is_none()
+    }
+
+    fn check_for_updates(&mut self) {
+        let Some(days) = self.model.settings.get_update_check_days() else {
+            return;
+        };
+        let interval = Duration::from_secs(days as u64 * 24 * 60 * 60);
+        // A clock set back makes the last check look like it is in the future, so check again
+        let checked_recently = self
+            .last_update_check
+            .is_some_and(|t| t.elapsed().is_ok_and(|e| e < interval));
+        if checked_recently {
+            return;
+        }
+        let now = SystemTime::now();
+        self.last_update_check = Some(now);
+        if let Err(err) = self.settings_storage.save_last_update_check(now) {
+            warn!("Failed to save the time of the update check: {}", err);
+        }
+        let commands = self.commands.clone();
+        update::check_in_background(move |release| {
+            commands.send(Command::ShowUpdate(release));
+        });
+    }
+
+    fn on_update_found(&mut self, release: Release) {
+        let text = format!(
+            "Version {} is available, click here to open the download page",
+            release.version
+        );
+        self.model.notification = Some(text.clone());
+        self.update_balloon = Some((text, release.url));
+    }
+
+    pub fn on_notify_balloon_click(&mut self) {
+        let Some((text, url)) = &self.update_balloon else {
+            return;
+        };
+        // The click might be for a different notification
+        if self.model.notification.as_ref() != Some(text) {
+            return;
+        }
+        if let Err(err) = open_url(url) {
+            error!("Failed to open the download page: {}", err);
+        }
+        self.update_balloon = None;
+    }
+
+    pub fn on_overlay_timer(&mut self) {
+        self.update_rtss();
     }
 
     pub fn on_command(&mut self, command: Command) {
-        match command {
-            Command::Observe => self
-                .settings_storage
-                .set_tdp_setting(&mut self.model.settings, TdpSetting::Tracking),
+        let explicit_choice = matches!(
+            command,
+            Command::Observe
+                | Command::ResetApplicationTdp(_)
+                | Command::SetApplicationTdp(..)
+                | Command::StepApplicationTdp(_)
+                | Command::SetAllAppsTdp(_)
+                | Command::ToggleApplicationFullscreenOnly(_)
+                | Command::SetTdp(_)
+                | Command::BoostFor(_)
+        );
+        if explicit_choice {
+            // Any explicit TDP choice takes over the boost
+            if self.boost_until.take().is_some() {
+                trace!("Boost cancelled");
+            }
+            self.reset_apply_stats();
+        }
+        let result = match command {
+            Command::Observe => {
+                // Anything else that is forced keeps its limit until it ends
+                let was_forcing = self
+                    .model
+                    .tdp
+                    .as_ref()
+                    .is_some_and(|m| m.state == TdpState::Forcing);
+                let result = self
+                    .settings_storage
+                    .set_tdp_setting(&mut self.model.settings, TdpSetting::Tracking);
+                if result.is_ok()
+                    && was_forcing
+                    && self.model.settings.is_reset_on_observe_enabled()
+                {
+                    self.reset_to_stock();
+                }
+                result
+            }
             Command::ResetApplicationTdp(app) => self
                 .settings_storage
                 .remove_app_limit(&mut self.model.settings, &app),
-            Command::SetApplicationTdp(app, limit) => {
-                self.settings_storage
-                    .set_app_limit(&mut self.model.settings, app, limit)
+            Command::SetApplicationTdp(app, limit) => self.set_app_limit(app, limit),
+            Command::StepApplicationTdp(up) => self.step_app_limit(up),
+            Command::SetAllAppsTdp(limit) => self.set_all_app_limits(limit),
+            Command::ToggleApplicationFullscreenOnly(app) => {
+                let enabled = !self.model.settings.is_app_fullscreen_only(&app);
+                self.settings_storage.set_app_fullscreen_only(
+                    &mut self.model.settings,
+                    &app,
+                    enabled,
+                )
             }
             Command::SetTdp(target) => self
                 .settings_storage
                 .set_tdp_setting(&mut self.model.settings, TdpSetting::Forcing(target)),
-            Command::Exit =>
-            // SAFETY: It is sound to destroy the window we own
-            unsafe { DestroyWindow(self.window).unwrap() },
+            Command::BoostFor(duration) => {
+                self.boost_until = Some(Instant::now() + duration);
+                Ok(())
+            }
+            Command::PushToBoost(pressed) => {
+                self.on_push_to_boost(pressed);
+                Ok(())
+            }
+            Command::SetTrayLimit(kind) => self
+                .settings_storage
+                .set_tray_limit(&mut self.model.settings, kind),
+            Command::SetChargeColorScheme(scheme) => self
+                .settings_storage
+                .set_charge_color_scheme(&mut self.model.settings, scheme),
+            Command::ResetSessionPeaks => {
+                self.model.peak_discharge = None;
+                Ok(())
+            }
+            // The window shows the dialog, since it runs a modal loop
+            Command::OpenSettings | Command::ClearLogs | Command::CopyLogTail => Ok(()),
+            #[cfg(feature = "rtss")]
+            Command::PreviewOverlay => {
+                self.model.overlay_preview = Some(vec![]);
+                self.update_rtss();
+                Ok(())
+            }
+            #[cfg(feature = "rtss")]
+            Command::CloseOverlayPreview => {
+                self.model.overlay_preview = None;
+                Ok(())
+            }
+            #[cfg(feature = "rtss")]
+            Command::CopyOverlayLayout => {
+                self.copy_overlay_layout();
+                Ok(())
+            }
+            #[cfg(feature = "rtss")]
+            Command::PasteOverlayLayout => self.paste_overlay_layout(),
+            Command::ShowUpdate(release) => {
+                self.on_update_found(release);
+                Ok(())
+            }
+            Command::Exit => {
+                // SAFETY: It is sound to destroy the window we own
+                unsafe { DestroyWindow(self.window).unwrap() };
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            error!("Failed to save settings: {}", err);
+            self.model.notification = Some(format!("Failed to save settings: {}", err));
         }
     }
 
+    #[cfg(feature = "rtss")]
+    fn copy_overlay_layout(&mut self) {
+        let snippet = format_layout_snippet(self.model.settings.get_overlay_settings());
+        self.model.notification = Some(match set_clipboard_text(self.window, &snippet) {
+            Ok(()) => String::from("Overlay layout is copied to the clipboard"),
+            Err(err) => {
+                error!("Failed to copy the overlay layout: {}", err);
+                format!("Failed to copy the overlay layout: {}", err)
+            }
+        });
+    }
+
+    /// Only settings errors are returned, problems with the snippet are shown right away.
+    #[cfg(feature = "rtss")]
+    fn paste_overlay_layout(&mut self) -> Result<(), Error> {
+        let text = match get_clipboard_text(self.window) {
+            Ok(text) => text.unwrap_or_default(),
+            Err(err) => {
+                error!("Failed to read the clipboard: {}", err);
+                self.model.notification = Some(format!("Failed to read the clipboard: {}", err));
+                return Ok(());
+            }
+        };
+        let current = self.model.settings.get_overlay_settings();
+        let overlay = match parse_layout_snippet(&text, current) {
+            Ok(overlay) => overlay,
+            Err(issues) => {
+                warn!("Overlay layout is not applied: {}", issues.join("; "));
+                self.model.notification = Some(format!(
+                    "Overlay layout is not applied:\n{}",
+                    issues.join("\n")
+                ));
+                return Ok(());
+            }
+        };
+        self.settings_storage
+            .set_overlay_layout(&mut self.model.settings, overlay)?;
+        info!("Overlay layout is pasted from the clipboard");
+        self.model.notification = Some(String::from("Overlay layout is applied"));
+        self.update_rtss();
+        Ok(())
+    }
+
+    fn on_push_to_boost(&mut self, pressed: bool) {
+        let now = Instant::now();
+        if pressed {
+            trace!("Push-to-boost key pressed");
+            self.push_boost_since.get_or_insert(now);
+        } else if let Some(since) = self.push_boost_since.take() {
+            trace!("Push-to-boost key released");
+            // Quick taps keep the boost for a while, so the SMU is not flipped back and forth
+            let until = since + PUSH_BOOST_MIN_DURATION;
+            if until > now && self.boost_until.is_none_or(|u| u < until) {
+                self.boost_until = Some(until);
+            }
+        }
+        // The key is expected to take effect right away
+        self.model.tdp = self.refresh_tdp();
+    }
+
+    /// Creates the foreground application limit from the current one if needed.
+    fn step_app_limit(&mut self, up: bool) -> Result<(), Error> {
+        let Some(app) = self.identify_fg_application() else {
+            debug!("No foreground application to step the limit for");
+            return Ok(());
+        };
+        let current = self.model.settings.get_app_limit(&app).or_else(|| {
+            let tdp = self.model.tdp.as_ref()?;
+            tdp.value.as_ref().ok().map(|v| v.fast)
+        });
+        let Some(current) = current else {
+            warn!("Current TDP limit is unknown, cannot step the application limit");
+            return Ok(());
+        };
+        let options = self.get_tdp_options();
+        let limit = step_tdp_option(&options, current, up);
+        debug!("Stepping the limit for {:?} to {}", app, limit);
+        self.set_app_limit(app, limit)?;
+        // Hotkeys are expected to take effect right away
+        self.apply_limit_change();
+        self.update_rtss();
+        Ok(())
+    }
+
+    /// Applies a changed limit setting right away, unless another one has just been applied.
+    /// A burst of changes then only writes the last one, on the next poll.
+    fn apply_limit_change(&mut self) {
+        let now = Instant::now();
+        if self
+            .limit_change_applied
+            .is_some_and(|at| now.duration_since(at) < LIMIT_CHANGE_DEBOUNCE)
+        {
+            debug!("Deferring the limit change to the next poll");
+            return;
+        }
+        self.limit_change_applied = Some(now);
+        self.model.tdp = self.refresh_tdp();
+    }
+
+    /// Refuses to set a limit for LilPowerMan itself, telling the user why.
+    fn set_app_limit(&mut self, app: OsString, limit: u32) -> Result<(), Error> {
+        if self.is_self(&app) {
+            warn!("Refusing to set an application limit for LilPowerMan itself");
+            self.model.notification =
+                Some(String::from("LilPowerMan cannot have an application limit"));
+            return Ok(());
+        }
+        self.settings_storage
+            .set_app_limit(&mut self.model.settings, app, limit)
+    }
+
+    fn set_all_app_limits(&mut self, limit: u32) -> Result<(), Error> {
+        let mut apps: Vec<OsString> = self
+            .model
+            .settings
+            .get_limited_apps()
+            .map(OsString::from)
+            .filter(|app| !self.is_self(app))
+            .collect();
+        if let Some(tdp) = &self.model.tdp {
+            for app in &tdp.applications {
+                if !apps.contains(app) {
+                    apps.push(app.clone());
+                }
+            }
+        }
+        let count = apps.len();
+        for app in apps {
+            self.settings_storage
+                .set_app_limit(&mut self.model.settings, app, limit)?;
+        }
+        info!("Limit of {} applications is set to {} mW", count, limit);
+        self.model.notification = Some(match count {
+            1 => format!(
+                "Set the limit of 1 application to {} W",
+                limit as f32 / 1000.0
+            ),
+            _ => format!(
+                "Set the limit of {} applications to {} W",
+                count,
+                limit as f32 / 1000.0
+            ),
+        });
+        Ok(())
+    }
+
+    pub fn get_settings_dialog_values(&self) -> SettingsDialogValues {
+        let settings = &self.model.settings;
+        let options = self.get_tdp_options();
+        let max_option = options.iter().max().copied().unwrap_or(MIN_TDP_OPTION);
+        SettingsDialogValues {
+            remove_missing_apps: settings.is_remove_missing_apps_enabled(),
+            fullscreen_detection: settings.is_fullscreen_detection_enabled(),
+            apply_on_startup: settings.is_apply_on_startup_enabled(),
+            pause_when_display_off: settings.is_pause_when_display_off_enabled(),
+            reset_on_observe: settings.is_reset_on_observe_enabled(),
+            show_charge_icon: settings.is_charge_icon_enabled(),
+            tray_shows_target: settings.is_tray_target_enabled(),
+            tray_gauge: settings.is_tray_gauge_enabled(),
+            tray_shows_power: settings.is_tray_power_enabled(),
+            icon_outline: settings.is_icon_outline_enabled(),
+            pause_for_competing_tools: settings.is_pause_for_competing_tools_enabled(),
+            apply_failure_sound: settings.is_apply_failure_sound_enabled(),
+            tdp_history: settings.is_tdp_history_enabled(),
+            fine_tdp_options: settings.is_fine_tdp_options_enabled(),
+            temperature_fahrenheit: settings.get_temperature_unit() == TemperatureUnit::Fahrenheit,
+            battery_saver_limit: settings.get_battery_saver_limit(),
+            tdp_range: MIN_TDP_OPTION..=max_option,
+            charge_idle_band: settings.get_charge_idle_band(),
+            update_check_days: settings.get_update_check_days(),
+            #[cfg(feature = "rtss")]
+            overlay_update_rate: settings.get_overlay_settings().update_rate,
+            #[cfg(feature = "rtss")]
+            battery_graph_watts: settings.get_overlay_settings().battery_graph_watts,
+            #[cfg(feature = "rtss")]
+            overlay_clock: settings.get_overlay_settings().show_clock,
+        }
+    }
+
+    pub fn on_logs_cleared(&mut self, result: Result<usize, IoError>) {
+        self.model.notification = Some(match result {
+            Ok(1) => String::from("Deleted 1 log file"),
+            Ok(deleted) => format!("Deleted {} log files", deleted),
+            Err(err) => {
+                error!("Failed to clear logs: {}", err);
+                format!("Failed to clear logs: {}", err)
+            }
+        });
+    }
+
+    /// `Ok(false)` means there is no log file yet.
+    pub fn on_log_tail_copied(&mut self, result: Result<bool, IoError>) {
+        self.model.notification = Some(match result {
+            Ok(true) => String::from("Recent log lines are copied to the clipboard"),
+            Ok(false) => String::from("Log file is not created yet"),
+            Err(err) => {
+                error!("Failed to copy the log: {}", err);
+                format!("Failed to copy the log: {}", err)
+            }
+        });
+    }
+
+    pub fn on_settings_dialog_accepted(&mut self, values: SettingsDialogValues) {
+        if let Err(err) = self.save_settings_dialog_values(values) {
+            error!("Failed to save settings: {}", err);
+            self.model.notification = Some(format!("Failed to save settings: {}", err));
+        }
+        self.overlay_timer_period = Self::get_overlay_timer_period(&self.model);
+        let options = self.get_tdp_options();
+        if let Some(tdp) = &mut self.model.tdp {
+            tdp.options = options;
+        }
+        // Battery Saver limit takes effect right away
+        self.model.tdp = self.refresh_tdp();
+        if !self.is_charge_icon_shown() {
+            self.model.charge_icon = None;
+        }
+    }
+
+    fn save_settings_dialog_values(&mut self, values: SettingsDialogValues) -> Result<(), Error> {
+        let settings = &mut self.model.settings;
+        let storage = &mut self.settings_storage;
+        storage.set_remove_missing_apps(settings, values.remove_missing_apps)?;
+        storage.set_fullscreen_detection(settings, values.fullscreen_detection)?;
+        storage.set_apply_on_startup(settings, values.apply_on_startup)?;
+        storage.set_pause_when_display_off(settings, values.pause_when_display_off)?;
+        storage.set_reset_on_observe(settings, values.reset_on_observe)?;
+        storage.set_charge_icon_enabled(settings, values.show_charge_icon)?;
+        storage.set_tray_target_enabled(settings, values.tray_shows_target)?;
+        storage.set_tray_gauge_enabled(settings, values.tray_gauge)?;
+        storage.set_tray_power_enabled(settings, values.tray_shows_power)?;
+        storage.set_icon_outline_enabled(settings, values.icon_outline)?;
+        storage
+            .set_pause_for_competing_tools_enabled(settings, values.pause_for_competing_tools)?;
+        storage.set_apply_failure_sound_enabled(settings, values.apply_failure_sound)?;
+        storage.set_tdp_history_enabled(settings, values.tdp_history)?;
+        storage.set_fine_tdp_options_enabled(settings, values.fine_tdp_options)?;
+        let unit = if values.temperature_fahrenheit {
+            TemperatureUnit::Fahrenheit
+        } else {
+            TemperatureUnit::Celsius
+        };
+        storage.set_temperature_unit(settings, unit)?;
+        storage.set_battery_saver_limit(settings, values.battery_saver_limit)?;
+        storage.set_charge_idle_band(settings, values.charge_idle_band)?;
+        storage.set_update_check_days(settings, values.update_check_days)?;
+        #[cfg(feature = "rtss")]
+        storage.set_overlay_update_rate(settings, values.overlay_update_rate)?;
+        #[cfg(feature = "rtss")]
+        storage.set_overlay_battery_graph_watts(settings, values.battery_graph_watts)?;
+        #[cfg(feature = "rtss")]
+        storage.set_overlay_show_clock(settings, values.overlay_clock)?;
+        Ok(())
+    }
+
     pub fn on_notify_icon_click(&mut self, id: u32, x: i32, y: i32) {
         if id == id::NotifyIcon::TdpLimit as _ {
             self.model.popup_menu = Some(PopupMenuModel {
@@ -255,6 +1654,8 @@ impl Controller {
                 menu: PopupMenuType::TdpIcon,
             })
         } else if id == id::NotifyIcon::ChargeRate as _ {
+            // Details are read on demand to keep polling cheap
+            self.model.battery_details = self.read_battery_details();
             self.model.popup_menu = Some(PopupMenuModel {
                 x,
                 y,
@@ -271,3 +1672,228 @@ impl Controller {
         &self.model
     }
 }
+
+/// Returns the highest menu option: the stock fast limit, but no lower than the default options.
+fn tdp_ceiling(stock: Option<u32>) -> u32 {
+    let default = DEFAULT_TDP_OPTIONS[DEFAULT_TDP_OPTIONS.len() - 1];
+    stock.unwrap_or(default).clamp(default, MAX_TDP_OPTION)
+}
+
+/// Builds the menu options from the default ones, followed by 5 W steps up to `ceiling`,
+/// with the ceiling itself as the highest one.
+fn tdp_options(ceiling: u32) -> Vec<u32> {
+    let mut options = DEFAULT_TDP_OPTIONS.to_vec();
+    let default = DEFAULT_TDP_OPTIONS[DEFAULT_TDP_OPTIONS.len() - 1];
+    if ceiling <= default {
+        return options;
+    }
+    let mut option = (default / 5000 + 1) * 5000;
+    // Options just below the ceiling would hardly differ from it
+    while option + 2500 <= ceiling {
+        options.push(option);
+        option += 5000;
+    }
+    options.push(ceiling);
+    options
+}
+
+/// Builds the menu options from 5 W up to `ceiling` in 1 W steps, for experimenting.
+fn fine_tdp_options(ceiling: u32) -> Vec<u32> {
+    let mut options: Vec<u32> = (MIN_TDP_OPTION..ceiling)
+        .step_by(FINE_TDP_OPTION_STEP as usize)
+        .collect();
+    options.push(ceiling);
+    options
+}
+
+/// Returns the closest option above (or below) `current`, staying at the last one at the ends.
+fn step_tdp_option(options: &[u32], current: u32, up: bool) -> u32 {
+    let next = if up {
+        options.iter().filter(|&&x| x > current).min()
+    } else {
+        options.iter().filter(|&&x| x < current).max()
+    };
+    let edge = if up {
+        options.iter().max()
+    } else {
+        options.iter().min()
+    };
+    next.or(edge).copied().unwrap_or(current)
+}
+
+/// Everything that can ask for a TDP limit, gathered by `refresh_tdp`.
+struct TdpSources {
+    /// Remaining boost in seconds, `Some(None)` while the push-to-boost key is held.
+    boost: Option<Option<u64>>,
+    /// Highest limit on the menu, which is what boosting applies.
+    max_option: Option<u32>,
+    battery_saver: Option<u32>,
+    /// Limit of the foreground application, if it has one that applies right now.
+    application: Option<u32>,
+    setting: TdpSetting,
+}
+
+/// Picks the limit to force and the resulting state. The first source that asks for
+/// a limit wins, in this order:
+/// 1. Boost, while it lasts or the push-to-boost key is held.
+/// 2. Battery Saver limit.
+/// 3. Limit of the foreground application.
+/// 4. Forced TDP setting.
+///
+/// Otherwise the state goes back to tracking, restoring the limit that was in effect
+/// before a temporary source took over. `current` is the measured fast limit.
+fn select_tdp_target(
+    sources: &TdpSources,
+    old_state: TdpState,
+    current: Option<u32>,
+) -> (Option<u32>, TdpState) {
+    // Limit to return to once a temporary source is gone
+    let fallback = match old_state {
+        TdpState::ForcingApplication { fallback }
+        | TdpState::Boosting { fallback, .. }
+        | TdpState::BatterySaver { fallback } => fallback,
+        TdpState::Forcing => None,
+        TdpState::Tracking => current,
+    };
+    if let Some(remaining_secs) = sources.boost {
+        let state = TdpState::Boosting {
+            remaining_secs,
+            fallback,
+        };
+        (sources.max_option, state)
+    } else if let Some(saver_limit) = sources.battery_saver {
+        (Some(saver_limit), TdpState::BatterySaver { fallback })
+    } else if let Some(app_limit) = sources.application {
+        let state = match old_state {
+            TdpState::ForcingApplication { .. } => old_state,
+            _ => TdpState::ForcingApplication { fallback },
+        };
+        (Some(app_limit), state)
+    } else {
+        match sources.setting {
+            TdpSetting::Forcing(x) => (Some(x), TdpState::Forcing),
+            TdpSetting::Tracking => {
+                let target = match old_state {
+                    TdpState::Forcing | TdpState::Tracking => None,
+                    _ => fallback,
+                };
+                (target, TdpState::Tracking)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tdp_options_reach_the_ceiling() {
+        assert_eq!(tdp_options(tdp_ceiling(None)), DEFAULT_TDP_OPTIONS);
+        // Low stock limits do not take the default options away
+        assert_eq!(tdp_options(tdp_ceiling(Some(15000))), DEFAULT_TDP_OPTIONS);
+        assert_eq!(
+            tdp_options(tdp_ceiling(Some(42000)))[DEFAULT_TDP_OPTIONS.len()..],
+            [30000, 35000, 42000]
+        );
+        assert_eq!(tdp_ceiling(Some(200000)), MAX_TDP_OPTION);
+    }
+
+    #[test]
+    fn fine_tdp_options_cover_every_watt() {
+        let options = fine_tdp_options(tdp_ceiling(Some(8000)));
+        assert_eq!(options.len(), 24);
+        assert_eq!(options[..3], [5000, 6000, 7000]);
+        assert_eq!(options.last(), Some(&28000));
+        let options = fine_tdp_options(tdp_ceiling(Some(42500)));
+        assert_eq!(options[options.len() - 2..], [42000, 42500]);
+    }
+
+    #[test]
+    fn tdp_options_are_stepped_within_range() {
+        let options = [5000, 10000, 15000];
+        assert_eq!(step_tdp_option(&options, 5000, true), 10000);
+        assert_eq!(step_tdp_option(&options, 10000, false), 5000);
+        // Limits between options go to the neighbouring one
+        assert_eq!(step_tdp_option(&options, 12000, true), 15000);
+        assert_eq!(step_tdp_option(&options, 12000, false), 10000);
+        assert_eq!(step_tdp_option(&options, 15000, true), 15000);
+        assert_eq!(step_tdp_option(&options, 5000, false), 5000);
+        assert_eq!(step_tdp_option(&options, 30000, true), 15000);
+    }
+
+    fn forcing_sources() -> TdpSources {
+        TdpSources {
+            boost: None,
+            max_option: Some(30000),
+            battery_saver: None,
+            application: None,
+            setting: TdpSetting::Forcing(15000),
+        }
+    }
+
+    #[test]
+    fn application_limit_wins_over_tdp_setting() {
+        let sources = TdpSources {
+            application: Some(8000),
+            ..forcing_sources()
+        };
+        let fallback = None;
+        assert_eq!(
+            select_tdp_target(&sources, TdpState::Forcing, Some(15000)),
+            (Some(8000), TdpState::ForcingApplication { fallback })
+        );
+        // The application has gone to the background
+        let state = TdpState::ForcingApplication { fallback };
+        assert_eq!(
+            select_tdp_target(&forcing_sources(), state, Some(8000)),
+            (Some(15000), TdpState::Forcing)
+        );
+    }
+
+    #[test]
+    fn temporary_sources_win_in_order() {
+        let sources = TdpSources {
+            boost: Some(Some(60)),
+            battery_saver: Some(6000),
+            application: Some(8000),
+            ..forcing_sources()
+        };
+        let fallback = Some(12000);
+        let boosting = TdpState::Boosting {
+            remaining_secs: Some(60),
+            fallback,
+        };
+        assert_eq!(
+            select_tdp_target(&sources, TdpState::Tracking, Some(12000)),
+            (Some(30000), boosting)
+        );
+        let sources = TdpSources {
+            boost: None,
+            ..sources
+        };
+        assert_eq!(
+            select_tdp_target(&sources, boosting, Some(30000)),
+            (Some(6000), TdpState::BatterySaver { fallback })
+        );
+    }
+
+    #[test]
+    fn tracking_restores_the_limit_from_before_forcing() {
+        let sources = TdpSources {
+            setting: TdpSetting::Tracking,
+            ..forcing_sources()
+        };
+        let state = TdpState::ForcingApplication {
+            fallback: Some(12000),
+        };
+        assert_eq!(
+            select_tdp_target(&sources, state, Some(8000)),
+            (Some(12000), TdpState::Tracking)
+        );
+        assert_eq!(
+            select_tdp_target(&sources, TdpState::Forcing, Some(15000)),
+            (None, TdpState::Tracking)
+        );
+    }
+}
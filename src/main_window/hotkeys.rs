@@ -0,0 +1,122 @@
+use super::command_queue::CommandSender;
+use super::commands::Command;
+use std::cell::RefCell;
+use windows::core::{Error, Owned};
+use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, SetWindowsHookExW, HC_ACTION, HHOOK, KBDLLHOOKSTRUCT, LLKHF_UP, WH_KEYBOARD_LL,
+};
+
+/// Virtual-key codes of the keys to watch, `None` for the unused ones.
+#[derive(Copy, Clone, Default)]
+pub struct HotkeyBindings {
+    pub push_to_boost: Option<u32>,
+    pub step_app_tdp_up: Option<u32>,
+    pub step_app_tdp_down: Option<u32>,
+}
+
+impl HotkeyBindings {
+    fn is_empty(&self) -> bool {
+        self.push_to_boost.is_none()
+            && self.step_app_tdp_up.is_none()
+            && self.step_app_tdp_down.is_none()
+    }
+}
+
+struct HookState {
+    bindings: HotkeyBindings,
+    sender: CommandSender,
+    /// Keys currently held down, so that auto-repeat is not reported as new presses.
+    pressed: Vec<u32>,
+}
+
+impl HookState {
+    fn on_key(&mut self, key: u32, pressed: bool) {
+        let was_pressed = self.pressed.contains(&key);
+        if pressed == was_pressed {
+            return;
+        }
+        if pressed {
+            self.pressed.push(key);
+        } else {
+            self.pressed.retain(|&k| k != key);
+        }
+        let bindings = self.bindings;
+        if bindings.push_to_boost == Some(key) {
+            self.sender.send(Command::PushToBoost(pressed));
+        }
+        if pressed && bindings.step_app_tdp_up == Some(key) {
+            self.sender.send(Command::StepApplicationTdp(true));
+        }
+        if pressed && bindings.step_app_tdp_down == Some(key) {
+            self.sender.send(Command::StepApplicationTdp(false));
+        }
+    }
+
+    fn is_bound(&self, key: u32) -> bool {
+        let b = &self.bindings;
+        [b.push_to_boost, b.step_app_tdp_up, b.step_app_tdp_down].contains(&Some(key))
+    }
+}
+
+thread_local! {
+    // Low-level hooks are called on the thread that installed them
+    static HOOK_STATE: RefCell<Option<HookState>> = const { RefCell::new(None) };
+}
+
+/// Watches keys system-wide and queues commands when they are pressed or released.
+/// Hotkeys only report key presses, so this relies on a low-level keyboard hook instead.
+pub struct Hotkeys {
+    _hook: Owned<HHOOK>,
+}
+
+impl Hotkeys {
+    /// Must be called on the thread that runs the message loop, at most once at a time.
+    /// Returns `None` if no key is bound.
+    pub fn install(bindings: HotkeyBindings, sender: CommandSender) -> Result<Option<Self>, Error> {
+        if bindings.is_empty() {
+            return Ok(None);
+        }
+        HOOK_STATE.with_borrow_mut(|state| {
+            *state = Some(HookState {
+                bindings,
+                sender,
+                pressed: vec![],
+            })
+        });
+        // SAFETY: The hook procedure is a valid function for the whole lifetime of the process
+        let hook = unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(Self::hook_proc), None, 0) }
+            .inspect_err(|_| HOOK_STATE.set(None))?;
+        debug!(
+            "Hotkeys are set up: push-to-boost {:?}, step up {:?}, step down {:?}",
+            bindings.push_to_boost, bindings.step_app_tdp_up, bindings.step_app_tdp_down
+        );
+        // SAFETY: We own the returned handle
+        Ok(Some(Hotkeys {
+            _hook: unsafe { Owned::new(hook) },
+        }))
+    }
+
+    extern "system" fn hook_proc(code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        if code == HC_ACTION as i32 {
+            // SAFETY: The OS provides a valid structure for HC_ACTION
+            let info = unsafe { &*(l_param.0 as *const KBDLLHOOKSTRUCT) };
+            HOOK_STATE.with_borrow_mut(|state| {
+                let Some(state) = state.as_mut().filter(|s| s.is_bound(info.vkCode)) else {
+                    return;
+                };
+                let pressed = (info.flags & LLKHF_UP).0 == 0;
+                state.on_key(info.vkCode, pressed);
+            });
+        }
+        // The key is never swallowed
+        // SAFETY: Arguments are passed through as received from the OS
+        unsafe { CallNextHookEx(None, code, w_param, l_param) }
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        HOOK_STATE.set(None);
+    }
+}
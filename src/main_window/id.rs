@@ -1,6 +1,8 @@
+#[derive(Copy, Clone, PartialEq)]
 #[repr(usize)]
 pub enum Timer {
     Main,
+    Overlay,
 }
 
 #[repr(u32)]
@@ -1,19 +1,77 @@
-use crate::settings::{Settings, SettingsStorage};
+use crate::battery::{BatteryDetails, PowerSource};
+use crate::icons::HighContrastColors;
+#[cfg(feature = "rtss")]
+use crate::rtss::Span;
+use crate::ryzenadj::ThrottleStatus;
+use crate::settings::{Settings, SettingsStorage, TdpLimitKind};
 use crate::versioned::Versioned;
 use std::collections::VecDeque;
 use std::ffi::OsString;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TdpState {
     Tracking,
     Forcing,
-    ForcingApplication { fallback: Option<u32> },
+    ForcingApplication {
+        fallback: Option<u32>,
+    },
+    Boosting {
+        /// `None` while the push-to-boost key is held.
+        remaining_secs: Option<u64>,
+        fallback: Option<u32>,
+    },
+    BatterySaver {
+        fallback: Option<u32>,
+    },
+}
+
+/// TDP limits in milliwatts.
+#[derive(Copy, Clone, PartialEq)]
+pub struct TdpLimits {
+    pub fast: u32,
+    pub slow: u32,
+    pub stapm: u32,
+}
+
+impl TdpLimits {
+    pub fn all(value: u32) -> Self {
+        TdpLimits {
+            fast: value,
+            slow: value,
+            stapm: value,
+        }
+    }
+
+    pub fn get(&self, kind: TdpLimitKind) -> u32 {
+        match kind {
+            TdpLimitKind::Fast => self.fast,
+            TdpLimitKind::Slow => self.slow,
+            TdpLimitKind::Stapm => self.stapm,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq)]
 pub struct TdpModel {
-    pub value: Result<u32, String>,
+    pub value: Result<TdpLimits, String>,
+    pub throttle: Option<ThrottleStatus>,
+    /// CPU temperature in degrees Celsius, `None` if the APU does not report it.
+    pub temperature: Option<f32>,
+    /// CPU temperature limit in degrees Celsius, `None` if the APU does not report it.
+    pub temperature_limit: Option<f32>,
+    /// Power drawn by the APU in W, `None` if it is not reported.
+    pub current_power: Option<f32>,
     pub state: TdpState,
+    /// How long the current target has been forced, in seconds.
+    pub forced_secs: Option<u64>,
+    /// Measured limits over the last minute, oldest first. Empty if not shown.
+    pub history: Vec<u32>,
+    /// Limit being forced, `None` while tracking.
+    pub target: Option<u32>,
+    /// RyzenAdj can read the limits of this chip, but not change them.
+    pub unsupported: bool,
+    /// Another tool that changes the limits, while limit changes are paused for it.
+    pub paused_by: Option<String>,
     pub applications: VecDeque<OsString>,
     pub options: Vec<u32>,
 }
@@ -31,12 +89,48 @@ pub struct PopupMenuModel {
     pub menu: PopupMenuType,
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub struct PowerSourceModel {
+    pub source: PowerSource,
+    /// Time since the last transition observed during this session, in seconds.
+    /// `None` until there is one.
+    pub secs: Option<u64>,
+}
+
+/// Remaining battery time estimates, in seconds.
+#[derive(Copy, Clone, PartialEq)]
+pub struct BatteryRuntime {
+    /// Estimate by Windows, which accounts for recent usage.
+    pub os_secs: Option<u32>,
+    /// Estimate from the current discharge rate.
+    pub instant_secs: Option<u32>,
+}
+
 /// Model defines the current state of the application.
 #[derive(Clone, Default, PartialEq)]
 pub struct Model {
     pub tdp: Option<TdpModel>,
-    pub charge_icon: Option<Result<i32, String>>,
+    /// Charge rate in mW, `None` if the battery does not report it.
+    pub charge_icon: Option<Result<Option<i32>, String>>,
+    pub battery_details: Option<BatteryDetails>,
+    pub power_source: Option<PowerSourceModel>,
+    pub battery_runtime: Option<BatteryRuntime>,
+    /// Charge level in percent.
+    pub battery_percent: Option<u32>,
+    /// Battery voltage in mV.
+    pub battery_voltage: Option<u32>,
+    /// Highest discharge rate in mW seen this session.
+    pub peak_discharge: Option<u32>,
     pub popup_menu: Option<PopupMenuModel>,
+    /// Message to be shown once in a balloon next to one of the icons.
+    pub notification: Option<String>,
+    /// Colors of the active high contrast theme, if any.
+    pub high_contrast: Option<HighContrastColors>,
+    /// DPI the notification icons are rendered for.
+    pub dpi: u32,
+    /// Overlay as it is laid out for RTSS, `Some` while the preview is open.
+    #[cfg(feature = "rtss")]
+    pub overlay_preview: Option<Vec<Span>>,
     pub settings: Versioned<Settings>,
 }
 
@@ -45,7 +139,18 @@ impl Model {
         Model {
             tdp: None,
             charge_icon: None,
+            battery_details: None,
+            power_source: None,
+            battery_runtime: None,
+            battery_percent: None,
+            battery_voltage: None,
+            peak_discharge: None,
             popup_menu: None,
+            notification: None,
+            high_contrast: None,
+            dpi: 96,
+            #[cfg(feature = "rtss")]
+            overlay_preview: None,
             settings: Versioned::new(settings_storage.load()),
         }
     }
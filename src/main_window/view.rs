@@ -1,17 +1,59 @@
 use super::commands::Command;
 use super::id;
-use super::model::{Model, PopupMenuType, TdpModel, TdpState};
+use super::model::{
+    BatteryRuntime, Model, PopupMenuType, PowerSourceModel, TdpLimits, TdpModel, TdpState,
+};
+use crate::battery::{BatteryDetails, PowerSource};
 use crate::gdip::{Color, GdiPlus};
 use crate::icons::NotifyIcon;
 use crate::menu::PopupMenu;
-use crate::settings::TdpSetting;
+#[cfg(feature = "rtss")]
+use crate::overlay_preview::OverlayPreview;
+use crate::ryzenadj::ThrottleStatus;
+use crate::settings::{
+    get_package_name, ChargeColorScheme, ChargeTooltipField, Settings, TdpLimitKind, TdpSetting,
+    TemperatureUnit,
+};
 use std::mem::replace;
 use std::path::Path;
+use std::time::Duration;
 use windows::Win32::Foundation::HWND;
 use windows::Win32::UI::WindowsAndMessaging::EndMenu;
 
+/// Menu command IDs are 16-bit. With options in 1 W steps the TDP menu needs several hundred,
+/// one per option in each application submenu.
 const IDM_TDP_START: u32 = 1;
-const IDM_CHARGE_START: u32 = 257;
+const IDM_CHARGE_START: u32 = 0x4000;
+/// Posted by the overlay preview window when the user closes it.
+#[cfg(feature = "rtss")]
+const IDM_CLOSE_OVERLAY_PREVIEW: u32 = 0x8000;
+const BOOST_PRESET_MINUTES: [u64; 3] = [5, 10, 30];
+const CHARGE_COLOR_SCHEMES: [(ChargeColorScheme, &str); 3] = [
+    (ChargeColorScheme::Classic, "&Green when charging"),
+    (ChargeColorScheme::Inverted, "&Red when discharging"),
+    (
+        ChargeColorScheme::RateMagnitude,
+        "Red when discharging &fast",
+    ),
+];
+/// Difference (in seconds) between the remaining time estimates at which both are shown.
+const RUNTIME_ESTIMATES_MAX_DIFF: u32 = 5 * 60;
+/// Discharge rate (mW) at which the rate-magnitude scheme reaches full red.
+const FAST_DISCHARGE_RATE: i32 = 30000;
+/// Color of the charge icon while the rate is within the idle band, e.g. on AC with a full battery.
+const IDLE_CHARGE_COLOR: Color = Color::from_rgb(0xB0, 0xB0, 0xB0);
+/// Difference (in mW) from the forced target at which a limit is considered not applied.
+const UNAPPLIED_LIMIT_TOLERANCE: u32 = 500;
+/// Power draw relative to the limit from which the gauge turns yellow, then red.
+const GAUGE_WARNING_LEVEL: f32 = 0.8;
+const GAUGE_CRITICAL_LEVEL: f32 = 0.95;
+/// Notification icon tooltips hold 128 UTF-16 units, including the terminating null.
+const MAX_TOOLTIP_LEN: usize = 127;
+const TRAY_LIMIT_KINDS: [(TdpLimitKind, &str); 3] = [
+    (TdpLimitKind::Fast, "&Fast limit"),
+    (TdpLimitKind::Slow, "&Slow limit"),
+    (TdpLimitKind::Stapm, "S&TAPM limit"),
+];
 
 /// View owns the UI components and renders model in the window.
 pub struct View<'gdip> {
@@ -24,6 +66,10 @@ pub struct View<'gdip> {
     charge_icon: Option<NotifyIcon<'gdip>>,
     charge_icon_popup_menu: Option<PopupMenu>,
     charge_icon_menu_commands: Vec<Command>,
+    /// Set while a popup menu is tracked in a nested message loop.
+    menu_active: bool,
+    #[cfg(feature = "rtss")]
+    overlay_preview: Option<OverlayPreview>,
 }
 
 impl<'gdip> View<'gdip> {
@@ -41,16 +87,23 @@ impl<'gdip> View<'gdip> {
             charge_icon: None,
             charge_icon_popup_menu: None,
             charge_icon_menu_commands: vec![],
+            menu_active: false,
+            #[cfg(feature = "rtss")]
+            overlay_preview: None,
         }
     }
 
-    /// Updates UI according to the provided model.
-    pub fn update(&mut self, new_model: &Model) {
+    /// Updates UI according to the provided model. Returns `false` if nothing has changed.
+    pub fn update(&mut self, new_model: &Model) -> bool {
+        if *new_model == self.model {
+            trace!("Bypassing view update - no changes detected");
+            return false;
+        }
         trace!("Updating the view");
         let old_model = replace(&mut self.model, new_model.clone());
         if let Some(tdp) = &new_model.tdp {
-            self.update_tdp_icon(&old_model.tdp, tdp);
-            let menu_rebuilt = self.update_tdp_menu(&old_model.tdp, tdp);
+            self.update_tdp_icon(&old_model, tdp, &new_model.settings);
+            let menu_rebuilt = self.update_tdp_menu(&old_model, tdp, &new_model.settings);
             self.update_tdp_selection(&old_model, &new_model, menu_rebuilt);
         } else {
             trace!("No TDP icon");
@@ -64,13 +117,43 @@ impl<'gdip> View<'gdip> {
                 NotifyIcon::new(self.window, id::NotifyIcon::ChargeRate as _, self.gdi_plus)
                     .unwrap()
             });
-            Self::update_charge_icon(charge_icon, &old_model.charge_icon, charge_icon_model);
-            self.build_charge_icon_menu();
+            let outline = new_model.settings.is_icon_outline_enabled();
+            charge_icon.set_high_contrast(new_model.high_contrast);
+            charge_icon.set_outline(outline);
+            charge_icon.set_dpi(new_model.dpi);
+            let scheme = new_model.settings.get_charge_color_scheme();
+            let idle_band = new_model.settings.get_charge_idle_band();
+            let tip = format_charge_tooltip(new_model);
+            Self::update_charge_icon(
+                charge_icon,
+                &old_model.charge_icon,
+                charge_icon_model,
+                &tip,
+                scheme,
+                idle_band,
+                old_model.high_contrast != new_model.high_contrast
+                    || old_model.dpi != new_model.dpi
+                    || old_model.settings.is_icon_outline_enabled() != outline
+                    || format_charge_tooltip(&old_model) != tip
+                    || old_model.settings.get_charge_color_scheme() != scheme
+                    || old_model.settings.get_charge_idle_band() != idle_band,
+            );
+            self.build_charge_icon_menu(&old_model, new_model);
         } else {
             trace!("No charge icon");
             self.charge_icon = None;
             self.charge_icon_popup_menu = None;
         }
+        if new_model.notification != old_model.notification {
+            if let Some(text) = &new_model.notification {
+                match self.tdp_icon.as_mut().or(self.charge_icon.as_mut()) {
+                    Some(icon) => icon.show_balloon("LilPowerMan", text),
+                    None => error!("No icon to show the notification: {}", text),
+                }
+            }
+        }
+        #[cfg(feature = "rtss")]
+        self.update_overlay_preview(&old_model, new_model);
         if new_model.popup_menu != old_model.popup_menu {
             // SAFETY: The call is always sound
             let result = unsafe { EndMenu() };
@@ -83,9 +166,17 @@ impl<'gdip> View<'gdip> {
                     PopupMenuType::TdpIcon => &self.tdp_icon_popup_menu,
                     PopupMenuType::ChargeIcon => &self.charge_icon_popup_menu,
                 };
-                if let Some(menu) = menu {
+                if self.menu_active {
+                    // The update came from the nested message loop of the menu being shown
+                    debug!(
+                        "Another popup menu is active, not showing {:?} menu",
+                        popup_menu.menu
+                    );
+                } else if let Some(menu) = menu {
+                    self.menu_active = true;
                     // SAFETY: The handle points to a currently live window
-                    _ = unsafe { menu.show(popup_menu.x, popup_menu.y, self.window) }
+                    _ = unsafe { menu.show(popup_menu.x, popup_menu.y, self.window) };
+                    self.menu_active = false;
                 } else {
                     error!(
                         "Request to display a non-existing {:?} menu",
@@ -96,11 +187,63 @@ impl<'gdip> View<'gdip> {
                 trace!("Hiding popup menu");
             }
         }
+        true
     }
 
-    fn update_tdp_icon(&mut self, old_model: &Option<TdpModel>, model: &TdpModel) {
-        if let Some(old_model) = old_model {
-            if old_model.state == model.state && old_model.value == model.value {
+    #[cfg(feature = "rtss")]
+    fn update_overlay_preview(&mut self, old_model: &Model, model: &Model) {
+        let Some(spans) = &model.overlay_preview else {
+            if self.overlay_preview.take().is_some() {
+                trace!("Closing overlay preview");
+            }
+            return;
+        };
+        if self.overlay_preview.is_none() {
+            trace!("Opening overlay preview");
+            // SAFETY: The handle points to a currently live window
+            match unsafe { OverlayPreview::new(self.window, IDM_CLOSE_OVERLAY_PREVIEW) } {
+                Ok(preview) => self.overlay_preview = Some(preview),
+                Err(err) => {
+                    error!("Failed to open overlay preview: {}", err);
+                    return;
+                }
+            }
+        } else if old_model.overlay_preview.as_ref() == Some(spans) {
+            return;
+        }
+        if let Some(preview) = &mut self.overlay_preview {
+            preview.update(spans);
+        }
+    }
+
+    fn update_tdp_icon(&mut self, old_model: &Model, model: &TdpModel, settings: &Settings) {
+        let kind = settings.get_tray_limit();
+        let show_target = settings.is_tray_target_enabled();
+        let show_gauge = settings.is_tray_gauge_enabled();
+        let show_power = settings.is_tray_power_enabled();
+        let outline = settings.is_icon_outline_enabled();
+        let unit = settings.get_temperature_unit();
+        if let Some(old_tdp) = &old_model.tdp {
+            if old_model.settings.get_tray_limit() == kind
+                && old_model.settings.is_tray_target_enabled() == show_target
+                && old_model.settings.is_tray_gauge_enabled() == show_gauge
+                && old_model.settings.is_tray_power_enabled() == show_power
+                && old_model.settings.is_icon_outline_enabled() == outline
+                && old_model.settings.get_temperature_unit() == unit
+                && (!(show_gauge || show_power) || old_tdp.current_power == model.current_power)
+                && old_tdp.target == model.target
+                && old_tdp.unsupported == model.unsupported
+                && old_tdp.paused_by == model.paused_by
+                && old_tdp.history == model.history
+                && old_model.high_contrast == self.model.high_contrast
+                && old_model.dpi == self.model.dpi
+                && old_tdp.state == model.state
+                && old_tdp.value == model.value
+                && old_tdp.throttle == model.throttle
+                && old_tdp.temperature == model.temperature
+                && old_tdp.temperature_limit == model.temperature_limit
+                && old_tdp.forced_secs == model.forced_secs
+            {
                 trace!("Bypassing TDP icon update - no changes detected");
                 return;
             }
@@ -111,28 +254,74 @@ impl<'gdip> View<'gdip> {
             trace!("Creating TDP icon");
             NotifyIcon::new(self.window, id::NotifyIcon::TdpLimit as _, self.gdi_plus).unwrap()
         });
+        tdp_icon.set_high_contrast(self.model.high_contrast);
+        tdp_icon.set_outline(outline);
+        tdp_icon.set_dpi(self.model.dpi);
         match model.value {
-            Ok(ref tdp_limit) => {
-                let tip;
-                let color;
-                match model.state {
-                    TdpState::Tracking => {
-                        tip = format!("Current TDP: {} mW", tdp_limit);
-                        color = Color::CYAN;
-                    }
-                    TdpState::Forcing => {
-                        tip = format!("TDP setting: {} mW", tdp_limit);
-                        color = Color::WHITE;
-                    }
-                    TdpState::ForcingApplication { .. } => {
-                        tip = format!("Application TDP setting: {} mW", tdp_limit);
-                        color = Color::YELLOW;
-                    }
+            Ok(ref limits) => {
+                // Throttling might keep the measured limit below the target for a while,
+                // the tooltip still lists the measured limits
+                let tdp_limit = match model.target {
+                    Some(target) if show_target => target,
+                    _ => limits.get(kind),
+                };
+                let (mut tip, color) = format_tdp_state(model.state, tdp_limit);
+                if let (TdpState::Forcing | TdpState::ForcingApplication { .. }, Some(secs)) =
+                    (model.state, model.forced_secs)
+                {
+                    tip.push_str(&format!(" for {}", format_duration(secs)));
+                }
+                if let TdpState::ForcingApplication { fallback } = model.state {
+                    tip.push_str(&format_app_fallback(fallback));
+                }
+                if let Some(tool) = &model.paused_by {
+                    tip.push_str(&format!("\nPaused while {} runs", tool));
+                }
+                // Right after the first line, so that it survives the tooltip length limit
+                if !model.history.is_empty() {
+                    tip.push('\n');
+                    tip.push_str(&format_sparkline(&model.history));
+                }
+                let power = model.current_power.filter(|_| show_power);
+                if let Some(power) = power {
+                    tip.push_str(&format!("\nPower draw: {:.1} W", power));
+                }
+                tip.push('\n');
+                tip.push_str(&format_tdp_limits(limits));
+                if let Some(unapplied) = model.target.and_then(|t| format_unapplied(limits, t)) {
+                    tip.push('\n');
+                    tip.push_str(&unapplied);
+                }
+                if let Some(throttle) = model.throttle.as_ref().and_then(format_throttle_status) {
+                    tip.push('\n');
+                    tip.push_str(&throttle);
+                }
+                if let Some(temperature) = format_temperature(model, unit) {
+                    tip.push('\n');
+                    tip.push_str(&temperature);
+                }
+                let color = if model.unsupported {
+                    tip.insert_str(0, "TDP control is not supported on this chip\n");
+                    Color::GRAY
+                } else {
+                    color
+                };
+                let gauge = model
+                    .current_power
+                    .filter(|_| show_gauge && tdp_limit > 0)
+                    .map(|power| {
+                        let fill = power * 1000.0 / tdp_limit as f32;
+                        (fill, gauge_color(fill))
+                    });
+                tdp_icon.set_gauge(gauge);
+                let text = match power {
+                    Some(power) => format!("{:.0}", power),
+                    None => format!("{}", tdp_limit / 1000),
                 };
-                let text = format!("{}", tdp_limit / 1000);
                 tdp_icon.update(tip.as_str(), text.as_str(), color);
             }
             Err(ref err) => {
+                tdp_icon.set_gauge(None);
                 tdp_icon.update(
                     format!("Failed to get TDP information: {}", err).as_str(),
                     "🛑",
@@ -154,25 +343,45 @@ impl<'gdip> View<'gdip> {
                 .get((id - IDM_CHARGE_START) as usize)
                 .cloned()
         } else {
+            #[cfg(feature = "rtss")]
+            if id == IDM_CLOSE_OVERLAY_PREVIEW {
+                return Some(Command::CloseOverlayPreview);
+            }
             None
         }
     }
 
     fn add_tdp_command(&mut self, command: Command) -> u32 {
         let id = IDM_TDP_START + self.tdp_icon_menu_commands.len() as u32;
+        assert!(id < IDM_CHARGE_START, "Too many TDP menu commands");
         self.tdp_icon_menu_commands.push(command);
         id
     }
 
     fn add_charge_command(&mut self, command: Command) -> u32 {
         let id = IDM_CHARGE_START + self.charge_icon_menu_commands.len() as u32;
+        #[cfg(feature = "rtss")]
+        assert!(
+            id < IDM_CLOSE_OVERLAY_PREVIEW,
+            "Too many charge menu commands"
+        );
         self.charge_icon_menu_commands.push(command);
         id
     }
 
-    fn update_tdp_menu(&mut self, old_model: &Option<TdpModel>, model: &TdpModel) -> bool {
-        if let Some(old_model) = old_model {
-            if old_model.options == model.options && old_model.applications == model.applications {
+    fn update_tdp_menu(
+        &mut self,
+        old_model: &Model,
+        model: &TdpModel,
+        settings: &Settings,
+    ) -> bool {
+        let reset_on_observe = settings.is_reset_on_observe_enabled();
+        if let Some(old_tdp) = &old_model.tdp {
+            if old_tdp.options == model.options
+                && old_tdp.applications == model.applications
+                && old_tdp.unsupported == model.unsupported
+                && old_model.settings.is_reset_on_observe_enabled() == reset_on_observe
+            {
                 trace!("Bypassing TDP menu update - no changes detected");
                 return false;
             }
@@ -181,6 +390,10 @@ impl<'gdip> View<'gdip> {
         // TODO: Update the existing menu instead of building a new one from scratch
         self.tdp_icon_menu_commands.clear();
         let mut menu = PopupMenu::new();
+        if model.unsupported {
+            menu.append_info_item("Unsupported chip, limits cannot be changed", false);
+            menu.append_separator();
+        }
         if model.applications.len() > 0 {
             for app in &model.applications {
                 let mut app_menu = PopupMenu::new();
@@ -190,25 +403,82 @@ impl<'gdip> View<'gdip> {
                     let id = self.add_tdp_command(Command::SetApplicationTdp(app.clone(), *tdp));
                     app_menu.append_menu_item(&format!("{} W", (*tdp as f32) / 1000.0), id);
                 }
-                let path = Path::new(app);
-                let file_name = path
-                    .file_name()
-                    .unwrap_or(app)
-                    .to_str()
-                    .unwrap_or("<UNKNOWN>");
+                app_menu.append_separator();
+                let id =
+                    self.add_tdp_command(Command::ToggleApplicationFullscreenOnly(app.clone()));
+                app_menu.append_menu_item("&Full-screen only", id);
+                let file_name = get_package_name(app).unwrap_or_else(|| {
+                    Path::new(app)
+                        .file_name()
+                        .unwrap_or(app)
+                        .to_str()
+                        .unwrap_or("<UNKNOWN>")
+                });
                 menu.append_submenu(file_name, app_menu);
             }
+            let mut all_apps_menu = PopupMenu::new();
+            for tdp in &model.options {
+                let id = self.add_tdp_command(Command::SetAllAppsTdp(*tdp));
+                all_apps_menu.append_menu_item(&format!("{} W…", (*tdp as f32) / 1000.0), id);
+            }
+            menu.append_submenu("Set &all applications", all_apps_menu);
             menu.append_separator();
         }
         let id = self.add_tdp_command(Command::Observe);
-        menu.append_menu_item("Just &observe", id);
+        let title = if reset_on_observe {
+            "&Observe (reset to stock)"
+        } else {
+            "Just &observe"
+        };
+        menu.append_menu_item(title, id);
         for tdp in &model.options {
             let id = self.add_tdp_command(Command::SetTdp(*tdp));
             menu.append_menu_item(&format!("{} W", (*tdp as f32) / 1000.0), id);
         }
         menu.append_separator();
+        let mut boost_menu = PopupMenu::new();
+        for minutes in BOOST_PRESET_MINUTES {
+            let id = self.add_tdp_command(Command::BoostFor(Duration::from_secs(minutes * 60)));
+            boost_menu.append_menu_item(&format!("{} minutes", minutes), id);
+        }
+        menu.append_submenu("&Boost", boost_menu);
+        let mut tray_limit_menu = PopupMenu::new();
+        for (kind, title) in TRAY_LIMIT_KINDS {
+            let id = self.add_tdp_command(Command::SetTrayLimit(kind));
+            tray_limit_menu.append_menu_item(title, id);
+        }
+        menu.append_submenu("&Icon shows", tray_limit_menu);
+        menu.append_separator();
+        #[cfg(feature = "rtss")]
+        {
+            let id = self.add_tdp_command(Command::PreviewOverlay);
+            menu.append_menu_item("Preview o&verlay", id);
+            let id = self.add_tdp_command(Command::CopyOverlayLayout);
+            menu.append_menu_item("Cop&y overlay layout", id);
+            let id = self.add_tdp_command(Command::PasteOverlayLayout);
+            menu.append_menu_item("&Paste overlay layout", id);
+        }
+        let id = self.add_tdp_command(Command::CopyLogTail);
+        menu.append_menu_item("Copy recent l&og", id);
+        let id = self.add_tdp_command(Command::ClearLogs);
+        menu.append_menu_item("&Clear logs…", id);
+        let id = self.add_tdp_command(Command::OpenSettings);
+        menu.append_menu_item("&Settings…", id);
         let id = self.add_tdp_command(Command::Exit);
         menu.append_menu_item("E&xit", id);
+        if model.unsupported {
+            for (i, cmd) in self.tdp_icon_menu_commands.iter().enumerate() {
+                if let Command::ResetApplicationTdp(_)
+                | Command::SetApplicationTdp(..)
+                | Command::ToggleApplicationFullscreenOnly(_)
+                | Command::SetAllAppsTdp(_)
+                | Command::SetTdp(_)
+                | Command::BoostFor(_) = cmd
+                {
+                    menu.enable_menu_item(i as u32 + IDM_TDP_START, false);
+                }
+            }
+        }
         self.tdp_icon_popup_menu = Some(menu);
         true
     }
@@ -231,45 +501,64 @@ impl<'gdip> View<'gdip> {
                 Command::SetApplicationTdp(app, limit) => {
                     model.settings.get_app_limit(app) == Some(*limit)
                 }
+                Command::ToggleApplicationFullscreenOnly(app) => {
+                    model.settings.is_app_fullscreen_only(app)
+                }
                 Command::SetTdp(target) => {
                     model.settings.get_tdp_setting() == TdpSetting::Forcing(*target)
                 }
-                Command::Exit => continue,
+                Command::SetTrayLimit(kind) => model.settings.get_tray_limit() == *kind,
+                Command::StepApplicationTdp(_)
+                | Command::SetAllAppsTdp(_)
+                | Command::BoostFor(_)
+                | Command::PushToBoost(_)
+                | Command::SetChargeColorScheme(_)
+                | Command::ResetSessionPeaks
+                | Command::OpenSettings
+                | Command::ClearLogs
+                | Command::CopyLogTail
+                | Command::ShowUpdate(_)
+                | Command::Exit => continue,
+                #[cfg(feature = "rtss")]
+                Command::PreviewOverlay
+                | Command::CloseOverlayPreview
+                | Command::CopyOverlayLayout
+                | Command::PasteOverlayLayout => continue,
             };
-            menu.check_menu_item(id, checked);
+            if menu.check_menu_item(id, checked).is_none() {
+                debug!(
+                    "Failed to update selection of TDP menu item {}, skipping",
+                    id
+                );
+            }
         }
     }
 
     fn update_charge_icon(
         charge_icon: &mut NotifyIcon,
-        old_model: &Option<Result<i32, String>>,
-        model: &Result<i32, String>,
+        old_model: &Option<Result<Option<i32>, String>>,
+        model: &Result<Option<i32>, String>,
+        tip: &str,
+        scheme: ChargeColorScheme,
+        idle_band: u32,
+        force: bool,
     ) {
-        if Some(model) == old_model.as_ref() {
+        if !force && Some(model) == old_model.as_ref() {
             trace!("Bypassing charge icon update - no changes detected");
             return;
         }
         trace!("Updating charge icon");
         match model {
-            Ok(charge_rate) => {
-                let is_charging = *charge_rate >= 0;
-                let abs_rate = charge_rate.abs();
-                let is_single_digit = abs_rate < 10000;
+            Ok(Some(charge_rate)) => {
                 charge_icon.update(
-                    format!("Battery charge rate: {} mW", charge_rate).as_str(),
-                    if is_single_digit {
-                        format!("{}.{}", abs_rate / 1000, (abs_rate / 100) % 10)
-                    } else {
-                        format!("{}", abs_rate / 1000)
-                    }
-                    .as_str(),
-                    if is_charging {
-                        Color::GREEN
-                    } else {
-                        Color::WHITE
-                    },
+                    tip,
+                    &format_charge_face(*charge_rate),
+                    charge_icon_color(scheme, idle_band, *charge_rate),
                 );
             }
+            Ok(None) => {
+                charge_icon.update(tip, "—", Color::WHITE);
+            }
             Err(err) => {
                 charge_icon.update(
                     format!("Failed to get battery information: {}", err).as_str(),
@@ -280,16 +569,472 @@ impl<'gdip> View<'gdip> {
         }
     }
 
-    fn build_charge_icon_menu(&mut self) {
-        if self.charge_icon_popup_menu.is_some() {
+    fn build_charge_icon_menu(&mut self, old_model: &Model, model: &Model) {
+        let details = &model.battery_details;
+        let scheme = model.settings.get_charge_color_scheme();
+        if self.charge_icon_popup_menu.is_some()
+            && old_model.battery_details == *details
+            && old_model.settings.get_charge_color_scheme() == scheme
+        {
             trace!("Bypassing charge icon menu update - no changes detected");
             return;
         }
         trace!("Updating charge icon menu");
         self.charge_icon_menu_commands.clear();
         let mut menu = PopupMenu::new();
+        menu.append_submenu(
+            "Battery &details",
+            Self::build_battery_details_menu(details),
+        );
+        let mut colors_menu = PopupMenu::new();
+        let mut selected = None;
+        for (option, title) in CHARGE_COLOR_SCHEMES {
+            let id = self.add_charge_command(Command::SetChargeColorScheme(option));
+            colors_menu.append_menu_item(title, id);
+            if option == scheme {
+                selected = Some(id);
+            }
+        }
+        menu.append_submenu("&Colors", colors_menu);
+        if let Some(id) = selected {
+            if menu.check_menu_item(id, true).is_none() {
+                debug!("Failed to check charge color scheme item {}", id);
+            }
+        }
+        let id = self.add_charge_command(Command::ResetSessionPeaks);
+        menu.append_menu_item("Reset &peak discharge", id);
+        menu.append_separator();
+        let id = self.add_charge_command(Command::OpenSettings);
+        menu.append_menu_item("&Settings…", id);
         let id = self.add_charge_command(Command::Exit);
         menu.append_menu_item("E&xit", id);
         self.charge_icon_popup_menu = Some(menu);
     }
+
+    fn build_battery_details_menu(details: &Option<BatteryDetails>) -> PopupMenu {
+        fn append(menu: &mut PopupMenu, title: &str, value: Option<String>) {
+            match value {
+                Some(value) => menu.append_info_item(&format!("{}: {}", title, value), true),
+                None => menu.append_info_item(&format!("{}: unknown", title), false),
+            }
+        }
+        let mut menu = PopupMenu::new();
+        let Some(details) = details else {
+            menu.append_info_item("Not available", false);
+            return menu;
+        };
+        append(&mut menu, "Manufacturer", details.manufacturer.clone());
+        append(
+            &mut menu,
+            "Health",
+            details.health().map(|h| format!("{} %", h)),
+        );
+        append(
+            &mut menu,
+            "Cycle count",
+            details.cycle_count.map(|c| c.to_string()),
+        );
+        append(
+            &mut menu,
+            "Voltage",
+            details
+                .voltage
+                .map(|v| format!("{}.{:03} V", v / 1000, v % 1000)),
+        );
+        append(
+            &mut menu,
+            "Design capacity",
+            details.designed_capacity.map(|c| format!("{} mWh", c)),
+        );
+        append(
+            &mut menu,
+            "Full charge capacity",
+            details.full_charged_capacity.map(|c| format!("{} mWh", c)),
+        );
+        menu
+    }
+}
+
+/// Colors the gauge by how close the power draw is to the limit.
+fn gauge_color(fill: f32) -> Color {
+    if fill >= GAUGE_CRITICAL_LEVEL {
+        Color::RED
+    } else if fill >= GAUGE_WARNING_LEVEL {
+        Color::YELLOW
+    } else {
+        Color::GREEN
+    }
+}
+
+/// Rates within `idle_band` mW of zero get a neutral color, so that a battery hovering
+/// around zero does not flip between the charging and the discharging colors.
+fn charge_icon_color(scheme: ChargeColorScheme, idle_band: u32, charge_rate: i32) -> Color {
+    if charge_rate.unsigned_abs() < idle_band {
+        return IDLE_CHARGE_COLOR;
+    }
+    let is_charging = charge_rate >= 0;
+    match scheme {
+        ChargeColorScheme::Classic if is_charging => Color::GREEN,
+        ChargeColorScheme::Classic => Color::WHITE,
+        ChargeColorScheme::Inverted if is_charging => Color::WHITE,
+        ChargeColorScheme::Inverted => Color::RED,
+        ChargeColorScheme::RateMagnitude if is_charging => Color::GREEN,
+        ChargeColorScheme::RateMagnitude => {
+            let rate = charge_rate.saturating_neg().min(FAST_DISCHARGE_RATE);
+            let fade = (255 - 255 * rate / FAST_DISCHARGE_RATE) as u8;
+            Color::from_rgb(0xFF, fade, fade)
+        }
+    }
+}
+
+/// Formats the charge rate in W for the icon, which only fits a couple of characters.
+/// The direction is conveyed by the color, so the sign is dropped.
+/// Rates below 10 W get one decimal digit, truncated rather than rounded.
+fn format_charge_face(rate_mw: i32) -> String {
+    let abs_rate = rate_mw.unsigned_abs();
+    if abs_rate < 10000 {
+        format!("{}.{}", abs_rate / 1000, (abs_rate / 100) % 10)
+    } else {
+        format!("{}", abs_rate / 1000)
+    }
+}
+
+/// Returns the first tooltip line for the state and the matching icon color.
+fn format_tdp_state(state: TdpState, tdp_limit: u32) -> (String, Color) {
+    match state {
+        TdpState::Tracking => (format!("Current TDP: {} mW", tdp_limit), Color::CYAN),
+        TdpState::Forcing => (format!("TDP setting: {} mW", tdp_limit), Color::WHITE),
+        TdpState::ForcingApplication { .. } => (
+            format!("Application TDP setting: {} mW", tdp_limit),
+            Color::YELLOW,
+        ),
+        TdpState::BatterySaver { .. } => (
+            format!("Battery saver TDP setting: {} mW", tdp_limit),
+            Color::ORANGE,
+        ),
+        TdpState::Boosting { remaining_secs, .. } => {
+            let tip = match remaining_secs {
+                Some(secs) => format!("Boost: {} mW, {} left", tdp_limit, format_duration(secs)),
+                None => format!("Boost: {} mW while the key is held", tdp_limit),
+            };
+            (tip, Color::GREEN)
+        }
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+fn format_power_source(model: &PowerSourceModel) -> String {
+    let source = match model.source {
+        PowerSource::Ac => "On AC",
+        PowerSource::Battery => "On battery",
+    };
+    match model.secs {
+        Some(secs) => format!("{} for {}", source, format_duration(secs)),
+        None => source.to_string(),
+    }
+}
+
+/// Formats a duration in seconds as e.g. `2h10m`.
+fn format_hours_minutes(secs: u32) -> String {
+    format!("{}h{:02}m", secs / 3600, (secs / 60) % 60)
+}
+
+fn format_battery_runtime(runtime: &BatteryRuntime) -> Option<String> {
+    match (runtime.os_secs, runtime.instant_secs) {
+        (Some(os), Some(instant)) if os.abs_diff(instant) >= RUNTIME_ESTIMATES_MAX_DIFF => {
+            Some(format!(
+                "Remaining: OS: {}, inst: {}",
+                format_hours_minutes(os),
+                format_hours_minutes(instant)
+            ))
+        }
+        (_, Some(secs)) | (Some(secs), None) => {
+            Some(format!("Remaining: {}", format_hours_minutes(secs)))
+        }
+        (None, None) => None,
+    }
+}
+
+/// Composes the charge icon tooltip from the fields chosen in the settings.
+fn format_charge_tooltip(model: &Model) -> String {
+    let fields = model.settings.get_charge_tooltip_fields();
+    let lines = fields.iter().filter_map(|field| match field {
+        ChargeTooltipField::Rate => match model.charge_icon {
+            Some(Ok(Some(rate))) => Some(format!("Battery charge rate: {} mW", rate)),
+            Some(Ok(None)) => Some(String::from("Battery charge rate: unknown")),
+            _ => None,
+        },
+        ChargeTooltipField::Percent => model.battery_percent.map(|p| format!("Charge: {} %", p)),
+        ChargeTooltipField::Voltage => model
+            .battery_voltage
+            .map(|v| format!("Voltage: {}.{:03} V", v / 1000, v % 1000)),
+        ChargeTooltipField::PowerSource => model.power_source.as_ref().map(format_power_source),
+        ChargeTooltipField::Runtime => model
+            .battery_runtime
+            .as_ref()
+            .and_then(format_battery_runtime),
+        ChargeTooltipField::PeakDischarge => model
+            .peak_discharge
+            .map(|rate| format!("Peak discharge: {} mW", rate)),
+        ChargeTooltipField::Health => model
+            .battery_details
+            .as_ref()
+            .and_then(BatteryDetails::health)
+            .map(|h| format!("Health: {} %", h)),
+    });
+    fit_tooltip(lines)
+}
+
+/// Joins the lines, dropping those that would not fit into a notification icon tooltip.
+/// A single line that is too long is cut short instead.
+fn fit_tooltip(lines: impl IntoIterator<Item = String>) -> String {
+    let mut tip = String::new();
+    let mut len = 0;
+    for line in lines {
+        let separator = if tip.is_empty() { 0 } else { 1 };
+        let line_len = line.encode_utf16().count();
+        if len + separator + line_len <= MAX_TOOLTIP_LEN {
+            if separator > 0 {
+                tip.push('\n');
+            }
+            tip.push_str(&line);
+            len += separator + line_len;
+        } else if tip.is_empty() {
+            // Leave room for the ellipsis, never splitting a character
+            for c in line.chars() {
+                if len + c.len_utf16() >= MAX_TOOLTIP_LEN {
+                    break;
+                }
+                tip.push(c);
+                len += c.len_utf16();
+            }
+            tip.push('…');
+            break;
+        } else {
+            break;
+        }
+    }
+    tip
+}
+
+fn format_app_fallback(fallback: Option<u32>) -> String {
+    match fallback {
+        Some(value) => format!(
+            ", will restore to {} W when closed",
+            (value as f32) / 1000.0
+        ),
+        None => String::from(", will return to tracking"),
+    }
+}
+
+fn format_tdp_limits(limits: &TdpLimits) -> String {
+    format!(
+        "Fast: {} mW, slow: {} mW, STAPM: {} mW",
+        limits.fast, limits.slow, limits.stapm
+    )
+}
+
+/// Names the limits that did not land on the forced target, e.g. when the SMU
+/// has only accepted some of them.
+fn format_unapplied(limits: &TdpLimits, target: u32) -> Option<String> {
+    let unapplied: Vec<_> = [
+        ("fast", limits.fast),
+        ("slow", limits.slow),
+        ("STAPM", limits.stapm),
+    ]
+    .into_iter()
+    .filter(|(_, limit)| limit.abs_diff(target) > UNAPPLIED_LIMIT_TOLERANCE)
+    .map(|(name, _)| name)
+    .collect();
+    if unapplied.is_empty() {
+        return None;
+    }
+    Some(format!("Not at {} mW: {}", target, unapplied.join(", ")))
+}
+
+/// Draws the values as a line of block characters, scaled from the lowest to the highest.
+fn format_sparkline(values: &[u32]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    let range = (max - min).max(1) as u64;
+    values
+        .iter()
+        .map(|&v| BLOCKS[((v - min) as u64 * (BLOCKS.len() as u64 - 1) / range) as usize])
+        .collect()
+}
+
+/// E.g. `CPU: 62 °C, limit 95 °C`, `None` if neither is reported.
+/// Formats a temperature in the unit, e.g. `65 °C`.
+fn format_temp(celsius: f32, unit: TemperatureUnit) -> String {
+    match unit {
+        TemperatureUnit::Celsius => format!("{:.0} °C", celsius),
+        TemperatureUnit::Fahrenheit => format!("{:.0} °F", celsius * 9.0 / 5.0 + 32.0),
+    }
+}
+
+fn format_temperature(model: &TdpModel, unit: TemperatureUnit) -> Option<String> {
+    let format = |celsius| format_temp(celsius, unit);
+    match (model.temperature, model.temperature_limit) {
+        (Some(t), Some(limit)) => Some(format!("CPU: {}, limit {}", format(t), format(limit))),
+        (Some(t), None) => Some(format!("CPU: {}", format(t))),
+        (None, Some(limit)) => Some(format!("CPU temperature limit: {}", format(limit))),
+        (None, None) => None,
+    }
+}
+
+fn format_throttle_status(status: &ThrottleStatus) -> Option<String> {
+    if !status.is_throttling() {
+        return None;
+    }
+    let mut reasons = vec![];
+    if status.thermal {
+        reasons.push("temperature");
+    }
+    if status.skin_temperature {
+        reasons.push("skin temperature");
+    }
+    if status.current {
+        reasons.push("current");
+    }
+    Some(format!("Throttling: {}", reasons.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::main_window::model::PopupMenuModel;
+
+    #[test]
+    fn unapplied_limits_are_named() {
+        let limits = TdpLimits {
+            fast: 15000,
+            slow: 15300,
+            stapm: 25000,
+        };
+        assert_eq!(
+            format_unapplied(&limits, 15000).as_deref(),
+            Some("Not at 15000 mW: STAPM")
+        );
+        assert_eq!(format_unapplied(&TdpLimits::all(15000), 15000), None);
+    }
+
+    #[test]
+    fn sparkline_spans_the_range() {
+        assert_eq!(format_sparkline(&[]), "");
+        assert_eq!(format_sparkline(&[15000, 15000]), "▁▁");
+        assert_eq!(format_sparkline(&[5000, 12000, 19000, 15000]), "▁▄█▆");
+    }
+
+    #[test]
+    fn gauge_turns_red_near_the_limit() {
+        assert_eq!(gauge_color(0.5), Color::GREEN);
+        assert_eq!(gauge_color(0.8), Color::YELLOW);
+        assert_eq!(gauge_color(0.95), Color::RED);
+        assert_eq!(gauge_color(1.2), Color::RED);
+    }
+
+    #[test]
+    fn rate_magnitude_fades_to_red() {
+        let scheme = ChargeColorScheme::RateMagnitude;
+        assert_eq!(charge_icon_color(scheme, 0, 5000), Color::GREEN);
+        assert_eq!(charge_icon_color(scheme, 0, 0), Color::GREEN);
+        assert_eq!(
+            charge_icon_color(scheme, 0, -15000),
+            Color::from_rgb(0xFF, 128, 128)
+        );
+        assert_eq!(
+            charge_icon_color(scheme, 0, -45000),
+            Color::from_rgb(0xFF, 0, 0)
+        );
+    }
+
+    #[test]
+    fn idle_band_is_neutral() {
+        let scheme = ChargeColorScheme::Classic;
+        assert_eq!(charge_icon_color(scheme, 300, 10), IDLE_CHARGE_COLOR);
+        assert_eq!(charge_icon_color(scheme, 300, -299), IDLE_CHARGE_COLOR);
+        assert_eq!(charge_icon_color(scheme, 300, 300), Color::GREEN);
+        assert_eq!(charge_icon_color(scheme, 300, -300), Color::WHITE);
+    }
+
+    #[test]
+    fn charge_face_formatting() {
+        assert_eq!(format_charge_face(0), "0.0");
+        assert_eq!(format_charge_face(99), "0.0");
+        assert_eq!(format_charge_face(1250), "1.2");
+        assert_eq!(format_charge_face(-1250), "1.2");
+        assert_eq!(format_charge_face(9999), "9.9");
+        assert_eq!(format_charge_face(-9999), "9.9");
+        assert_eq!(format_charge_face(10000), "10");
+        assert_eq!(format_charge_face(-10999), "10");
+        assert_eq!(format_charge_face(123456), "123");
+        assert_eq!(format_charge_face(i32::MIN), "2147483");
+    }
+
+    #[test]
+    fn tooltip_lines_that_do_not_fit_are_dropped() {
+        let line = "x".repeat(60);
+        assert_eq!(
+            fit_tooltip([line.clone(), line.clone(), line.clone()]),
+            format!("{}\n{}", line, line)
+        );
+        let tip = fit_tooltip(["😀".repeat(100)]);
+        assert_eq!(tip.encode_utf16().count(), MAX_TOOLTIP_LEN);
+        assert!(tip.ends_with("😀…"));
+    }
+
+    #[test]
+    fn temperatures_are_converted() {
+        assert_eq!(format_temp(64.6, TemperatureUnit::Celsius), "65 °C");
+        assert_eq!(format_temp(100.0, TemperatureUnit::Fahrenheit), "212 °F");
+        assert_eq!(format_temp(-40.0, TemperatureUnit::Fahrenheit), "-40 °F");
+    }
+
+    #[test]
+    fn boost_counts_down() {
+        let boost = |remaining_secs| TdpState::Boosting {
+            remaining_secs,
+            fallback: None,
+        };
+        // A different remaining time is a different state, so the icon is not bypassed
+        assert_ne!(boost(Some(60)), boost(Some(59)));
+        assert_eq!(
+            format_tdp_state(boost(Some(60)), 30000),
+            ("Boost: 30000 mW, 00:01:00 left".to_string(), Color::GREEN)
+        );
+        assert_eq!(
+            format_tdp_state(boost(Some(59)), 30000),
+            ("Boost: 30000 mW, 00:00:59 left".to_string(), Color::GREEN)
+        );
+    }
+
+    #[test]
+    fn identical_models_are_not_rendered() {
+        let gdi_plus = GdiPlus::new();
+        // SAFETY: None of the models below contain icons, so the window handle is never used
+        let mut view = unsafe { View::new(HWND::default(), &gdi_plus) };
+
+        assert!(!view.update(&Model::default()));
+
+        let model = Model {
+            popup_menu: Some(PopupMenuModel {
+                x: 0,
+                y: 0,
+                menu: PopupMenuType::ChargeIcon,
+            }),
+            ..Default::default()
+        };
+        assert!(view.update(&model));
+        assert!(!view.update(&model.clone()));
+    }
 }
@@ -1,9 +1,9 @@
 use windows::core::{Error, Owned, PCWSTR};
 use windows::Win32::Foundation::HWND;
 use windows::Win32::UI::WindowsAndMessaging::{
-    AppendMenuW, CheckMenuItem, CreatePopupMenu, SetForegroundWindow, TrackPopupMenu, HMENU,
-    MF_BYCOMMAND, MF_CHECKED, MF_ENABLED, MF_POPUP, MF_SEPARATOR, MF_STRING, MF_UNCHECKED,
-    TPM_LEFTBUTTON,
+    AppendMenuW, CheckMenuItem, CreatePopupMenu, EnableMenuItem, SetForegroundWindow,
+    TrackPopupMenu, HMENU, MF_BYCOMMAND, MF_CHECKED, MF_ENABLED, MF_GRAYED, MF_POPUP, MF_SEPARATOR,
+    MF_STRING, MF_UNCHECKED, TPM_LEFTBUTTON,
 };
 
 pub struct PopupMenu {
@@ -37,6 +37,16 @@ impl PopupMenu {
         unsafe { AppendMenuW(*self.handle, flags, id as usize, PCWSTR(buf.as_ptr())).unwrap() };
     }
 
+    /// Appends an informational menu item that does not trigger any command.
+    /// Items with `available` set to `false` are grayed out.
+    pub fn append_info_item(&mut self, title: &str, available: bool) {
+        let mut buf: Vec<u16> = title.encode_utf16().collect();
+        buf.push(0); // null-terminate
+        let flags = MF_STRING | if available { MF_ENABLED } else { MF_GRAYED };
+        // SAFETY: Menu handle is owned by `self` and stays valid until drop
+        unsafe { AppendMenuW(*self.handle, flags, 0, PCWSTR(buf.as_ptr())).unwrap() };
+    }
+
     /// Appends a submenu to the menu, taking ownership of the `PopupMenu` instance
     pub fn append_submenu(&mut self, title: &str, menu: PopupMenu) {
         let mut buf: Vec<u16> = title.encode_utf16().collect();
@@ -50,15 +60,29 @@ impl PopupMenu {
 
     /// Tries to set the checked state of a menu item and returns the previous state.
     /// `true` means checked, and `false` means unchecked.
+    /// Returns `None` if the menu item does not exist.
     pub fn check_menu_item(&mut self, id: u32, checked: bool) -> Option<bool> {
         let flags = MF_BYCOMMAND | if checked { MF_CHECKED } else { MF_UNCHECKED };
         // SAFETY: Menu handle is owned by `self` and stays valid until drop
         let result = unsafe { CheckMenuItem(*self.handle, id, flags.0) };
         match result {
-            r if r == MF_CHECKED.0 => Some(false),
-            r if r == MF_UNCHECKED.0 => Some(true),
+            r if r == MF_CHECKED.0 => Some(true),
+            r if r == MF_UNCHECKED.0 => Some(false),
             u32::MAX => None,
-            r => panic!("Unexpected response from CheckMenuItem: {}", r),
+            r => {
+                error!("Unexpected response from CheckMenuItem: {}", r);
+                None
+            }
+        }
+    }
+
+    /// Grays out a menu item, or enables it back. Items in submenus are found as well.
+    pub fn enable_menu_item(&mut self, id: u32, enabled: bool) {
+        let flags = MF_BYCOMMAND | if enabled { MF_ENABLED } else { MF_GRAYED };
+        // SAFETY: Menu handle is owned by `self` and stays valid until drop
+        let result = unsafe { EnableMenuItem(*self.handle, id, flags) };
+        if result.0 == -1 {
+            debug!("Menu item {} does not exist", id);
         }
     }
 
@@ -0,0 +1,153 @@
+use std::fmt::Display;
+use std::fs::{rename, File};
+use std::io::{Error as IoError, Write};
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use windows::Win32::Foundation::SYSTEMTIME;
+use windows::Win32::Storage::FileSystem::FILE_SHARE_READ;
+
+#[cfg(not(test))]
+use crate::winapi::get_local_time;
+
+const HEADER: &str = "timestamp,tdp_measured,tdp_target,state,charge_rate,percent,temp,fps\n";
+/// Once the file grows beyond this size, it is moved aside and a new one is started.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Metrics measured during a single poll. Unavailable values are left empty in the file.
+#[derive(Default)]
+pub struct MetricsRow {
+    pub tdp_measured: Option<u32>,
+    pub tdp_target: Option<u32>,
+    pub state: Option<&'static str>,
+    pub charge_rate: Option<i32>,
+    pub percent: Option<u32>,
+    pub temp: Option<f32>,
+    pub fps: Option<f32>,
+}
+
+/// Appends a CSV row per poll for offline analysis of a session.
+pub struct MetricsLog {
+    path: PathBuf,
+    /// `None` only while the file is being rotated.
+    file: Option<File>,
+    size: u64,
+}
+
+fn format_value<T: Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn format_row(time: &SYSTEMTIME, row: &MetricsRow) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02},{},{},{},{},{},{},{}\n",
+        time.wYear,
+        time.wMonth,
+        time.wDay,
+        time.wHour,
+        time.wMinute,
+        time.wSecond,
+        format_value(row.tdp_measured),
+        format_value(row.tdp_target),
+        format_value(row.state),
+        format_value(row.charge_rate),
+        format_value(row.percent),
+        format_value(row.temp.map(|t| format!("{:.1}", t))),
+        format_value(row.fps.map(|f| format!("{:.1}", f))),
+    )
+}
+
+impl MetricsLog {
+    fn open_file(path: &Path) -> Result<(File, u64), IoError> {
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .share_mode(FILE_SHARE_READ.0)
+            .open(path)?;
+        let mut size = file.metadata()?.len();
+        if size == 0 {
+            file.write_all(HEADER.as_bytes())?;
+            size = HEADER.len() as u64;
+        }
+        Ok((file, size))
+    }
+
+    pub fn open(path: &Path) -> Result<Self, IoError> {
+        let (file, size) = Self::open_file(path)?;
+        info!("Writing metrics to {}", path.display());
+        Ok(MetricsLog {
+            path: PathBuf::from(path),
+            file: Some(file),
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> Result<(), IoError> {
+        let mut old_path = self.path.clone().into_os_string();
+        old_path.push(".old");
+        debug!("Moving metrics log to {}", old_path.to_string_lossy());
+        // The file is opened with FILE_SHARE_READ only, so it has to be closed before renaming
+        self.file = None;
+        rename(&self.path, &old_path)?;
+        let (file, size) = Self::open_file(&self.path)?;
+        self.file = Some(file);
+        self.size = size;
+        Ok(())
+    }
+
+    pub fn append(&mut self, row: &MetricsRow) -> Result<(), IoError> {
+        if self.size > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        let Some(file) = &mut self.file else {
+            // A previous rotation failed halfway
+            return Ok(());
+        };
+        let line = format_row(&get_local_time(), row);
+        file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Drop for MetricsLog {
+    fn drop(&mut self) {
+        if let Some(Err(err)) = self.file.as_ref().map(File::sync_data) {
+            error!("Failed to flush metrics log to disk: {}", err);
+        }
+        debug!("Metrics log closed");
+    }
+}
+
+#[cfg(test)]
+fn get_local_time() -> SYSTEMTIME {
+    SYSTEMTIME {
+        wYear: 2025,
+        wMonth: 5,
+        wDayOfWeek: 0,
+        wDay: 10,
+        wHour: 23,
+        wMinute: 15,
+        wSecond: 46,
+        wMilliseconds: 788,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_values_are_left_empty() {
+        let row = MetricsRow {
+            tdp_measured: Some(15000),
+            state: Some("tracking"),
+            charge_rate: Some(-12345),
+            fps: Some(59.94),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_row(&get_local_time(), &row),
+            "2025-05-10T23:15:46,15000,,tracking,-12345,,,59.9\n"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use crate::rtss::{strip_tags, Span};
+use crate::winapi::colors::{color_from_rgb, COLOR_BLACK, COLOR_WHITE};
+use crate::winapi::{get_instance_handle, AcquiredDC, PaintContext};
+use std::cmp::{max, min};
+use windows::core::{w, Error, Owned, PCWSTR};
+use windows::Win32::Foundation::{
+    COLORREF, ERROR_CLASS_ALREADY_EXISTS, HWND, LPARAM, LRESULT, RECT, WPARAM,
+};
+use windows::Win32::Graphics::Gdi::{
+    CreateCompatibleBitmap, CreateFontW, CreateSolidBrush, GetStockObject, InvalidateRect,
+    BLACK_BRUSH, CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, DEFAULT_PITCH, FW_BOLD,
+    HBITMAP, HBRUSH, HFONT, OUT_DEFAULT_PRECIS,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    AdjustWindowRectEx, CreateWindowExW, DefWindowProcW, DestroyWindow, GetWindowLongPtrW,
+    PostMessageW, RegisterClassExW, SetForegroundWindow, SetWindowLongPtrW, SetWindowPos,
+    ShowWindow, CREATESTRUCTW, CW_USEDEFAULT, GWLP_USERDATA, SWP_NOACTIVATE, SWP_NOMOVE,
+    SWP_NOZORDER, SW_SHOWNORMAL, WM_CLOSE, WM_COMMAND, WM_NCCREATE, WM_PAINT, WNDCLASSEXW,
+    WS_CAPTION, WS_EX_TOPMOST, WS_OVERLAPPED, WS_SYSMENU,
+};
+
+const CLASS_NAME: PCWSTR = w!("OverlayPreview");
+const MAX_WIDTH: i32 = 800;
+const MAX_HEIGHT: i32 = 600;
+const PADDING: i32 = 8;
+const FONT_HEIGHT: i32 = 24;
+/// Width of the graph placeholders, matching the number of samples in the RTSS graphs.
+const GRAPH_WIDTH: i32 = 50;
+const GRAPH_COLOR: COLORREF = color_from_rgb(0x40, 0x40, 0x40);
+
+/// Part of the preview that the window procedure needs access to.
+struct State {
+    owner: HWND,
+    close_command: u32,
+    bitmap: Option<Owned<HBITMAP>>,
+    width: i32,
+    height: i32,
+}
+
+/// Window that approximates how the overlay looks in RTSS, to help with writing templates.
+/// RTSS-specific positioning is ignored, and graphs are shown as placeholders.
+pub struct OverlayPreview {
+    window: HWND,
+    state: Box<State>,
+    font: Owned<HFONT>,
+    small_font: Owned<HFONT>,
+}
+
+fn create_font(height: i32) -> Owned<HFONT> {
+    // SAFETY: All arguments are constants, the face name is a static string
+    let font = unsafe {
+        CreateFontW(
+            -height,
+            0,
+            0,
+            0,
+            FW_BOLD.0 as _,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET.0 as _,
+            OUT_DEFAULT_PRECIS.0 as _,
+            CLIP_DEFAULT_PRECIS.0 as _,
+            CLEARTYPE_QUALITY.0 as _,
+            DEFAULT_PITCH.0 as _,
+            w!("Segoe UI"),
+        )
+    };
+    if font.is_invalid() {
+        panic!("Failed to create the overlay preview font");
+    }
+    // SAFETY: We own the returned handle
+    unsafe { Owned::new(font) }
+}
+
+/// Converts `0xRRGGBB` used by RTSS.
+fn color_from_rtss(color: u32) -> COLORREF {
+    color_from_rgb((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+impl OverlayPreview {
+    /// Creates the window. Closing it posts `WM_COMMAND` with `close_command` to the owner,
+    /// which is expected to drop the preview.
+    ///
+    /// # Safety
+    ///
+    /// The owner window handle should stay valid for the entire lifetime of the returned instance.
+    pub unsafe fn new(owner: HWND, close_command: u32) -> Result<Self, Error> {
+        let class = WNDCLASSEXW {
+            cbSize: size_of::<WNDCLASSEXW>() as u32,
+            lpfnWndProc: Some(Self::wnd_proc),
+            hInstance: get_instance_handle(),
+            // SAFETY: Stock objects are always valid
+            hbrBackground: HBRUSH(unsafe { GetStockObject(BLACK_BRUSH) }.0),
+            lpszClassName: CLASS_NAME,
+            ..Default::default()
+        };
+        // SAFETY: Argument points to a valid structure that outlives the call
+        if unsafe { RegisterClassExW(&class) } == 0 {
+            let err = Error::from_win32();
+            // The class stays registered after the first preview is closed
+            if err.code() != ERROR_CLASS_ALREADY_EXISTS.to_hresult() {
+                return Err(err);
+            }
+        }
+        let mut state = Box::new(State {
+            owner,
+            close_command,
+            bitmap: None,
+            width: 0,
+            height: 0,
+        });
+        // SAFETY: The state is boxed, so it does not move and outlives the window
+        let window = unsafe {
+            CreateWindowExW(
+                WS_EX_TOPMOST,
+                CLASS_NAME,
+                w!("Overlay preview"),
+                WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                None,
+                None,
+                get_instance_handle(),
+                Some(state.as_mut() as *mut _ as _),
+            )
+        }?;
+        // SAFETY: The window handle is valid
+        unsafe {
+            _ = ShowWindow(window, SW_SHOWNORMAL);
+            _ = SetForegroundWindow(window);
+        }
+        Ok(OverlayPreview {
+            window,
+            state,
+            font: create_font(FONT_HEIGHT),
+            small_font: create_font(FONT_HEIGHT / 2),
+        })
+    }
+
+    /// Renders the spans and resizes the window to fit them.
+    pub fn update(&mut self, spans: &[Span]) {
+        let screen = AcquiredDC::new();
+        // SAFETY: The screen DC is valid
+        let bitmap = unsafe { CreateCompatibleBitmap(*screen, MAX_WIDTH, MAX_HEIGHT) };
+        if bitmap.is_invalid() {
+            error!("Failed to create the overlay preview bitmap");
+            return;
+        }
+        // SAFETY: We own the returned handle
+        let bitmap = unsafe { Owned::new(bitmap) };
+        let (width, height) = {
+            // SAFETY: The bitmap is valid and is only selected into this context
+            let mut pc = unsafe { PaintContext::for_bitmap(*bitmap) };
+            self.render(&mut pc, spans)
+        };
+        self.state.bitmap = Some(bitmap);
+        if (width, height) != (self.state.width, self.state.height) {
+            self.state.width = width;
+            self.state.height = height;
+            self.resize(width, height);
+        }
+        // SAFETY: The window handle is valid
+        unsafe { _ = InvalidateRect(self.window, None, false) };
+    }
+
+    /// Draws the spans from the top left corner, returns the size they take.
+    fn render(&self, pc: &mut PaintContext, spans: &[Span]) -> (i32, i32) {
+        // SAFETY: The call is always sound, and we own the brush
+        let graph_brush = unsafe { Owned::new(CreateSolidBrush(GRAPH_COLOR)) };
+        // SAFETY: Stock objects are always valid
+        let background = HBRUSH(unsafe { GetStockObject(BLACK_BRUSH) }.0);
+        pc.fill_rect(
+            &RECT {
+                left: 0,
+                top: 0,
+                right: MAX_WIDTH,
+                bottom: MAX_HEIGHT,
+            },
+            background,
+        );
+        pc.set_bg_color(COLOR_BLACK);
+        pc.set_font(&self.font);
+        let line_height = pc.measure_text("Ag").cy;
+        let (mut x, mut y) = (PADDING, PADDING);
+        let mut width = x;
+        let draw = |pc: &mut PaintContext, x: &mut i32, y: i32, text: &str, small, color| {
+            if text.is_empty() {
+                return;
+            }
+            pc.set_font(if small { &self.small_font } else { &self.font });
+            pc.set_text_color(color);
+            let size = pc.measure_text(text);
+            // Small text sits on the same baseline
+            pc.draw_text(text, *x, y + line_height - size.cy);
+            *x += size.cx;
+        };
+        for span in spans {
+            match span {
+                Span::Raw(text) => {
+                    let text = strip_tags(text);
+                    for (i, line) in text.split('\n').enumerate() {
+                        if i > 0 {
+                            x = PADDING;
+                            y += line_height;
+                        }
+                        draw(
+                            pc,
+                            &mut x,
+                            y,
+                            line.trim_end_matches('\r'),
+                            false,
+                            COLOR_WHITE,
+                        );
+                    }
+                }
+                Span::Text { text, small, color } => {
+                    let color = color.map_or(COLOR_WHITE, color_from_rtss);
+                    draw(pc, &mut x, y, text, *small, color);
+                }
+                Span::Framerate(fps) => {
+                    let text = fps.map_or(String::from("-"), |fps| format!("{fps:.0}"));
+                    draw(pc, &mut x, y, &text, false, COLOR_WHITE);
+                }
+                Span::Graph(_) => {
+                    let top = y + line_height / 4;
+                    let rect = RECT {
+                        left: x,
+                        top,
+                        right: x + GRAPH_WIDTH,
+                        bottom: top + line_height / 2,
+                    };
+                    pc.fill_rect(&rect, *graph_brush);
+                    x += GRAPH_WIDTH + PADDING;
+                }
+                Span::Newline => {
+                    x = PADDING;
+                    y += line_height;
+                }
+            }
+            width = max(width, x);
+        }
+        (
+            min(width + PADDING, MAX_WIDTH),
+            min(y + line_height + PADDING, MAX_HEIGHT),
+        )
+    }
+
+    fn resize(&self, width: i32, height: i32) {
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        };
+        let style = WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU;
+        // SAFETY: The provided pointer references a local structure
+        if let Err(err) = unsafe { AdjustWindowRectEx(&mut rect, style, false, WS_EX_TOPMOST) } {
+            warn!("Failed to compute the overlay preview size: {}", err);
+            return;
+        }
+        // SAFETY: The window handle is valid
+        let result = unsafe {
+            SetWindowPos(
+                self.window,
+                None,
+                0,
+                0,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+        };
+        if let Err(err) = result {
+            warn!("Failed to resize the overlay preview: {}", err);
+        }
+    }
+
+    extern "system" fn wnd_proc(
+        window: HWND,
+        message: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        if message == WM_NCCREATE {
+            // SAFETY: We trust the OS to provide us with a valid pointer
+            let cs = unsafe { &*(l_param.0 as *const CREATESTRUCTW) };
+            // SAFETY: The function is sound as long as the input is valid
+            unsafe { SetWindowLongPtrW(window, GWLP_USERDATA, cs.lpCreateParams as _) };
+        } else {
+            // SAFETY: The function is sound as long as the input is valid
+            let user_data = unsafe { GetWindowLongPtrW(window, GWLP_USERDATA) };
+            // SAFETY: The state outlives the window and is not mutated while messages are handled
+            if let Some(state) = unsafe { (user_data as *const State).as_ref() } {
+                match message {
+                    WM_PAINT => {
+                        // SAFETY: We are handling WM_PAINT
+                        let mut pc = unsafe { PaintContext::for_window(window) };
+                        if let Some(bitmap) = &state.bitmap {
+                            // SAFETY: The bitmap is owned by the state and not selected anywhere
+                            unsafe { pc.draw_bitmap(**bitmap, state.width, state.height) };
+                        }
+                        return LRESULT(0);
+                    }
+                    WM_CLOSE => {
+                        // The owner drops the preview, which destroys the window
+                        let w_param = WPARAM(state.close_command as _);
+                        // SAFETY: The owner window is valid while the preview exists
+                        if let Err(err) =
+                            unsafe { PostMessageW(state.owner, WM_COMMAND, w_param, None) }
+                        {
+                            error!("Failed to close the overlay preview: {}", err);
+                        }
+                        return LRESULT(0);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // SAFETY: We are in the context of message processor,
+        //   validity of the arguments is guaranteed by the caller (OS)
+        unsafe { DefWindowProcW(window, message, w_param, l_param) }
+    }
+}
+
+impl Drop for OverlayPreview {
+    fn drop(&mut self) {
+        // SAFETY: The window is owned by us and has not been destroyed yet
+        if let Err(err) = unsafe { DestroyWindow(self.window) } {
+            error!("Failed to destroy the overlay preview: {}", err);
+        }
+    }
+}
@@ -1,18 +1,59 @@
 mod bindings;
+mod layout;
 mod shared_memory;
+mod snippet;
+mod template;
 
 use crate::battery::BatteryStatus;
-use crate::winapi::get_local_time;
-use shared_memory::{open_shared_memory, EmbeddedGraph, SharedMemoryBuilder, SharedMemoryView};
+use crate::settings::{OverlayGraphSettings, OverlaySettings};
+use shared_memory::{with_shared_memory, EmbeddedGraph, GraphFlags, SharedMemoryBuilder};
 use std::fmt::{Debug, Display, Formatter};
+
+pub use layout::{strip_tags, GraphKind, Span};
+pub use snippet::{format_layout_snippet, parse_layout_snippet};
+use template::{Element, Token, DEFAULT_TEMPLATE};
 use windows::core::Error as WindowsError;
+use windows::Win32::Foundation::SYSTEMTIME;
 
 pub struct Rtss {
     battery_graph: EmbeddedGraph,
+    /// Highest discharge rate in W seen so far, rounded up to `BATTERY_GRAPH_STEP`.
+    battery_graph_peak: u32,
     fps_graph: EmbeddedGraph,
+    template: String,
+    tokens: Vec<Token>,
     ever_updated: bool,
+    /// OSD frame id of the last update, see `SharedMemoryView::for_each_entry`.
+    last_osd_frame: Option<u32>,
+    /// OSD slot we are registered in, checked on every update.
+    osd_slot: Option<usize>,
+}
+
+/// Values rendered in the overlay. They are gathered by the caller,
+/// so the overlay does not need to know where they come from.
+pub struct OverlayMetrics {
+    /// `None` if battery elements are hidden.
+    pub battery: Option<BatteryStatus>,
+    /// TDP limit in milliwatts.
+    pub tdp_limit: Option<u32>,
+    pub fps: Option<f32>,
+    pub time: SYSTEMTIME,
+    /// Per-application TDP profile, if one is being applied.
+    pub profile: Option<ActiveProfile>,
+}
+
+pub struct ActiveProfile {
+    pub application: String,
+    /// TDP limit in milliwatts.
+    pub tdp_limit: u32,
 }
 
+/// Longer application names are truncated in the profile line.
+const MAX_PROFILE_NAME_LEN: usize = 16;
+/// Granularity of the auto-scaled battery graph range in W.
+/// Keeps the range from creeping up with every slightly higher sample.
+const BATTERY_GRAPH_STEP: u32 = 5;
+
 pub enum Error {
     RtssV2NotRunning,
     RtssVersionNotSupported(String),
@@ -44,49 +85,245 @@ impl Display for Error {
 }
 
 impl Rtss {
-    pub fn new() -> Rtss {
+    /// `battery_graph_peak` is the value of `get_battery_graph_peak` from a previous session.
+    pub fn new(battery_graph_peak: Option<u32>) -> Rtss {
         Rtss {
             battery_graph: EmbeddedGraph::new(50, 15, -45.0, 0.0),
+            battery_graph_peak: battery_graph_peak.unwrap_or(0),
             fps_graph: EmbeddedGraph::new(50, 15, 0.0, 60.0),
+            template: String::from(DEFAULT_TEMPLATE),
+            tokens: template::parse(DEFAULT_TEMPLATE),
             ever_updated: false,
+            last_osd_frame: None,
+            osd_slot: None,
+        }
+    }
+
+    fn set_template(&mut self, template: &str) {
+        if self.template != template {
+            trace!("Parsing new overlay template");
+            self.template = String::from(template);
+            self.tokens = template::parse(template);
         }
     }
 
-    pub fn update(&mut self, battery: &BatteryStatus) -> Result<(), Error> {
-        let mem = open_shared_memory()?;
-        let mut view = SharedMemoryView::from_file(&mem)?;
+    /// Reads the framerate of the foreground application as measured by RTSS.
+    /// Returns `None` if the sample had to be discarded.
+    pub fn read_fps() -> Result<Option<f32>, Error> {
+        with_shared_memory(|view| view.get_fps())
+    }
+
+    /// Records a battery sample in the graph.
+    /// Battery is polled less often than the overlay is updated, so this is done separately.
+    pub fn push_battery(&mut self, battery: &BatteryStatus) {
         self.battery_graph
             .push((battery.charge_rate as f32) / 1000.0);
-        self.fps_graph.push(view.get_fps()?);
+        let discharge = battery.charge_rate.min(0).unsigned_abs().div_ceil(1000);
+        if discharge > self.battery_graph_peak {
+            self.battery_graph_peak = discharge.next_multiple_of(BATTERY_GRAPH_STEP);
+        }
+    }
+
+    /// Returns the range in W the battery graph uses when it is auto-scaled.
+    pub fn get_battery_graph_peak(&self) -> u32 {
+        self.battery_graph_peak
+    }
+
+    /// Renders the metrics according to the settings and writes them into the RTSS shared memory.
+    pub fn update(
+        &mut self,
+        metrics: &OverlayMetrics,
+        settings: &OverlaySettings,
+    ) -> Result<(), Error> {
+        if let Some(fps) = metrics.fps {
+            self.fps_graph.push(fps);
+        }
+        let spans = self.layout(metrics, settings);
         let mut builder = SharedMemoryBuilder::new();
-        builder.add_graph(&self.battery_graph);
-        builder.add_text(&format!(
-            "{}.{:03}<S=50>W<S>",
+        for span in &spans {
+            self.add_span(&mut builder, span);
+        }
+        let (slot, frame) = with_shared_memory(|view| builder.write(view, self.osd_slot))?;
+        self.osd_slot = Some(slot);
+        if let Some(last) = self
+            .last_osd_frame
+            .filter(|&last| !osd_frame_advanced(last, frame))
+        {
+            // Only clients change the frame id, so it can only go back if the shared memory
+            // has been created anew
+            warn!(
+                "RTSS OSD frame id went back from {} to {}, RTSS has likely been restarted",
+                last, frame
+            );
+        }
+        self.last_osd_frame = Some(frame);
+        self.ever_updated = true;
+        Ok(())
+    }
+
+    /// Lays out the metrics according to the settings, without writing them anywhere.
+    pub fn layout(&mut self, metrics: &OverlayMetrics, settings: &OverlaySettings) -> Vec<Span> {
+        self.set_template(settings.template.as_deref().unwrap_or(DEFAULT_TEMPLATE));
+        let watts = settings
+            .battery_graph_watts
+            .unwrap_or(self.battery_graph_peak.max(BATTERY_GRAPH_STEP));
+        self.battery_graph.set_range(-(watts as f32), 0.0);
+        Self::configure_graph(&mut self.battery_graph, &settings.battery_graph);
+        self.fps_graph.set_range(0.0, settings.fps_graph_max as f32);
+        Self::configure_graph(&mut self.fps_graph, &settings.fps_graph);
+        let mut spans = vec![];
+        // Every element starts on its own line, while literal text continues the current one
+        let mut line_empty = true;
+        for token in &self.tokens {
+            match token {
+                Token::Text(text) => {
+                    spans.push(Span::Raw(text.clone()));
+                }
+                Token::Element(Element::Battery) if metrics.battery.is_none() => continue,
+                Token::Element(Element::Profile) if metrics.profile.is_none() => continue,
+                Token::Element(element) => {
+                    if !line_empty {
+                        spans.push(Span::Newline);
+                    }
+                    match element {
+                        Element::Battery => {
+                            if let Some(battery) = &metrics.battery {
+                                Self::add_battery(&mut spans, battery, settings);
+                            }
+                        }
+                        Element::Fps => {
+                            let time = settings.show_clock.then_some(&metrics.time);
+                            Self::add_fps(&mut spans, metrics.fps, time);
+                        }
+                        Element::Tdp => Self::add_tdp(&mut spans, metrics.tdp_limit),
+                        Element::Profile => {
+                            if let Some(profile) = &metrics.profile {
+                                Self::add_profile(&mut spans, profile);
+                            }
+                        }
+                        Element::Gap => {}
+                    }
+                }
+            }
+            line_empty = false;
+        }
+        spans
+    }
+
+    fn configure_graph(graph: &mut EmbeddedGraph, settings: &OverlayGraphSettings) {
+        graph.set_size(settings.width, settings.height);
+        graph.set_flags(GraphFlags::parse(
+            settings.style.as_deref().unwrap_or_default(),
+        ));
+    }
+
+    fn add_span(&self, builder: &mut SharedMemoryBuilder, span: &Span) {
+        match span {
+            Span::Raw(text) => {
+                builder.add_text(text);
+            }
+            Span::Text { text, small, color } => {
+                let text = if *small {
+                    format!("<S=50>{text}<S>")
+                } else {
+                    text.clone()
+                };
+                match color {
+                    Some(color) => builder.add_colored_text(&text, *color),
+                    None => builder.add_text(&text),
+                };
+            }
+            Span::Graph(GraphKind::Battery) => {
+                builder.add_graph(&self.battery_graph);
+            }
+            Span::Graph(GraphKind::Fps) => {
+                builder.add_graph(&self.fps_graph);
+            }
+            Span::Framerate(_) => {
+                builder.add_text("<FR>");
+            }
+            Span::Newline => {
+                builder.add_newline();
+            }
+        }
+    }
+
+    fn add_battery(spans: &mut Vec<Span>, battery: &BatteryStatus, settings: &OverlaySettings) {
+        spans.push(Span::Graph(GraphKind::Battery));
+        let rate = format!(
+            "{}.{:03}",
             battery.charge_rate / 1000,
             (battery.charge_rate % 1000).abs()
-        ));
+        );
         if battery.charge_rate < 0 {
             // draining
             let mins = (-60.0 * (battery.capacity as f64 / battery.charge_rate as f64)) as i64;
-            builder.add_text(&format!("  {mins}<S=50>mins<S>"));
+            let time_left = [
+                Span::text(rate),
+                Span::small("W"),
+                Span::text(format!("  {mins}")),
+                Span::small("mins"),
+            ];
+            if mins < settings.low_battery_minutes as i64 {
+                let color = settings.low_battery_color;
+                spans.extend(time_left.map(|s| s.colored(color)));
+            } else {
+                spans.extend(time_left);
+            }
         } else {
-            builder.add_text("  (on charger)");
+            let color = settings.charging_color;
+            spans.extend([
+                Span::text(rate).colored(color),
+                Span::small("W").colored(color),
+                Span::text("  (on charger)"),
+            ]);
         }
-        let time = get_local_time();
-        builder
-            .add_newline()
-            .add_graph(&self.fps_graph)
-            .add_text("<FR><S=50>FPS<S>")
-            .add_text(&format!("  {:02}:{:02}", time.wHour, time.wMinute))
-            .write(&mut view)?;
-        self.ever_updated = true;
-        Ok(())
+    }
+
+    fn add_fps(spans: &mut Vec<Span>, fps: Option<f32>, time: Option<&SYSTEMTIME>) {
+        spans.extend([
+            Span::Graph(GraphKind::Fps),
+            Span::Framerate(fps),
+            Span::small("FPS"),
+        ]);
+        if let Some(time) = time {
+            spans.push(Span::text(format!(
+                "  {:02}:{:02}",
+                time.wHour, time.wMinute
+            )));
+        }
+    }
+
+    fn add_tdp(spans: &mut Vec<Span>, tdp_limit: Option<u32>) {
+        let limit = match tdp_limit {
+            Some(limit) => format!("{}.{:01}", limit / 1000, (limit / 100) % 10),
+            None => String::from("-"),
+        };
+        spans.extend([Span::text(limit), Span::small("W TDP")]);
+    }
+
+    fn add_profile(spans: &mut Vec<Span>, profile: &ActiveProfile) {
+        let mut name: String = profile
+            .application
+            .chars()
+            .take(MAX_PROFILE_NAME_LEN)
+            .collect();
+        if name.len() < profile.application.len() {
+            name.push('…');
+        }
+        spans.extend([
+            Span::text(format!(
+                "Profile: {} {}.{:01}",
+                name,
+                profile.tdp_limit / 1000,
+                (profile.tdp_limit / 100) % 10
+            )),
+            Span::small("W"),
+        ]);
     }
 
     fn unregister(&mut self) -> Result<(), Error> {
-        let mem = open_shared_memory()?;
-        let mut view = SharedMemoryView::from_file(&mem)?;
-        view.unregister()
+        with_shared_memory(|view| view.unregister())
     }
 }
 
@@ -103,3 +340,61 @@ impl Drop for Rtss {
         }
     }
 }
+
+/// Whether the OSD frame id has moved forward since the last update, allowing for wrapping.
+/// Other clients bump it as well, so it might have moved by more than one.
+fn osd_frame_advanced(last: u32, current: u32) -> bool {
+    let delta = current.wrapping_sub(last);
+    delta != 0 && delta <= u32::MAX / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battery(charge_rate: i32) -> BatteryStatus {
+        BatteryStatus {
+            charge_rate,
+            capacity: 50_000,
+            os_runtime_secs: None,
+            percent: None,
+            voltage: None,
+        }
+    }
+
+    #[test]
+    fn battery_is_omitted_without_battery() {
+        let mut rtss = Rtss::new(None);
+        let metrics = OverlayMetrics {
+            battery: None,
+            tdp_limit: Some(15_000),
+            fps: Some(60.0),
+            time: SYSTEMTIME::default(),
+            profile: None,
+        };
+        let spans = rtss.layout(&metrics, &OverlaySettings::default());
+        assert!(!spans.contains(&Span::Graph(GraphKind::Battery)));
+        assert!(spans.contains(&Span::Graph(GraphKind::Fps)));
+    }
+
+    #[test]
+    fn battery_graph_peak_grows_in_steps() {
+        let mut rtss = Rtss::new(Some(10));
+        rtss.push_battery(&battery(-8_000));
+        rtss.push_battery(&battery(20_000));
+        assert_eq!(rtss.get_battery_graph_peak(), 10);
+        rtss.push_battery(&battery(-10_001));
+        assert_eq!(rtss.get_battery_graph_peak(), 15);
+        rtss.push_battery(&battery(-14_500));
+        assert_eq!(rtss.get_battery_graph_peak(), 15);
+    }
+
+    #[test]
+    fn osd_frame_advance_allows_wrapping() {
+        assert!(osd_frame_advanced(5, 6));
+        assert!(osd_frame_advanced(5, 9));
+        assert!(osd_frame_advanced(u32::MAX, 1));
+        assert!(!osd_frame_advanced(5, 5));
+        assert!(!osd_frame_advanced(5, 1));
+    }
+}
@@ -0,0 +1,76 @@
+/// Graph embedded into the overlay.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GraphKind {
+    Battery,
+    Fps,
+}
+
+/// Piece of the overlay, independent of how it ends up being displayed.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Span {
+    /// Literal text from the template, which may contain RTSS tags.
+    Raw(String),
+    Text {
+        text: String,
+        /// Shown at half the size, e.g. for units.
+        small: bool,
+        /// Color in `0xRRGGBB` format, default if `None`.
+        color: Option<u32>,
+    },
+    Graph(GraphKind),
+    /// Framerate of the foreground application. RTSS fills it in, the value is only for previews.
+    Framerate(Option<f32>),
+    Newline,
+}
+
+impl Span {
+    pub fn text(text: impl Into<String>) -> Self {
+        Span::Text {
+            text: text.into(),
+            small: false,
+            color: None,
+        }
+    }
+
+    pub fn small(text: impl Into<String>) -> Self {
+        Span::Text {
+            text: text.into(),
+            small: true,
+            color: None,
+        }
+    }
+
+    /// Sets the color of a text span. Other spans are returned unchanged.
+    pub fn colored(mut self, new_color: u32) -> Self {
+        if let Span::Text { color, .. } = &mut self {
+            *color = Some(new_color);
+        }
+        self
+    }
+}
+
+/// Removes RTSS tags (e.g. `<S=50>`), so the text can be displayed elsewhere.
+pub fn strip_tags(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_stripped() {
+        assert_eq!(strip_tags("<S=50>W<S> <C=FF0000>low<C>"), "W low");
+        assert_eq!(strip_tags("a < b"), "a < b");
+    }
+}
@@ -3,9 +3,11 @@ use super::Error;
 use std::borrow::Cow;
 use std::cmp::min;
 use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
-use std::ptr::slice_from_raw_parts;
+use std::ops::{BitOr, Deref, DerefMut};
+use std::ptr::{addr_of, read_volatile, slice_from_raw_parts};
 use std::sync::atomic::Ordering;
+use std::thread::sleep;
+use std::time::Duration;
 use windows::core::{w, Error as WindowsError, Owned};
 use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, HANDLE};
 use windows::Win32::System::Memory::{
@@ -15,6 +17,10 @@ use windows::Win32::System::Memory::{
 
 const RTSS_MIN_SUPPORTED_VERSION: u32 = 0x0002000e; // v2.14 is the lowest to support OSD locking
 const OWNER_SIGNATURE: &str = "LilPowerMan";
+const MAP_ATTEMPTS: u32 = 2;
+const MAP_RETRY_DELAY: Duration = Duration::from_millis(20);
+/// Framerates above this are treated as torn reads rather than actual measurements.
+const MAX_PLAUSIBLE_FPS: f32 = 10_000.0;
 
 struct SharedMemoryGuard<'parent> {
     mem: &'parent mut RtssSharedMemory,
@@ -23,7 +29,7 @@ struct SharedMemoryGuard<'parent> {
 impl<'parent> SharedMemoryGuard<'parent> {
     fn new(view: &'parent mut SharedMemoryView) -> Self {
         // SAFETY: We validated that view.addr points to a valid instance of RtssSharedMemory
-        let mem = unsafe { &mut *(view.view.addr.Value as *mut RtssSharedMemory) };
+        let mem = unsafe { &mut *(view.addr.Value as *mut RtssSharedMemory) };
         while mem
             .busy
             .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
@@ -51,7 +57,8 @@ impl<'parent> DerefMut for SharedMemoryGuard<'parent> {
 
 impl<'parent> Drop for SharedMemoryGuard<'parent> {
     fn drop(&mut self) {
-        self.mem.busy.store(0, Ordering::Relaxed);
+        // Pairs with the Acquire above, so the next writer sees everything written under the lock
+        self.mem.busy.store(0, Ordering::Release);
     }
 }
 
@@ -68,6 +75,28 @@ pub fn open_shared_memory() -> Result<Owned<HANDLE>, Error> {
     })
 }
 
+/// Maps the RTSS shared memory and passes the view to `f`.
+/// Mapping can fail momentarily while RTSS recreates the shared memory, e.g. when it restarts,
+/// so it is retried with a freshly opened handle. Layout and version errors are not retried.
+pub fn with_shared_memory<T>(
+    f: impl FnOnce(&mut SharedMemoryView) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut attempt = 1;
+    loop {
+        let mem = open_shared_memory()?;
+        let view = SharedMemoryView::from_file(&mem);
+        match view {
+            Ok(mut view) => return f(&mut view),
+            Err(Error::WindowsError(err)) if attempt < MAP_ATTEMPTS => {
+                debug!("Failed to map RTSS shared memory, retrying: {}", err);
+                attempt += 1;
+                sleep(MAP_RETRY_DELAY);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 struct OwnedMemoryMapView<'mem> {
     addr: MEMORY_MAPPED_VIEW_ADDRESS,
     _mem: PhantomData<&'mem Owned<HANDLE>>,
@@ -84,7 +113,9 @@ impl<'mem> Drop for OwnedMemoryMapView<'mem> {
 }
 
 pub struct SharedMemoryView<'mem> {
-    view: OwnedMemoryMapView<'mem>,
+    addr: MEMORY_MAPPED_VIEW_ADDRESS,
+    /// Unmaps the memory when dropped, `None` if the memory is not a mapped file.
+    _view: Option<OwnedMemoryMapView<'mem>>,
     size: usize,
 }
 
@@ -166,12 +197,31 @@ impl<'mem> SharedMemoryView<'mem> {
         }
         debug!("RTSS version: {version}");
         // SAFETY: It is safe to use addr as a pointer to RtssSharedMemory
-        Ok(SharedMemoryView { view, size })
+        Ok(SharedMemoryView {
+            addr,
+            _view: Some(view),
+            size,
+        })
     }
 
-    pub fn get_fps(&self) -> Result<f32, Error> {
+    /// Creates a view over an in-process buffer that imitates the RTSS shared memory.
+    #[cfg(test)]
+    fn from_buffer(buffer: &'mem mut [u32]) -> Self {
+        let size = size_of_val(buffer);
+        assert!(size >= size_of::<RtssSharedMemory>());
+        SharedMemoryView {
+            addr: MEMORY_MAPPED_VIEW_ADDRESS {
+                Value: buffer.as_mut_ptr() as *mut _,
+            },
+            _view: None,
+            size,
+        }
+    }
+
+    /// Returns `None` if RTSS was updating the foreground application's entry while it was read.
+    pub fn get_fps(&self) -> Result<Option<f32>, Error> {
         // SAFETY: We verified that `view` is a valid RtssSharedMemory instance in `from_file`
-        let mem = unsafe { &*(self.view.addr.Value as *const RtssSharedMemory) };
+        let mem = unsafe { &*(self.addr.Value as *const RtssSharedMemory) };
         if mem.signature != RTSS_SIGNATURE {
             return Err(Error::RtssV2NotRunning);
         }
@@ -185,7 +235,7 @@ impl<'mem> SharedMemoryView<'mem> {
             return Err(Error::UnexpectedMemoryLayout);
         }
         let pid = get_fg_application_pid().map_err(Error::WindowsError)?;
-        let base_addr = self.view.addr.Value as usize;
+        let base_addr = self.addr.Value as usize;
         let map_view_size = self.size;
         let n = mem.osd_arr_size as usize;
         for i in 0..n {
@@ -197,30 +247,48 @@ impl<'mem> SharedMemoryView<'mem> {
             }
             let entry_addr = base_addr + mem.app_arr_offset as usize + i * entry_size;
             // SAFETY: entry_addr points to a complete AppEntry, entirely within the mapped file
-            let entry = unsafe { &mut *(entry_addr as *mut RtssSharedMemoryAppEntry) };
+            let entry = unsafe { &*(entry_addr as *const RtssSharedMemoryAppEntry) };
             if entry.process_id == pid {
-                let time0 = entry.time0;
-                let time1 = entry.time1;
-                return Ok(if time1 == time0 {
-                    0.0
-                } else {
-                    1000.0 * (entry.frames as f32) / (time1 - time0) as f32
-                });
+                // App entries are not covered by the OSD lock, so RTSS can write them concurrently.
+                // Take a snapshot of every field once and validate the snapshot instead.
+                // SAFETY: The pointers are derived from a reference to a live entry
+                let (frames, time0, time1) = unsafe {
+                    (
+                        read_volatile(addr_of!(entry.frames)),
+                        read_volatile(addr_of!(entry.time0)),
+                        read_volatile(addr_of!(entry.time1)),
+                    )
+                };
+                let fps = framerate(frames, time0, time1);
+                if fps.is_none() {
+                    debug!(
+                        "Skipping inconsistent RTSS framerate sample: {} frames in {}..{} ms",
+                        frames, time0, time1
+                    );
+                }
+                return Ok(fps);
             }
         }
-        Ok(0.0)
+        Ok(Some(0.0))
     }
 
     fn lock(&mut self) -> SharedMemoryGuard {
         SharedMemoryGuard::new(self)
     }
 
-    fn for_each_entry<D, F>(&mut self, process: D, finalize: F) -> Result<(), Error>
+    /// Visits the OSD entries under the lock, then bumps the global OSD frame id.
+    ///
+    /// RTSS redraws the OSD of the running 3D applications only when the frame id changes,
+    /// and it never changes the id itself: every client increments it after writing its
+    /// entry. The increment is done while still holding the lock, with Release ordering,
+    /// so RTSS never sees the new id without the entry contents that go with it.
+    /// Returns the new frame id.
+    fn for_each_entry<D, F>(&mut self, process: D, finalize: F) -> Result<u32, Error>
     where
         D: Fn(usize, &mut RtssSharedMemoryOsdEntry) -> SharedMemoryIterationNextStep,
         F: FnOnce(Option<(usize, &mut RtssSharedMemoryOsdEntry)>) -> Result<(), Error>,
     {
-        let base_addr = self.view.addr.Value as usize;
+        let base_addr = self.addr.Value as usize;
         let map_view_size = self.size;
         let mem = self.lock();
         if mem.signature != RTSS_SIGNATURE {
@@ -263,8 +331,8 @@ impl<'mem> SharedMemoryView<'mem> {
             }
         }
         finalize(remembered_entry)?;
-        mem.osd_frame.fetch_add(1, Ordering::Release);
-        Ok(())
+        let frame = mem.osd_frame.fetch_add(1, Ordering::Release);
+        Ok(frame.wrapping_add(1))
     }
 
     pub fn unregister(&mut self) -> Result<(), Error> {
@@ -272,29 +340,35 @@ impl<'mem> SharedMemoryView<'mem> {
             |i, entry| {
                 let owner = string_from_mem(&entry.osd_owner);
                 if owner == OWNER_SIGNATURE {
-                    // SAFETY: entry points to a piece of shared memory we own
-                    unsafe { std::ptr::write_bytes(entry, 0, size_of_val(&entry)) };
-                    info!("Unregistered ourselves from slot {i}");
                     trace!(
-                        "Erased {} bytes at address 0x{:016X}",
-                        size_of_val(&entry),
+                        "Erasing {} bytes at address 0x{:016X}",
+                        size_of_val(entry),
                         entry as *const _ as usize
                     );
+                    // SAFETY: entry points to a single OSD entry in the shared memory we own
+                    unsafe { std::ptr::write_bytes(entry as *mut RtssSharedMemoryOsdEntry, 0, 1) };
+                    info!("Unregistered ourselves from slot {i}");
                 }
                 Continue
             },
             |_| Ok(()),
         )
+        .map(|_| ())
     }
 
-    fn update<F>(&mut self, f: F) -> Result<(), Error>
+    /// Updates the slot that carries our owner signature, registering in an empty one if there
+    /// is none. `slot` is where we were registered before, if anywhere; if it has been cleared,
+    /// it is taken back. Returns our slot and the OSD frame id after the update.
+    fn update<F>(&mut self, slot: Option<usize>, f: F) -> Result<(usize, u32), Error>
     where
         F: FnOnce(&mut RtssSharedMemoryOsdEntry) -> Result<(), Error>,
     {
-        self.for_each_entry(
-            |_i, entry| {
+        let mut registered = None;
+        let frame = self.for_each_entry(
+            |i, entry| {
                 let current_owner = string_from_mem(&entry.osd_owner);
-                if current_owner == OWNER_SIGNATURE {
+                if current_owner == OWNER_SIGNATURE || (current_owner.is_empty() && slot == Some(i))
+                {
                     RememberAndBreak
                 } else if current_owner == "" {
                     RememberIfNeededAndContinue
@@ -308,11 +382,22 @@ impl<'mem> SharedMemoryView<'mem> {
                 };
                 let current_owner = string_from_mem(&target_entry.osd_owner);
                 if current_owner != OWNER_SIGNATURE {
+                    if let Some(slot) = slot {
+                        warn!("Our RTSS OSD slot {slot} has been reclaimed, registering again");
+                    }
+                    // Whatever the previous owner left would mix with our text and graphs
+                    // SAFETY: entry points to a single OSD entry in the shared memory we own
+                    unsafe {
+                        std::ptr::write_bytes(target_entry as *mut RtssSharedMemoryOsdEntry, 0, 1)
+                    };
                     info!("Registered ourselves in slot {target_idx}");
                 }
+                registered = Some(target_idx);
                 f(target_entry)
             },
-        )
+        )?;
+        // The finalizer always runs before the frame id is returned
+        Ok((registered.unwrap_or_default(), frame))
     }
 }
 
@@ -334,6 +419,11 @@ impl SharedMemoryBuilder {
         self
     }
 
+    /// Adds text wrapped into RTSS color tags. The color is `0xRRGGBB`.
+    pub fn add_colored_text(&mut self, text: &str, color: u32) -> &mut Self {
+        self.add_text(&format!("<C={:06X}>{text}<C>", color & 0xFFFFFF))
+    }
+
     pub fn add_newline(&mut self) -> &mut Self {
         self.add_text("\r\n")
     }
@@ -346,8 +436,14 @@ impl SharedMemoryBuilder {
         self
     }
 
-    pub fn write(&self, view: &mut SharedMemoryView) -> Result<(), Error> {
-        view.update(|entry| {
+    /// Writes the OSD into our slot, see `SharedMemoryView::update`.
+    /// Returns our slot and the OSD frame id that makes RTSS redraw it.
+    pub fn write(
+        &self,
+        view: &mut SharedMemoryView,
+        slot: Option<usize>,
+    ) -> Result<(usize, u32), Error> {
+        view.update(slot, |entry| {
             if !string_to_mem(OWNER_SIGNATURE, &mut entry.osd_owner)
                 || !string_to_mem(&self.osd, &mut entry.osd_ex)
                 || !slice_to_mem(&self.buffer, &mut entry.buffer)
@@ -360,6 +456,61 @@ impl SharedMemoryBuilder {
     }
 }
 
+/// Appearance of an embedded graph, a subset of `RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_*`.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct GraphFlags(u32);
+
+impl GraphFlags {
+    pub const FILLED: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_FILLED);
+    pub const BAR: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_BAR);
+    pub const BACKGROUND: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_BGND);
+    pub const VERTICAL: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_VERTICAL);
+    pub const MIRRORED: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_MIRRORED);
+    pub const AUTOSCALE: Self = Self(RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_AUTOSCALE);
+
+    const NAMES: [(&'static str, Self); 6] = [
+        ("filled", Self::FILLED),
+        ("bar", Self::BAR),
+        ("background", Self::BACKGROUND),
+        ("vertical", Self::VERTICAL),
+        ("mirrored", Self::MIRRORED),
+        ("autoscale", Self::AUTOSCALE),
+    ];
+
+    /// Parses a comma-separated list of flag names, e.g. `filled,mirrored`.
+    /// Unknown names are ignored.
+    pub fn parse(text: &str) -> Self {
+        text.split(',')
+            .map(str::trim)
+            .filter_map(|name| {
+                Self::NAMES
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|&(_, flag)| flag)
+            })
+            .fold(Self::default(), BitOr::bitor)
+    }
+
+    /// Whether `parse` recognizes a single flag name.
+    pub fn is_known(name: &str) -> bool {
+        Self::NAMES
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for GraphFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
 pub struct EmbeddedGraph {
     core: RtssEmbeddedObjectGraph,
     data: Vec<f32>,
@@ -388,6 +539,29 @@ impl EmbeddedGraph {
         }
     }
 
+    pub fn set_range(&mut self, min: f32, max: f32) {
+        self.core.min = min;
+        self.core.max = max;
+    }
+
+    /// Changing the width starts the graph over, since every sample takes one pixel.
+    pub fn set_size(&mut self, width: u16, height: u16) {
+        let len = width as usize;
+        if len != self.data.len() {
+            self.core.header.size =
+                (size_of::<RtssEmbeddedObjectGraph>() + len * size_of::<f32>()) as u32;
+            self.core.header.width = width as i32;
+            self.core.data_count = len as u32;
+            self.data = vec![0.0; len];
+            self.data_ptr = 0;
+        }
+        self.core.header.height = height as i32;
+    }
+
+    pub fn set_flags(&mut self, flags: GraphFlags) {
+        self.core.flags = flags.bits();
+    }
+
     pub fn push(&mut self, value: f32) {
         self.data[self.data_ptr] = value;
         self.data_ptr = (self.data_ptr + 1) % self.data.len();
@@ -412,3 +586,347 @@ impl EmbeddedGraph {
         [core_chunk, head_chunk, tail_chunk]
     }
 }
+
+/// Computes the framerate from the timing fields of an app entry.
+/// Returns `None` if the fields are inconsistent with each other.
+fn framerate(frames: u32, time0: u32, time1: u32) -> Option<f32> {
+    if time1 == time0 {
+        // Nothing has been measured yet
+        return if frames == 0 { Some(0.0) } else { None };
+    }
+    if time1 < time0 {
+        return None;
+    }
+    let fps = 1000.0 * (frames as f32) / (time1 - time0) as f32;
+    if fps > MAX_PLAUSIBLE_FPS {
+        return None;
+    }
+    Some(fps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn graph_flags_are_parsed() {
+        assert_eq!(GraphFlags::parse(""), GraphFlags::default());
+        assert_eq!(
+            GraphFlags::parse("filled, Mirrored,unknown"),
+            GraphFlags::FILLED | GraphFlags::MIRRORED
+        );
+        assert_eq!(
+            GraphFlags::parse("bar").bits(),
+            RTSS_EMBEDDED_OBJECT_GRAPH_FLAG_BAR
+        );
+    }
+
+    #[test]
+    fn graph_offsets_follow_insertion_order() {
+        let battery_graph = EmbeddedGraph::new(50, 15, -45.0, 0.0);
+        let fps_graph = EmbeddedGraph::new(20, 15, 0.0, 60.0);
+        let battery_size = battery_graph.core.header.size as usize;
+        let fps_size = fps_graph.core.header.size as usize;
+
+        let mut builder = SharedMemoryBuilder::new();
+        builder
+            .add_graph(&fps_graph)
+            .add_newline()
+            .add_graph(&battery_graph);
+
+        assert_eq!(
+            builder.osd,
+            format!("<OBJ=00000000>\r\n<OBJ={:08X}>", fps_size)
+        );
+        assert_eq!(builder.buffer.len(), fps_size + battery_size);
+        assert_eq!(
+            builder.buffer[fps_size..fps_size + 4],
+            RTSS_EMBEDDED_OBJECT_GRAPH_SIGNATURE
+        );
+    }
+
+    #[test]
+    fn colored_text_reaches_osd_intact() {
+        let mut builder = SharedMemoryBuilder::new();
+        builder
+            .add_colored_text("1.500", 0x00FF00)
+            .add_text(" ")
+            .add_colored_text("low", 0x12FF0000);
+        let mut osd_ex = [0xAAu8; 64];
+
+        assert!(string_to_mem(&builder.osd, &mut osd_ex));
+        assert_eq!(
+            string_from_mem(&osd_ex),
+            "<C=00FF00>1.500<C> <C=FF0000>low<C>"
+        );
+    }
+
+    const OSD_SLOTS: usize = 4;
+
+    /// Allocates a fake RTSS shared memory with `OSD_SLOTS` empty OSD entries.
+    fn fake_shared_memory() -> Vec<u32> {
+        let header_size = size_of::<RtssSharedMemory>();
+        let entry_size = size_of::<RtssSharedMemoryOsdEntry>();
+        let size = header_size + OSD_SLOTS * entry_size;
+        let mut buffer = vec![0u32; size.div_ceil(size_of::<u32>())];
+        // SAFETY: The buffer is large enough and suitably aligned for the header
+        let mem = unsafe { &mut *(buffer.as_mut_ptr() as *mut RtssSharedMemory) };
+        mem.signature = RTSS_SIGNATURE;
+        mem.version = RTSS_MIN_SUPPORTED_VERSION;
+        mem.osd_entry_size = entry_size as u32;
+        mem.osd_arr_offset = header_size as u32;
+        mem.osd_arr_size = OSD_SLOTS as u32;
+        buffer
+    }
+
+    fn osd_entry(buffer: &mut [u32], i: usize) -> &mut RtssSharedMemoryOsdEntry {
+        let offset = size_of::<RtssSharedMemory>() + i * size_of::<RtssSharedMemoryOsdEntry>();
+        assert!(offset + size_of::<RtssSharedMemoryOsdEntry>() <= size_of_val(buffer));
+        // SAFETY: The entry lies entirely within the buffer, and byte arrays have no alignment
+        unsafe {
+            &mut *((buffer.as_mut_ptr() as *mut u8).add(offset) as *mut RtssSharedMemoryOsdEntry)
+        }
+    }
+
+    fn osd_frame(buffer: &[u32]) -> u32 {
+        // SAFETY: The buffer was created by `fake_shared_memory`
+        let mem = unsafe { &*(buffer.as_ptr() as *const RtssSharedMemory) };
+        mem.osd_frame.load(Ordering::Relaxed)
+    }
+
+    fn header(buffer: &mut [u32]) -> &mut RtssSharedMemory {
+        // SAFETY: The buffer was created by `fake_shared_memory`
+        unsafe { &mut *(buffer.as_mut_ptr() as *mut RtssSharedMemory) }
+    }
+
+    /// Visits every OSD entry, asserting that each one lies entirely within the buffer.
+    /// Returns the visited slot numbers.
+    fn visit_all_entries(buffer: &mut [u32]) -> Result<Vec<usize>, Error> {
+        let range = buffer.as_ptr_range();
+        let (start, end) = (range.start as usize, range.end as usize);
+        let visited = RefCell::new(vec![]);
+        SharedMemoryView::from_buffer(buffer).for_each_entry(
+            |i, entry| {
+                let addr = entry as *mut RtssSharedMemoryOsdEntry as usize;
+                assert!(addr >= start && addr + size_of_val(entry) <= end);
+                visited.borrow_mut().push(i);
+                Continue
+            },
+            |_| Ok(()),
+        )?;
+        Ok(visited.into_inner())
+    }
+
+    #[test]
+    fn valid_layout_visits_every_slot_but_the_first() {
+        let mut buffer = fake_shared_memory();
+        assert_eq!(visit_all_entries(&mut buffer).unwrap(), vec![1, 2, 3]);
+        assert_eq!(osd_frame(&buffer), 1);
+    }
+
+    #[test]
+    fn signature_mismatch_is_rejected() {
+        let mut buffer = fake_shared_memory();
+        header(&mut buffer).signature = *b"\xAD\xDE\0\0";
+        let result = visit_all_entries(&mut buffer);
+        assert!(matches!(result, Err(Error::RtssV2NotRunning)));
+        assert_eq!(osd_frame(&buffer), 0);
+    }
+
+    #[test]
+    fn small_entry_size_is_rejected() {
+        let mut buffer = fake_shared_memory();
+        header(&mut buffer).osd_entry_size = size_of::<RtssSharedMemoryOsdEntry>() as u32 - 1;
+        let result = visit_all_entries(&mut buffer);
+        assert!(matches!(result, Err(Error::UnexpectedMemoryLayout)));
+        assert_eq!(osd_frame(&buffer), 0);
+    }
+
+    #[test]
+    fn array_offset_near_the_end_is_rejected() {
+        let mut buffer = fake_shared_memory();
+        let size = size_of_val(buffer.as_slice());
+        let entry_size = size_of::<RtssSharedMemoryOsdEntry>();
+        // Slot 0 fits exactly, slot 1 would end one entry past the buffer
+        header(&mut buffer).osd_arr_offset = (size - entry_size) as u32;
+        let result = visit_all_entries(&mut buffer);
+        assert!(matches!(result, Err(Error::UnexpectedMemoryLayout)));
+        assert_eq!(osd_frame(&buffer), 0);
+    }
+
+    #[test]
+    fn huge_array_size_stops_at_the_end_of_the_buffer() {
+        let mut buffer = fake_shared_memory();
+        header(&mut buffer).osd_arr_size = u32::MAX;
+        let result = visit_all_entries(&mut buffer);
+        assert!(matches!(result, Err(Error::UnexpectedMemoryLayout)));
+        assert_eq!(osd_frame(&buffer), 0);
+    }
+
+    #[test]
+    fn random_layouts_stay_in_bounds() {
+        let mut rng = fastrand::Rng::with_seed(0x5254_5353);
+        let entry_size = size_of::<RtssSharedMemoryOsdEntry>() as u32;
+        for _ in 0..1000 {
+            let mut buffer = fake_shared_memory();
+            let size = size_of_val(buffer.as_slice()) as u32;
+            let mem = header(&mut buffer);
+            mem.osd_arr_offset = rng.u32(0..=size);
+            mem.osd_entry_size = rng.u32(entry_size - 16..=entry_size * 2);
+            mem.osd_arr_size = if rng.bool() {
+                u32::MAX
+            } else {
+                rng.u32(0..=OSD_SLOTS as u32 * 2)
+            };
+            let arr_size = mem.osd_arr_size as usize;
+            // Every visited entry is checked against the buffer bounds
+            if let Ok(visited) = visit_all_entries(&mut buffer) {
+                assert!(visited.iter().all(|&i| i < arr_size));
+            }
+        }
+    }
+
+    #[test]
+    fn write_takes_first_free_slot_and_keeps_it() {
+        let mut buffer = fake_shared_memory();
+        string_to_mem("SomeoneElse", &mut osd_entry(&mut buffer, 1).osd_owner);
+        let mut builder = SharedMemoryBuilder::new();
+        builder.add_text("first");
+
+        builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), None)
+            .unwrap();
+        // Slot 0 is reserved by RTSS, slot 1 belongs to another application
+        assert_eq!(
+            string_from_mem(&osd_entry(&mut buffer, 2).osd_owner),
+            OWNER_SIGNATURE
+        );
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 2).osd_ex), "first");
+        assert_eq!(osd_frame(&buffer), 1);
+
+        // Free up an earlier slot, our own slot should still be preferred
+        osd_entry(&mut buffer, 1).osd_owner[0] = 0;
+        builder.add_text(" second");
+        builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), None)
+            .unwrap();
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 1).osd_owner), "");
+        assert_eq!(
+            string_from_mem(&osd_entry(&mut buffer, 2).osd_ex),
+            "first second"
+        );
+        assert_eq!(osd_frame(&buffer), 2);
+    }
+
+    #[test]
+    fn reclaimed_slot_is_registered_again_cleanly() {
+        let mut buffer = fake_shared_memory();
+        let mut builder = SharedMemoryBuilder::new();
+        builder.add_text("ours");
+        let (slot, _) = builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), None)
+            .unwrap();
+        assert_eq!(slot, 1);
+
+        // Another tool takes our slot over and leaves its text in the next one
+        string_to_mem("SomeoneElse", &mut osd_entry(&mut buffer, 1).osd_owner);
+        string_to_mem("stale text", &mut osd_entry(&mut buffer, 2).osd);
+        let (slot, _) = builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), Some(slot))
+            .unwrap();
+        assert_eq!(slot, 2);
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 2).osd), "");
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 2).osd_ex), "ours");
+
+        // A cleared slot is taken back, even if an earlier one is free
+        osd_entry(&mut buffer, 1).osd_owner[0] = 0;
+        osd_entry(&mut buffer, 2).osd_owner[0] = 0;
+        let (slot, _) = builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), Some(slot))
+            .unwrap();
+        assert_eq!(slot, 2);
+        assert_eq!(
+            string_from_mem(&osd_entry(&mut buffer, 2).osd_owner),
+            OWNER_SIGNATURE
+        );
+    }
+
+    #[test]
+    fn write_fails_without_free_slots() {
+        let mut buffer = fake_shared_memory();
+        for i in 1..OSD_SLOTS {
+            string_to_mem("SomeoneElse", &mut osd_entry(&mut buffer, i).osd_owner);
+        }
+        let result =
+            SharedMemoryBuilder::new().write(&mut SharedMemoryView::from_buffer(&mut buffer), None);
+        assert!(matches!(result, Err(Error::NoEmptyOsdSlots)));
+    }
+
+    #[test]
+    fn write_reports_overflow() {
+        let mut buffer = fake_shared_memory();
+        let mut builder = SharedMemoryBuilder::new();
+        builder.add_text(&"x".repeat(4096));
+        let result = builder.write(&mut SharedMemoryView::from_buffer(&mut buffer), None);
+        assert!(matches!(result, Err(Error::EntryOverflow)));
+    }
+
+    #[test]
+    fn graph_is_serialized_into_entry_buffer() {
+        let mut buffer = fake_shared_memory();
+        let mut graph = EmbeddedGraph::new(4, 15, 0.0, 60.0);
+        graph.push(1.0);
+        graph.push(2.0);
+        let mut builder = SharedMemoryBuilder::new();
+        builder.add_graph(&graph);
+
+        builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), None)
+            .unwrap();
+
+        let entry = osd_entry(&mut buffer, 1);
+        assert_eq!(string_from_mem(&entry.osd_ex), "<OBJ=00000000>");
+        let header_size = size_of::<RtssEmbeddedObjectGraph>();
+        let data: Vec<f32> = entry.buffer[header_size..header_size + 4 * size_of::<f32>()]
+            .chunks(size_of::<f32>())
+            .map(|c| f32::from_ne_bytes(c.try_into().unwrap()))
+            .collect();
+        // Oldest samples come first
+        assert_eq!(data, vec![0.0, 0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn unregister_erases_only_own_slot() {
+        let mut buffer = fake_shared_memory();
+        let mut builder = SharedMemoryBuilder::new();
+        builder.add_text("ours");
+        builder
+            .write(&mut SharedMemoryView::from_buffer(&mut buffer), None)
+            .unwrap();
+        string_to_mem("SomeoneElse", &mut osd_entry(&mut buffer, 2).osd_owner);
+
+        SharedMemoryView::from_buffer(&mut buffer)
+            .unregister()
+            .unwrap();
+
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 1).osd_owner), "");
+        assert_eq!(string_from_mem(&osd_entry(&mut buffer, 1).osd_ex), "");
+        assert_eq!(
+            string_from_mem(&osd_entry(&mut buffer, 2).osd_owner),
+            "SomeoneElse"
+        );
+    }
+
+    #[test]
+    fn inconsistent_frame_timings_are_skipped() {
+        assert_eq!(framerate(0, 500, 500), Some(0.0));
+        assert_eq!(framerate(60, 1000, 2000), Some(60.0));
+        // `frames` was updated, but the period was not yet
+        assert_eq!(framerate(60, 2000, 2000), None);
+        // `time0` was advanced past the old `time1`
+        assert_eq!(framerate(60, 3000, 2000), None);
+        // `frames` belongs to a much longer period than the one read
+        assert_eq!(framerate(60_000, 1000, 1001), None);
+    }
+}
@@ -0,0 +1,216 @@
+use super::shared_memory::GraphFlags;
+use super::template;
+use crate::settings::{OverlayGraphSettings, OverlaySettings, MAX_OVERLAY_GRAPH_SIZE};
+
+/// First line of every snippet, so that unrelated clipboard text is not taken for one.
+const HEADER: &str = "[LilPowerMan overlay]";
+
+/// Writes the presentation part of the overlay settings as `key=value` lines
+/// that can be pasted into a forum post and read back by `parse_layout_snippet`.
+/// The update rate is left out, since it depends on the machine rather than on the layout.
+pub fn format_layout_snippet(settings: &OverlaySettings) -> String {
+    let mut lines = vec![String::from(HEADER)];
+    let template = settings.template.as_deref().unwrap_or_default();
+    lines.push(format!("template={}", escape(template)));
+    lines.push(format!("charging_color={:06X}", settings.charging_color));
+    lines.push(format!(
+        "low_battery_color={:06X}",
+        settings.low_battery_color
+    ));
+    lines.push(format!(
+        "low_battery_minutes={}",
+        settings.low_battery_minutes
+    ));
+    lines.push(match settings.battery_graph_watts {
+        Some(watts) => format!("battery_graph_watts={}", watts),
+        None => String::from("battery_graph_watts=auto"),
+    });
+    lines.push(format!("show_clock={}", settings.show_clock as u32));
+    format_graph(&mut lines, "battery_graph", &settings.battery_graph);
+    format_graph(&mut lines, "fps_graph", &settings.fps_graph);
+    lines.push(format!("fps_graph_max={}", settings.fps_graph_max));
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+fn format_graph(lines: &mut Vec<String>, name: &str, graph: &OverlayGraphSettings) {
+    lines.push(format!("{}_size={}x{}", name, graph.width, graph.height));
+    let style = graph.style.as_deref().unwrap_or_default();
+    lines.push(format!("{}_style={}", name, style));
+}
+
+/// Reads a snippet written by `format_layout_snippet` on top of the current settings.
+/// Keys that are missing keep their current values. Nothing is applied if any line
+/// is invalid, all the problems are returned instead.
+pub fn parse_layout_snippet(
+    text: &str,
+    current: &OverlaySettings,
+) -> Result<OverlaySettings, Vec<String>> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some(HEADER) {
+        return Err(vec![String::from("Text is not an overlay layout")]);
+    }
+    let mut settings = current.clone();
+    let mut issues = vec![];
+    for line in lines {
+        let Some((key, value)) = line.split_once('=') else {
+            issues.push(format!("Line is not a setting: {}", line));
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let result = match key {
+            "template" => {
+                let template = unescape(value);
+                let template_issues = template::validate(&template);
+                if template_issues.is_empty() {
+                    settings.template = (!template.is_empty()).then_some(template);
+                    Ok(())
+                } else {
+                    Err(template_issues.join(", "))
+                }
+            }
+            "charging_color" => parse_color(value).map(|c| settings.charging_color = c),
+            "low_battery_color" => parse_color(value).map(|c| settings.low_battery_color = c),
+            "low_battery_minutes" => parse_number(value).map(|m| settings.low_battery_minutes = m),
+            "battery_graph_watts" if value == "auto" => {
+                settings.battery_graph_watts = None;
+                Ok(())
+            }
+            "battery_graph_watts" => {
+                parse_number(value).map(|w| settings.battery_graph_watts = Some(w))
+            }
+            "show_clock" => match value {
+                "0" | "1" => {
+                    settings.show_clock = value == "1";
+                    Ok(())
+                }
+                _ => Err(String::from("expected 0 or 1")),
+            },
+            "battery_graph_size" => parse_size(value, &mut settings.battery_graph),
+            "battery_graph_style" => parse_style(value, &mut settings.battery_graph),
+            "fps_graph_size" => parse_size(value, &mut settings.fps_graph),
+            "fps_graph_style" => parse_style(value, &mut settings.fps_graph),
+            "fps_graph_max" => match parse_number(value) {
+                Ok(0) => Err(String::from("must not be 0")),
+                result => result.map(|m| settings.fps_graph_max = m),
+            },
+            _ => Err(String::from("unknown setting")),
+        };
+        if let Err(issue) = result {
+            issues.push(format!("{}: {}", key, issue));
+        }
+    }
+    if issues.is_empty() {
+        Ok(settings)
+    } else {
+        Err(issues)
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))
+}
+
+fn parse_color(value: &str) -> Result<u32, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(color),
+        _ => Err(format!("{} is not an RRGGBB color", value)),
+    }
+}
+
+fn parse_size(value: &str, graph: &mut OverlayGraphSettings) -> Result<(), String> {
+    let error = || {
+        format!(
+            "{} is not a size from 1x1 to {max}x{max}",
+            value,
+            max = MAX_OVERLAY_GRAPH_SIZE
+        )
+    };
+    let (width, height) = value.split_once('x').ok_or_else(error)?;
+    let parse = |x: &str| match x.trim().parse() {
+        Ok(x @ 1..=MAX_OVERLAY_GRAPH_SIZE) => Ok(x as u16),
+        _ => Err(error()),
+    };
+    graph.width = parse(width)?;
+    graph.height = parse(height)?;
+    Ok(())
+}
+
+fn parse_style(value: &str, graph: &mut OverlayGraphSettings) -> Result<(), String> {
+    let unknown: Vec<_> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !GraphFlags::is_known(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("unknown flags {}", unknown.join(", ")));
+    }
+    graph.style = (!value.is_empty()).then(|| String::from(value));
+    Ok(())
+}
+
+/// Keeps multi-line templates on a single line of the snippet.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let settings = OverlaySettings {
+            template: Some(String::from("<C=FF00FF>{fps}\\\n{battery}")),
+            battery_graph_watts: None,
+            fps_graph: OverlayGraphSettings {
+                width: 120,
+                height: 15,
+                style: Some(String::from("filled,mirrored")),
+            },
+            ..OverlaySettings::default()
+        };
+        let snippet = format_layout_snippet(&settings);
+        let parsed = parse_layout_snippet(&snippet, &OverlaySettings::default());
+        assert!(parsed.is_ok_and(|p| p == settings));
+    }
+
+    #[test]
+    fn invalid_lines_are_reported() {
+        let snippet = "[LilPowerMan overlay]\ntemplate={fps}{nope}\ncharging_color=green\n\
+            fps_graph_size=600x10\nfps_graph_style=bar,wavy\nshow_clock=0\n";
+        let issues = parse_layout_snippet(snippet, &OverlaySettings::default()).err();
+        assert_eq!(
+            issues,
+            Some(vec![
+                String::from("template: Unknown placeholder {nope}"),
+                String::from("charging_color: green is not an RRGGBB color"),
+                String::from("fps_graph_size: 600x10 is not a size from 1x1 to 500x500"),
+                String::from("fps_graph_style: unknown flags wavy"),
+            ])
+        );
+    }
+}
@@ -0,0 +1,157 @@
+/// Template used when the settings do not provide one.
+pub const DEFAULT_TEMPLATE: &str = "{battery}{fps}";
+
+/// Overlay element that occupies its own line in the OSD.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Element {
+    /// Battery charge rate graph, followed by the rate and the estimated time.
+    Battery,
+    /// Framerate graph, followed by the clock.
+    Fps,
+    /// Current TDP limit.
+    Tdp,
+    /// Per-application TDP profile. Omitted when no profile is active.
+    Profile,
+    /// Blank line.
+    Gap,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    Text(String),
+    Element(Element),
+}
+
+/// Splits the overlay template into literal text and placeholders (e.g. `{battery}`).
+/// Unknown placeholders are dropped.
+pub fn parse(template: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+        let name = &rest[start + 1..start + len];
+        match parse_element(name) {
+            Some(element) => tokens.push(Token::Element(element)),
+            None => warn!("Unknown overlay template placeholder: {{{name}}}"),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+    tokens
+}
+
+fn parse_element(name: &str) -> Option<Element> {
+    match name {
+        "battery" => Some(Element::Battery),
+        "fps" => Some(Element::Fps),
+        "tdp" => Some(Element::Tdp),
+        "profile" => Some(Element::Profile),
+        "gap" => Some(Element::Gap),
+        _ => None,
+    }
+}
+
+/// Lists the problems `parse` would silently work around: unknown or unclosed placeholders,
+/// and RTSS tags (e.g. `<C=FF0000>`) missing either bracket. Positions are in characters.
+pub fn validate(template: &str) -> Vec<String> {
+    let mut issues = vec![];
+    // Byte offset of the name and character position of the brace
+    let mut placeholder = None;
+    let mut tag = None;
+    for (pos, (i, c)) in template.char_indices().enumerate() {
+        match c {
+            '{' if placeholder.is_none() => placeholder = Some((i + 1, pos)),
+            '}' => {
+                if let Some((start, _)) = placeholder.take() {
+                    let name = &template[start..i];
+                    if parse_element(name).is_none() {
+                        issues.push(format!("Unknown placeholder {{{name}}}"));
+                    }
+                }
+            }
+            '<' => {
+                if let Some(start) = tag.replace(pos) {
+                    issues.push(format!("Tag at position {start} is not closed"));
+                }
+            }
+            '>' if tag.take().is_none() => {
+                issues.push(format!("Unexpected '>' at position {pos}"));
+            }
+            _ => {}
+        }
+    }
+    if let Some((_, start)) = placeholder {
+        issues.push(format!("Placeholder at position {start} is not closed"));
+    }
+    if let Some(start) = tag {
+        issues.push(format!("Tag at position {start} is not closed"));
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_template() {
+        assert_eq!(
+            parse(DEFAULT_TEMPLATE),
+            vec![
+                Token::Element(Element::Battery),
+                Token::Element(Element::Fps)
+            ]
+        );
+    }
+
+    #[test]
+    fn reordered_with_gap() {
+        assert_eq!(
+            parse("{fps}{gap}{battery}"),
+            vec![
+                Token::Element(Element::Fps),
+                Token::Element(Element::Gap),
+                Token::Element(Element::Battery)
+            ]
+        );
+    }
+
+    #[test]
+    fn text_and_unknown_placeholders() {
+        assert_eq!(
+            parse("<S=50>{fps} {nope}{battery} tail{"),
+            vec![
+                Token::Text(String::from("<S=50>")),
+                Token::Element(Element::Fps),
+                Token::Text(String::from(" ")),
+                Token::Element(Element::Battery),
+                Token::Text(String::from(" tail{"))
+            ]
+        );
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate("<C=FF0000>{fps}<C>{gap}{battery}").is_empty());
+        assert_eq!(
+            validate("<C=FF0000{fps}<C> {nope}> <S=50"),
+            vec![
+                String::from("Tag at position 0 is not closed"),
+                String::from("Unknown placeholder {nope}"),
+                String::from("Unexpected '>' at position 24"),
+                String::from("Tag at position 26 is not closed")
+            ]
+        );
+        assert_eq!(
+            validate("{fps}{tdp"),
+            vec![String::from("Placeholder at position 5 is not closed")]
+        );
+    }
+}
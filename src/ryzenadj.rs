@@ -1,7 +1,25 @@
 use libloading::os::windows::Symbol;
 use libloading::Library;
+use std::env::{current_exe, var_os};
 use std::ffi::c_void;
 use std::fmt::{Debug, Display, Formatter};
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+use windows::Win32::Foundation::ERROR_BAD_EXE_FORMAT;
+
+const LIBRARY_NAME: &str = "libryzenadj.dll";
+/// Environment variable with the full path of the library to load instead of the bundled one.
+/// Whoever can set it can run code in the (usually elevated) application, the same as whoever
+/// can replace the bundled library.
+const LIBRARY_PATH_VARIABLE: &str = "LILPOWERMAN_RYZENADJ_DLL";
+/// Pauses before retrying a limit change the SMU has turned down, e.g. right after resume.
+const SMU_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+];
 
 #[repr(transparent)]
 #[derive(Clone, Copy)]
@@ -15,6 +33,7 @@ impl RyzenAccess {
 
 pub enum Error {
     LibraryLoading(libloading::Error),
+    ArchitectureMismatch,
     InitFailure,
     FamilyNotSupported,
     SMUTimeout,
@@ -22,9 +41,24 @@ pub enum Error {
     SMURejected,
     InvalidMemoryAccess,
     UnknownErrorCode(i32),
+    /// The library is too old to export the function.
+    // Only returned by `set_tctl_temp`, which nothing calls yet
+    #[allow(dead_code)]
+    MissingExport(&'static str),
 }
 
 impl Error {
+    /// Returns `true` for errors that the driver reports when it cannot access the hardware,
+    /// which typically means the application has to be run as administrator.
+    pub fn is_access_error(&self) -> bool {
+        matches!(self, Self::InitFailure | Self::InvalidMemoryAccess)
+    }
+
+    /// Returns `true` for errors that the SMU reports while it is briefly busy.
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::SMURejected | Self::SMUTimeout)
+    }
+
     fn check(errorcode: i32) -> Result<(), Self> {
         match errorcode {
             0 => Ok(()),
@@ -40,7 +74,14 @@ impl Error {
 
 impl From<libloading::Error> for Error {
     fn from(error: libloading::Error) -> Self {
-        Self::LibraryLoading(error)
+        let os_error = std::error::Error::source(&error)
+            .and_then(|source| source.downcast_ref::<IoError>())
+            .and_then(IoError::raw_os_error);
+        if os_error == Some(ERROR_BAD_EXE_FORMAT.0 as i32) {
+            Self::ArchitectureMismatch
+        } else {
+            Self::LibraryLoading(error)
+        }
     }
 }
 
@@ -54,6 +95,11 @@ impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::LibraryLoading(inner) => write!(f, "Failed to load library: {inner}"),
+            Self::ArchitectureMismatch => write!(
+                f,
+                "The library is not a {}-bit DLL. Its bitness must match the application",
+                usize::BITS
+            ),
             Self::InitFailure => write!(f, "Failed to init RyzenAdj"),
             Self::FamilyNotSupported => write!(f, "APU family is not supported"),
             Self::SMUTimeout => write!(f, "SMU Timeout"),
@@ -61,12 +107,52 @@ impl Display for Error {
             Self::SMURejected => write!(f, "SMU operation is rejected"),
             Self::InvalidMemoryAccess => write!(f, "Memory access error"),
             Self::UnknownErrorCode(x) => write!(f, "Unknown error code {x}"),
+            Self::MissingExport(name) => write!(f, "The library does not export {name}"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// Tally of limit writes, to tell a poorly supported chip from occasional rejections.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct ApplyStats {
+    pub succeeded: u32,
+    pub rejected: u32,
+    pub timed_out: u32,
+    /// Any other failure.
+    pub failed: u32,
+}
+
+impl ApplyStats {
+    pub fn record<T>(&mut self, result: &Result<T, Error>) {
+        match result {
+            Ok(_) => self.succeeded += 1,
+            Err(Error::SMURejected) => self.rejected += 1,
+            Err(Error::SMUTimeout) => self.timed_out += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.succeeded + self.rejected + self.timed_out + self.failed
+    }
+}
+
+impl Display for ApplyStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SMU apply success: {}/{}", self.succeeded, self.total())?;
+        if self.total() > self.succeeded {
+            write!(
+                f,
+                " ({} rejected, {} timed out, {} other)",
+                self.rejected, self.timed_out, self.failed
+            )?;
+        }
+        Ok(())
+    }
+}
+
 struct Native {
     /// # Safety
     ///
@@ -81,10 +167,77 @@ struct Native {
     /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
     /// Caller should refresh table before accessing any values.
     get_fast_limit: Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_fast_value: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_slow_limit: Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>,
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_stapm_limit: Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_tctl_temp: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_tctl_temp_value: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_apu_skin_temp_limit: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_apu_skin_temp_value: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_vrm_current: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    /// Caller should refresh table before accessing any values.
+    get_vrm_current_value: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
     /// # Safety
     ///
     /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
     set_stapm_limit: Symbol<unsafe extern "C" fn(RyzenAccess, u32) -> i32>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    // Only used by `RyzenAdj::set_tctl_temp`, which nothing calls yet
+    #[allow(dead_code)]
+    set_tctl_temp: Option<Symbol<unsafe extern "C" fn(RyzenAccess, u32) -> i32>>,
     /// # Safety
     ///
     /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
@@ -93,6 +246,18 @@ struct Native {
     ///
     /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
     set_slow_limit: Symbol<unsafe extern "C" fn(RyzenAccess, u32) -> i32>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    get_cpu_family: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> i32>>,
+    /// Not exported by older versions of the library.
+    ///
+    /// # Safety
+    ///
+    /// Caller should ensure library is still loaded and `RyzenAccess` instance has not been cleaned up.
+    get_bios_if_ver: Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> i32>>,
     /// # Safety
     ///
     /// Caller should ensure library is still loaded.
@@ -100,11 +265,74 @@ struct Native {
     cleanup_ryzenadj: Symbol<unsafe extern "C" fn(RyzenAccess)>,
 }
 
+/// Limiters the chip is currently running into.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct ThrottleStatus {
+    /// CPU temperature has reached the Tctl limit.
+    pub thermal: bool,
+    /// APU skin temperature has reached its limit.
+    pub skin_temperature: bool,
+    /// VRM current has reached its (EDC) limit.
+    pub current: bool,
+}
+
+impl ThrottleStatus {
+    pub fn is_throttling(&self) -> bool {
+        self.thermal || self.skin_temperature || self.current
+    }
+}
+
+/// Values within this fraction of the limit are considered to be at the limit.
+const LIMIT_TOLERANCE: f32 = 0.01;
+
+/// Returns `None` if the family does not report either of the values.
+fn is_at_limit(value: f32, limit: f32) -> Option<bool> {
+    if value.is_nan() || limit.is_nan() || limit <= 0.0 {
+        None
+    } else {
+        Some(value >= limit * (1.0 - LIMIT_TOLERANCE))
+    }
+}
+
+/// Converts a limit reported in watts. Rounds, so that a limit set as 4003 mW does not
+/// read back as 4002 mW due to the float representation.
+fn to_milliwatts(watts: f32) -> u32 {
+    (watts * 1000.0).round() as u32
+}
+
+/// Values of a single table refresh, so that they are consistent with each other.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TdpSnapshot {
+    /// Limits in milliwatts.
+    pub fast_limit: u32,
+    pub slow_limit: u32,
+    pub stapm_limit: u32,
+    /// CPU temperature and its limit in degrees Celsius, NaN if not reported.
+    pub temperature: f32,
+    pub temperature_limit: f32,
+    /// Power tracked against the fast limit in watts, see [`RyzenAdjTable::get_current_power`].
+    pub current_power: f32,
+    pub throttle: Option<ThrottleStatus>,
+}
+
 pub struct RyzenAdjTable<'lib> {
     main: &'lib RyzenAdj,
 }
 
 impl<'lib> RyzenAdjTable<'lib> {
+    /// Reads all the values the application tracks at once.
+    pub fn snapshot(&self) -> TdpSnapshot {
+        TdpSnapshot {
+            fast_limit: self.get_fast_limit(),
+            slow_limit: self.get_slow_limit(),
+            stapm_limit: self.get_stapm_limit(),
+            temperature: self.get_tctl_temp(),
+            temperature_limit: self.get_tctl_temp_limit(),
+            current_power: self.get_current_power(),
+            throttle: self.get_throttle_status(),
+        }
+    }
+
     /// Returns current TDP fast limit in milliwatts.
     pub fn get_fast_limit(&self) -> u32 {
         debug!("Reading TDP fast limit");
@@ -112,7 +340,102 @@ impl<'lib> RyzenAdjTable<'lib> {
         // for the lifetime of `RyzenAdj` instance
         // The table has been refreshed as part of `RyzenAdjTable` initialization.
         let value = unsafe { (self.main.native.get_fast_limit)(self.main.ry) };
-        (value * 1000f32) as u32
+        to_milliwatts(value)
+    }
+
+    /// Returns current TDP slow limit in milliwatts.
+    pub fn get_slow_limit(&self) -> u32 {
+        debug!("Reading TDP slow limit");
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        // The table has been refreshed as part of `RyzenAdjTable` initialization.
+        let value = unsafe { (self.main.native.get_slow_limit)(self.main.ry) };
+        to_milliwatts(value)
+    }
+
+    /// Returns current TDP STAPM limit in milliwatts.
+    pub fn get_stapm_limit(&self) -> u32 {
+        debug!("Reading TDP STAPM limit");
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        // The table has been refreshed as part of `RyzenAdjTable` initialization.
+        let value = unsafe { (self.main.native.get_stapm_limit)(self.main.ry) };
+        to_milliwatts(value)
+    }
+
+    /// Reads a value through a getter the library might not export, NaN if it does not.
+    fn get_optional_value(
+        &self,
+        getter: &Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> f32>>,
+    ) -> f32 {
+        match getter {
+            // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+            // for the lifetime of `RyzenAdj` instance
+            // The table has been refreshed as part of `RyzenAdjTable` initialization.
+            Some(getter) => unsafe { getter(self.main.ry) },
+            None => f32::NAN,
+        }
+    }
+
+    /// Returns current CPU (Tctl) temperature in degrees Celsius.
+    ///
+    /// The value is NaN on APU families that do not report it,
+    /// or if the library does not export it.
+    pub fn get_tctl_temp(&self) -> f32 {
+        debug!("Reading CPU temperature");
+        self.get_optional_value(&self.main.native.get_tctl_temp_value)
+    }
+
+    /// Returns the configured CPU (Tctl) temperature limit in degrees Celsius.
+    ///
+    /// The value is NaN on APU families that do not report it,
+    /// or if the library does not export it.
+    pub fn get_tctl_temp_limit(&self) -> f32 {
+        debug!("Reading CPU temperature limit");
+        self.get_optional_value(&self.main.native.get_tctl_temp)
+    }
+
+    /// Returns the power currently tracked against the fast limit in watts.
+    ///
+    /// Some firmwares report zero, negative values or NaN instead.
+    /// The value is NaN as well if the library does not export it.
+    pub fn get_current_power(&self) -> f32 {
+        debug!("Reading current power");
+        let Some(get_fast_value) = &self.main.native.get_fast_value else {
+            return f32::NAN;
+        };
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        // The table has been refreshed as part of `RyzenAdjTable` initialization.
+        unsafe { get_fast_value(self.main.ry) }
+    }
+
+    /// Returns the limiters currently in effect, or `None` if the APU family or the library
+    /// does not report them.
+    pub fn get_throttle_status(&self) -> Option<ThrottleStatus> {
+        debug!("Reading throttle status");
+        let native = &self.main.native;
+        let read = |getter| self.get_optional_value(getter);
+        let thermal = is_at_limit(
+            read(&native.get_tctl_temp_value),
+            read(&native.get_tctl_temp),
+        );
+        let skin_temperature = is_at_limit(
+            read(&native.get_apu_skin_temp_value),
+            read(&native.get_apu_skin_temp_limit),
+        );
+        let current = is_at_limit(
+            read(&native.get_vrm_current_value),
+            read(&native.get_vrm_current),
+        );
+        if thermal.is_none() && skin_temperature.is_none() && current.is_none() {
+            return None;
+        }
+        Some(ThrottleStatus {
+            thermal: thermal.unwrap_or(false),
+            skin_temperature: skin_temperature.unwrap_or(false),
+            current: current.unwrap_or(false),
+        })
     }
 }
 
@@ -133,11 +456,37 @@ pub struct RyzenAdj {
     ry: RyzenAccess,
 }
 
+/// Returns the library named by `LIBRARY_PATH_VARIABLE`, or the one next to the executable,
+/// so that it is found regardless of the working directory, e.g. when started at login.
+/// Falls back to the working directory.
+fn get_library_path() -> PathBuf {
+    if let Some(path) = var_os(LIBRARY_PATH_VARIABLE).filter(|p| !p.is_empty()) {
+        let path = PathBuf::from(path);
+        // A relative path would be looked up in the DLL search path
+        if path.is_absolute() && path.is_file() {
+            info!("RyzenAdj library is overridden with {:?}", path);
+            return path;
+        }
+        warn!(
+            "Ignoring {}, {:?} is not the full path of an existing file",
+            LIBRARY_PATH_VARIABLE, path
+        );
+    }
+    current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(LIBRARY_NAME)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(".").join(LIBRARY_NAME))
+}
+
 impl RyzenAdj {
     pub fn new() -> Result<Self, Error> {
-        debug!("Loading RyzenAdj library");
-        // SAFETY: Bundled DLL version does not include any initialization/termination routines
-        let library = unsafe { Library::new("./libryzenadj.dll")? };
+        let path = get_library_path();
+        debug!("Loading RyzenAdj library from {:?}", path);
+        // SAFETY: RyzenAdj does not include any initialization/termination routines.
+        // The library is trusted to be RyzenAdj: it comes either from the application folder
+        // or from `LIBRARY_PATH_VARIABLE`, both controlled by whoever installed the application.
+        let library = unsafe { Library::new(&path)? };
         // SAFETY: The specified types match the library header
         let native = unsafe {
             Native {
@@ -145,9 +494,23 @@ impl RyzenAdj {
                 cleanup_ryzenadj: get_native_symbol(&library, b"cleanup_ryzenadj")?,
                 refresh_table: get_native_symbol(&library, b"refresh_table")?,
                 get_fast_limit: get_native_symbol(&library, b"get_fast_limit")?,
+                get_fast_value: get_native_symbol(&library, b"get_fast_value").ok(),
+                get_slow_limit: get_native_symbol(&library, b"get_slow_limit")?,
+                get_stapm_limit: get_native_symbol(&library, b"get_stapm_limit")?,
+                get_tctl_temp: get_native_symbol(&library, b"get_tctl_temp").ok(),
+                get_tctl_temp_value: get_native_symbol(&library, b"get_tctl_temp_value").ok(),
+                get_apu_skin_temp_limit: get_native_symbol(&library, b"get_apu_skin_temp_limit")
+                    .ok(),
+                get_apu_skin_temp_value: get_native_symbol(&library, b"get_apu_skin_temp_value")
+                    .ok(),
+                get_vrm_current: get_native_symbol(&library, b"get_vrm_current").ok(),
+                get_vrm_current_value: get_native_symbol(&library, b"get_vrm_current_value").ok(),
                 set_fast_limit: get_native_symbol(&library, b"set_fast_limit")?,
                 set_slow_limit: get_native_symbol(&library, b"set_slow_limit")?,
                 set_stapm_limit: get_native_symbol(&library, b"set_stapm_limit")?,
+                set_tctl_temp: get_native_symbol(&library, b"set_tctl_temp").ok(),
+                get_cpu_family: get_native_symbol(&library, b"get_cpu_family").ok(),
+                get_bios_if_ver: get_native_symbol(&library, b"get_bios_if_ver").ok(),
             }
         };
         debug!("Initializing RyzenAdj");
@@ -156,14 +519,33 @@ impl RyzenAdj {
         if ry.is_invalid() {
             Err(Error::InitFailure)
         } else {
-            Ok(RyzenAdj {
+            let ryzen_adj = RyzenAdj {
                 _library: library,
                 native,
                 ry,
-            })
+            };
+            // The library does not report its own version, but these identify what it supports
+            debug!("RyzenAdj is initialized: {}", ryzen_adj.describe());
+            Ok(ryzen_adj)
         }
     }
 
+    /// Describes the CPU as seen by the library, for diagnostics.
+    pub fn describe(&self) -> String {
+        let describe = |symbol: &Option<Symbol<unsafe extern "C" fn(RyzenAccess) -> i32>>| {
+            // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+            // for the lifetime of `RyzenAdj` instance
+            symbol.as_ref().map_or(String::from("unknown"), |f| {
+                unsafe { f(self.ry) }.to_string()
+            })
+        };
+        format!(
+            "CPU family {}, BIOS interface version {}",
+            describe(&self.native.get_cpu_family),
+            describe(&self.native.get_bios_if_ver)
+        )
+    }
+
     /// Provides access to the refreshed table of CPU information.
     pub fn get_table(&self) -> Result<RyzenAdjTable, Error> {
         debug!("Reading TDP table");
@@ -175,23 +557,73 @@ impl RyzenAdj {
 
     /// Tries to change the TDP limit to the provided value in milliwatts.
     /// This action invalidates the table, thus it requires a unique reference to `RyzenAdj`.
+    /// Transient SMU errors are retried a few times before giving up.
     pub fn set_all_limits(&mut self, value: u32) -> Result<(), Error> {
-        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
-        // for the lifetime of `RyzenAdj` instance
-        unsafe {
-            debug!("Setting STAPM limit");
-            log::logger().flush();
-            Error::check((self.native.set_stapm_limit)(self.ry, value))?;
-            debug!("Setting slow TDP limit");
-            log::logger().flush();
-            Error::check((self.native.set_slow_limit)(self.ry, value))?;
-            debug!("Setting fast TDP limit");
-            log::logger().flush();
-            Error::check((self.native.set_fast_limit)(self.ry, value))?;
-            debug!("All limits set");
+        let mut result = self.set_limits(value, value, value);
+        for delay in SMU_RETRY_DELAYS {
+            match result {
+                Err(ref err) if err.is_transient() => {
+                    debug!("Retrying to set TDP limits in {:?}: {}", delay, err);
+                    sleep(delay);
+                    result = self.set_limits(value, value, value);
+                }
+                _ => break,
+            }
         }
+        result
+    }
+
+    /// Tries to change each TDP limit to the provided value in milliwatts.
+    pub fn set_limits(&mut self, fast: u32, slow: u32, stapm: u32) -> Result<(), Error> {
+        self.set_stapm_limit(stapm)?;
+        self.set_slow_limit(slow)?;
+        self.set_fast_limit(fast)?;
+        debug!("All limits set");
         Ok(())
     }
+
+    /// Tries to change the fast TDP limit to the provided value in milliwatts.
+    pub fn set_fast_limit(&mut self, value: u32) -> Result<(), Error> {
+        debug!("Setting fast TDP limit");
+        log::logger().flush();
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        Error::check(unsafe { (self.native.set_fast_limit)(self.ry, value) })
+    }
+
+    /// Tries to change the slow TDP limit to the provided value in milliwatts.
+    pub fn set_slow_limit(&mut self, value: u32) -> Result<(), Error> {
+        debug!("Setting slow TDP limit");
+        log::logger().flush();
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        Error::check(unsafe { (self.native.set_slow_limit)(self.ry, value) })
+    }
+
+    /// Tries to change the CPU (Tctl) temperature limit to the provided value in degrees Celsius.
+    ///
+    /// The valid range depends on the APU family, the SMU rejects values outside of it.
+    // Kept as part of the wrapper API, the temperature limit setting was dropped for now
+    #[allow(dead_code)]
+    pub fn set_tctl_temp(&mut self, celsius: u32) -> Result<(), Error> {
+        debug!("Setting CPU temperature limit");
+        let Some(set_tctl_temp) = &self.native.set_tctl_temp else {
+            return Err(Error::MissingExport("set_tctl_temp"));
+        };
+        log::logger().flush();
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        Error::check(unsafe { set_tctl_temp(self.ry, celsius) })
+    }
+
+    /// Tries to change the STAPM limit to the provided value in milliwatts.
+    pub fn set_stapm_limit(&mut self, value: u32) -> Result<(), Error> {
+        debug!("Setting STAPM limit");
+        log::logger().flush();
+        // SAFETY: Validity of Library and `RyzenAccess` pointers is guaranteed
+        // for the lifetime of `RyzenAdj` instance
+        Error::check(unsafe { (self.native.set_stapm_limit)(self.ry, value) })
+    }
 }
 
 impl Drop for RyzenAdj {
@@ -203,3 +635,33 @@ impl Drop for RyzenAdj {
         unsafe { (self.native.cleanup_ryzenadj)(self.ry) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_rounded_to_milliwatts() {
+        assert_eq!(to_milliwatts(15.0), 15000);
+        assert_eq!(to_milliwatts(4.003), 4003);
+        assert_eq!(to_milliwatts(2.041), 2041);
+        // Families without the value report NaN
+        assert_eq!(to_milliwatts(f32::NAN), 0);
+    }
+
+    #[test]
+    fn apply_stats_are_tallied() {
+        let mut stats = ApplyStats::default();
+        stats.record(&Ok(()));
+        stats.record(&Ok(()));
+        assert_eq!(stats.to_string(), "SMU apply success: 2/2");
+        stats.record::<()>(&Err(Error::SMURejected));
+        stats.record::<()>(&Err(Error::SMUTimeout));
+        stats.record::<()>(&Err(Error::SMURejected));
+        stats.record::<()>(&Err(Error::InvalidMemoryAccess));
+        assert_eq!(
+            stats.to_string(),
+            "SMU apply success: 2/6 (2 rejected, 1 timed out, 1 other)"
+        );
+    }
+}
@@ -1,14 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
+use std::ops::RangeInclusive;
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use windows::core::{w, Error, Owned, PCWSTR, PWSTR};
 use windows::Win32::Foundation::{
     ERROR_FILE_NOT_FOUND, ERROR_MORE_DATA, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS,
 };
+#[cfg(feature = "rtss")]
+use windows::Win32::System::Registry::REG_SZ;
 use windows::Win32::System::Registry::{
     RegCreateKeyExW, RegDeleteValueW, RegEnumValueW, RegGetValueW, RegQueryInfoKeyW,
     RegSetValueExW, HKEY, HKEY_CURRENT_USER, KEY_ALL_ACCESS, REG_DWORD_LITTLE_ENDIAN,
-    REG_OPTION_NON_VOLATILE, RRF_RT_REG_DWORD, RRF_ZEROONFAILURE,
+    REG_OPTION_NON_VOLATILE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ, RRF_ZEROONFAILURE,
 };
 
 #[derive(Copy, Clone, Default, PartialEq)]
@@ -18,10 +23,276 @@ pub enum TdpSetting {
     Forcing(u32),
 }
 
+/// TDP limit that is displayed in the tray icon.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum TdpLimitKind {
+    #[default]
+    Fast,
+    Slow,
+    Stapm,
+}
+
+pub const MAX_OVERLAY_UPDATE_RATE: u32 = 10;
+/// Charge rate in mW around zero that the charge icon shows as idle, unless configured.
+const DEFAULT_CHARGE_IDLE_BAND: u32 = 300;
+/// Layout of the settings key, bumped whenever a value changes its meaning.
+/// Settings written by a build with a newer layout are only read, never written.
+const SCHEMA_VERSION: u32 = 1;
+pub const MAX_CHARGE_IDLE_BAND: u32 = 5000;
+/// Limit on either side of an overlay graph in pixels.
+pub const MAX_OVERLAY_GRAPH_SIZE: u32 = 500;
+
+/// Colors of the charge icon.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum ChargeColorScheme {
+    /// Green when charging, white when discharging.
+    #[default]
+    Classic,
+    /// White when charging, red when discharging.
+    Inverted,
+    /// Green when charging, from white to red as the discharge rate grows.
+    RateMagnitude,
+}
+
+/// Unit of the temperatures in the tooltip.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+/// Line of the charge icon tooltip.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ChargeTooltipField {
+    Rate,
+    Percent,
+    Voltage,
+    PowerSource,
+    Runtime,
+    Health,
+    /// Highest discharge rate seen this session.
+    PeakDischarge,
+}
+
+/// Tooltip lines shown when nothing is configured.
+const DEFAULT_CHARGE_TOOLTIP: [ChargeTooltipField; 4] = [
+    ChargeTooltipField::Rate,
+    ChargeTooltipField::PowerSource,
+    ChargeTooltipField::Runtime,
+    ChargeTooltipField::PeakDischarge,
+];
+
+/// Executables of tools that change the TDP limits themselves, checked when nothing
+/// is configured.
+const DEFAULT_COMPETING_TOOLS: [&str; 4] = [
+    "ryzencontroller.exe",
+    "amdryzenmaster.exe",
+    "handheldcompanion.exe",
+    "universal x86 tuning utility.exe",
+];
+
+/// Escapes the text for a JSON string literal, without the quotes.
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the TDP setting and the application limits in mW as JSON, e.g.
+/// `{"tdp": 15000, "applications": {"c:\\game.exe": 10000}}`. `tdp` is `null` while tracking.
+pub fn format_settings_json(settings: &Settings) -> String {
+    let tdp = match settings.get_tdp_setting() {
+        TdpSetting::Tracking => String::from("null"),
+        TdpSetting::Forcing(limit) => limit.to_string(),
+    };
+    let mut apps: Vec<_> = settings.app_limits.iter().collect();
+    apps.sort();
+    let apps: Vec<String> = apps
+        .into_iter()
+        .map(|(app, limit)| {
+            let app = escape_json(&app.to_string_lossy());
+            format!("    \"{}\": {}", app, limit)
+        })
+        .collect();
+    if apps.is_empty() {
+        return format!("{{\n  \"tdp\": {},\n  \"applications\": {{}}\n}}\n", tdp);
+    }
+    format!(
+        "{{\n  \"tdp\": {},\n  \"applications\": {{\n{}\n  }}\n}}\n",
+        tdp,
+        apps.join(",\n")
+    )
+}
+
+/// Returns the package name for the AUMID of a packaged application, e.g.
+/// `Microsoft.MinecraftUWP` for `Microsoft.MinecraftUWP_8wekyb3d8bbwe!App`.
+/// `None` if the application is identified by its executable path instead.
+pub fn get_package_name(app: &OsStr) -> Option<&str> {
+    if Path::new(app).is_absolute() {
+        return None;
+    }
+    app.to_str()?.split(['_', '!']).next()
+}
+
+/// Parses a comma-separated list of executable names, e.g. `a.exe, b.exe`, in lower case.
+fn parse_competing_tools(value: &str) -> Vec<OsString> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| OsString::from(n.to_lowercase()))
+        .collect()
+}
+
+/// Parses a comma-separated list of tooltip fields, e.g. `rate,percent,runtime`.
+/// Unknown names are skipped.
+fn parse_charge_tooltip(value: &str) -> Vec<ChargeTooltipField> {
+    let mut fields = vec![];
+    for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let field = match name.to_ascii_lowercase().as_str() {
+            "rate" => ChargeTooltipField::Rate,
+            "percent" => ChargeTooltipField::Percent,
+            "voltage" => ChargeTooltipField::Voltage,
+            "source" => ChargeTooltipField::PowerSource,
+            "runtime" => ChargeTooltipField::Runtime,
+            "health" => ChargeTooltipField::Health,
+            "peak" => ChargeTooltipField::PeakDischarge,
+            _ => {
+                warn!("Unknown charge tooltip field: {}", name);
+                continue;
+            }
+        };
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+/// Presentation of the RTSS overlay. Colors are `0xRRGGBB`.
+#[derive(Clone, PartialEq)]
+pub struct OverlaySettings {
+    pub template: Option<String>,
+    pub charging_color: u32,
+    pub low_battery_color: u32,
+    pub low_battery_minutes: u32,
+    /// How many times per second the overlay is refreshed.
+    pub update_rate: u32,
+    /// Discharge rate in W at the bottom of the battery graph, `None` to scale it
+    /// to the highest rate seen so far.
+    pub battery_graph_watts: Option<u32>,
+    /// Whether the time is shown next to the framerate.
+    pub show_clock: bool,
+    pub battery_graph: OverlayGraphSettings,
+    pub fps_graph: OverlayGraphSettings,
+    /// Framerate at the top of the FPS graph.
+    pub fps_graph_max: u32,
+}
+
+/// Size and style of an overlay graph. Sizes are in pixels.
+#[derive(Clone, PartialEq)]
+pub struct OverlayGraphSettings {
+    pub width: u16,
+    pub height: u16,
+    /// Comma-separated RTSS graph flags, e.g. `filled,mirrored`.
+    pub style: Option<String>,
+}
+
+impl Default for OverlayGraphSettings {
+    fn default() -> Self {
+        OverlayGraphSettings {
+            width: 50,
+            height: 15,
+            style: None,
+        }
+    }
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        OverlaySettings {
+            template: None,
+            charging_color: 0x00FF00,
+            low_battery_color: 0xFF0000,
+            low_battery_minutes: 15,
+            update_rate: 1,
+            battery_graph_watts: Some(45),
+            show_clock: true,
+            battery_graph: OverlayGraphSettings::default(),
+            fps_graph: OverlayGraphSettings::default(),
+            fps_graph_max: 60,
+        }
+    }
+}
+
+/// TDP limit last applied by the application, stored apart from the user's preferences
+/// to recover after a crash. Limits are in mW.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AppliedTdp {
+    pub limit: u32,
+    /// Limit to return to once the applied one is no longer needed, if any.
+    pub restore: Option<u32>,
+}
+
+/// Limits the system had before the application changed them, in mW.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StockTdp {
+    pub fast: u32,
+    pub slow: u32,
+    pub stapm: u32,
+}
+
+/// Change that brings an invalid setting back into a sane state.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Repair {
+    ClampAppLimit(OsString, u32),
+    RemoveApp(OsString),
+    ClampTdpSetting(u32),
+}
+
 #[derive(Clone, Default, PartialEq)]
 pub struct Settings {
     app_limits: HashMap<OsString, u32>,
+    /// Applications whose limit only applies while they are full-screen.
+    fullscreen_only_apps: HashSet<OsString>,
     tdp: TdpSetting,
+    tray_limit: TdpLimitKind,
+    charge_color_scheme: ChargeColorScheme,
+    temperature_unit: TemperatureUnit,
+    /// Empty if the default tooltip is shown.
+    charge_tooltip: Vec<ChargeTooltipField>,
+    remove_missing_apps: bool,
+    fullscreen_detection: bool,
+    apply_on_startup: bool,
+    pause_when_display_off: bool,
+    reset_on_observe: bool,
+    tray_shows_target: bool,
+    tray_gauge: bool,
+    tray_shows_power: bool,
+    icon_outline: bool,
+    pause_for_competing_tools: bool,
+    apply_failure_sound: bool,
+    tdp_history: bool,
+    fine_tdp_options: bool,
+    /// Empty if the default tools are looked for.
+    competing_tools: Vec<OsString>,
+    hide_charge_icon: bool,
+    charge_idle_band: u32,
+    battery_saver_limit: Option<u32>,
+    push_to_boost_key: Option<u32>,
+    step_app_tdp_up_key: Option<u32>,
+    step_app_tdp_down_key: Option<u32>,
+    update_check_days: Option<u32>,
+    csv_log_path: Option<String>,
+    overlay: OverlaySettings,
 }
 
 impl Settings {
@@ -29,21 +300,236 @@ impl Settings {
         self.app_limits.get(app).copied()
     }
 
+    /// Applications that have a limit of their own.
+    pub fn get_limited_apps(&self) -> impl Iterator<Item = &OsStr> {
+        self.app_limits.keys().map(OsString::as_os_str)
+    }
+
+    pub fn is_app_fullscreen_only(&self, app: &OsStr) -> bool {
+        self.fullscreen_only_apps.contains(app)
+    }
+
     pub fn get_tdp_setting(&self) -> TdpSetting {
         self.tdp
     }
+
+    pub fn get_tray_limit(&self) -> TdpLimitKind {
+        self.tray_limit
+    }
+
+    pub fn get_charge_color_scheme(&self) -> ChargeColorScheme {
+        self.charge_color_scheme
+    }
+
+    pub fn get_temperature_unit(&self) -> TemperatureUnit {
+        self.temperature_unit
+    }
+
+    pub fn get_charge_tooltip_fields(&self) -> &[ChargeTooltipField] {
+        if self.charge_tooltip.is_empty() {
+            &DEFAULT_CHARGE_TOOLTIP
+        } else {
+            &self.charge_tooltip
+        }
+    }
+
+    /// Whether applications that no longer exist on disk are removed at startup.
+    pub fn is_remove_missing_apps_enabled(&self) -> bool {
+        self.remove_missing_apps
+    }
+
+    /// Whether a full-screen application stays in the foreground while the desktop briefly
+    /// takes its place.
+    pub fn is_fullscreen_detection_enabled(&self) -> bool {
+        self.fullscreen_detection
+    }
+
+    /// Whether the forced TDP setting is applied as soon as the application starts.
+    pub fn is_apply_on_startup_enabled(&self) -> bool {
+        self.apply_on_startup
+    }
+
+    /// Whether polling slows down while the display is off.
+    pub fn is_pause_when_display_off_enabled(&self) -> bool {
+        self.pause_when_display_off
+    }
+
+    /// Whether switching to observing brings back the stock limits instead of keeping the
+    /// last forced one.
+    pub fn is_reset_on_observe_enabled(&self) -> bool {
+        self.reset_on_observe
+    }
+
+    /// Whether the TDP icon shows the forced target instead of the measured limit.
+    pub fn is_tray_target_enabled(&self) -> bool {
+        self.tray_shows_target
+    }
+
+    /// Whether the TDP icon shows a bar filled by the power draw relative to the limit.
+    pub fn is_tray_gauge_enabled(&self) -> bool {
+        self.tray_gauge
+    }
+
+    /// Whether the TDP icon shows the power draw instead of the limit, when it is known.
+    pub fn is_tray_power_enabled(&self) -> bool {
+        self.tray_shows_power
+    }
+
+    /// Whether the text of the tray icons is outlined, so that it is legible on light taskbars.
+    pub fn is_icon_outline_enabled(&self) -> bool {
+        self.icon_outline
+    }
+
+    /// Whether TDP changes stop while another tool that changes them is running.
+    pub fn is_pause_for_competing_tools_enabled(&self) -> bool {
+        self.pause_for_competing_tools
+    }
+
+    /// Whether a sound is played when the TDP limit repeatedly fails to apply.
+    pub fn is_apply_failure_sound_enabled(&self) -> bool {
+        self.apply_failure_sound
+    }
+
+    /// Whether the TDP tooltip shows how the limit changed over the last minute.
+    pub fn is_tdp_history_enabled(&self) -> bool {
+        self.tdp_history
+    }
+
+    /// Whether the TDP menus offer every watt up to the highest limit, for experimenting.
+    pub fn is_fine_tdp_options_enabled(&self) -> bool {
+        self.fine_tdp_options
+    }
+
+    /// Executable names of the tools to pause for, in lower case.
+    pub fn get_competing_tools(&self) -> Vec<OsString> {
+        if self.competing_tools.is_empty() {
+            DEFAULT_COMPETING_TOOLS.map(OsString::from).to_vec()
+        } else {
+            self.competing_tools.clone()
+        }
+    }
+
+    /// Whether the charge icon and the battery overlay elements are shown,
+    /// provided there is a battery.
+    pub fn is_charge_icon_enabled(&self) -> bool {
+        !self.hide_charge_icon
+    }
+
+    /// Charge rate in mW on either side of zero at which the charge icon shows neither
+    /// charging nor discharging. 0 if disabled.
+    pub fn get_charge_idle_band(&self) -> u32 {
+        self.charge_idle_band
+    }
+
+    /// TDP limit to apply while Windows Battery Saver is on, `None` if disabled.
+    pub fn get_battery_saver_limit(&self) -> Option<u32> {
+        self.battery_saver_limit
+    }
+
+    /// Virtual-key code of the key that boosts TDP while held, `None` if disabled.
+    pub fn get_push_to_boost_key(&self) -> Option<u32> {
+        self.push_to_boost_key
+    }
+
+    /// Virtual-key code of the key that raises the foreground application limit, `None` if disabled.
+    pub fn get_step_app_tdp_up_key(&self) -> Option<u32> {
+        self.step_app_tdp_up_key
+    }
+
+    /// Virtual-key code of the key that lowers the foreground application limit, `None` if disabled.
+    pub fn get_step_app_tdp_down_key(&self) -> Option<u32> {
+        self.step_app_tdp_down_key
+    }
+
+    /// How often to check for a newer version, `None` if disabled.
+    pub fn get_update_check_days(&self) -> Option<u32> {
+        self.update_check_days
+    }
+
+    pub fn get_csv_log_path(&self) -> Option<&str> {
+        self.csv_log_path.as_deref()
+    }
+
+    /// Finds limits outside of the supported range and, if enabled,
+    /// applications that no longer exist on disk.
+    pub fn find_repairs(
+        &self,
+        range: &RangeInclusive<u32>,
+        app_exists: impl Fn(&OsStr) -> bool,
+    ) -> Vec<Repair> {
+        let mut repairs = vec![];
+        for (app, limit) in &self.app_limits {
+            if self.remove_missing_apps && !app_exists(app) {
+                repairs.push(Repair::RemoveApp(app.clone()));
+            } else if !range.contains(limit) {
+                let clamped = (*limit).clamp(*range.start(), *range.end());
+                repairs.push(Repair::ClampAppLimit(app.clone(), clamped));
+            }
+        }
+        if let TdpSetting::Forcing(x) = self.tdp {
+            if !range.contains(&x) {
+                repairs.push(Repair::ClampTdpSetting(
+                    x.clamp(*range.start(), *range.end()),
+                ));
+            }
+        }
+        repairs
+    }
+
+    #[cfg(feature = "rtss")]
+    pub fn get_overlay_settings(&self) -> &OverlaySettings {
+        &self.overlay
+    }
 }
 
 pub struct SettingsStorage {
     root_key: Owned<HKEY>,
     app_key: Owned<HKEY>,
+    fullscreen_only_key: Owned<HKEY>,
+    /// The settings were written by a newer version, so nothing is saved.
+    read_only: bool,
 }
 
 impl SettingsStorage {
-    pub fn new() -> Self {
-        let root_key = Self::create_subkey(HKEY_CURRENT_USER, w!("Software\\LilPowerMan")).unwrap();
-        let app_key = Self::create_subkey(*root_key, w!("Applications")).unwrap();
-        SettingsStorage { root_key, app_key }
+    pub fn new() -> Result<Self, Error> {
+        let root_key = Self::create_subkey(HKEY_CURRENT_USER, w!("Software\\LilPowerMan"))?;
+        let app_key = Self::create_subkey(*root_key, w!("Applications"))?;
+        // Kept apart from the limits, so that older versions still understand those
+        let fullscreen_only_key = Self::create_subkey(*root_key, w!("FullscreenOnlyApplications"))?;
+        let mut storage = SettingsStorage {
+            root_key,
+            app_key,
+            fullscreen_only_key,
+            read_only: false,
+        };
+        match storage.load_dword(w!("SchemaVersion")) {
+            Some(version) if version > SCHEMA_VERSION => {
+                warn!(
+                    "Settings use schema version {}, this build only knows {}, not saving any changes",
+                    version, SCHEMA_VERSION
+                );
+                storage.read_only = true;
+            }
+            Some(SCHEMA_VERSION) => {}
+            _ => storage.save_dword(w!("SchemaVersion"), SCHEMA_VERSION)?,
+        }
+        Ok(storage)
+    }
+
+    /// Whether changes are kept for this session only, because a newer version owns the settings.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Storage that persists nothing, for when the settings key cannot be opened,
+    /// e.g. because of a policy. Settings still change for the rest of the session.
+    pub fn in_memory() -> Self {
+        SettingsStorage {
+            root_key: Owned::default(),
+            app_key: Owned::default(),
+            fullscreen_only_key: Owned::default(),
+            read_only: false,
+        }
     }
 
     fn create_subkey(parent: HKEY, name: PCWSTR) -> Result<Owned<HKEY>, Error> {
@@ -69,7 +555,10 @@ impl SettingsStorage {
         Ok(unsafe { Owned::new(key) })
     }
 
-    fn load_tdp_setting(&self) -> TdpSetting {
+    fn load_dword(&self, name: PCWSTR) -> Option<u32> {
+        if self.root_key.is_invalid() {
+            return None;
+        }
         let mut data = 0;
         let mut data_len = size_of::<u32>() as u32;
         // SAFETY: All provided pointers reference local variables, string is null-terminated
@@ -77,30 +566,213 @@ impl SettingsStorage {
             RegGetValueW(
                 *self.root_key,
                 None,
-                w!("TdpSetting"),
+                name,
                 RRF_RT_REG_DWORD | RRF_ZEROONFAILURE,
                 None,
                 Some(&mut data as *mut _ as *mut _),
                 Some(&mut data_len),
             )
         };
-        if result != ERROR_SUCCESS && result != ERROR_MORE_DATA && result != ERROR_FILE_NOT_FOUND {
+        if result == ERROR_FILE_NOT_FOUND {
+            return None;
+        }
+        if result != ERROR_SUCCESS && result != ERROR_MORE_DATA {
             panic!("{}", Error::from(result));
         }
-        if data == 0 {
-            TdpSetting::Tracking
-        } else {
-            TdpSetting::Forcing(data)
+        Some(data)
+    }
+
+    fn save_dword(&mut self, name: PCWSTR, value: u32) -> Result<(), Error> {
+        if self.root_key.is_invalid() || self.read_only {
+            return Ok(());
+        }
+        let data: [u8; 4] = value.to_le_bytes();
+        // SAFETY: All provided pointers reference local variables, string is null-terminated
+        let result = unsafe {
+            RegSetValueExW(
+                *self.root_key,
+                name,
+                0,
+                REG_DWORD_LITTLE_ENDIAN,
+                Some(&data),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return Err(Error::from(result));
         }
+        Ok(())
     }
 
-    pub fn load(&self) -> Settings {
+    /// Saves the string, or deletes the value if there is none, so that the default applies.
+    #[cfg(feature = "rtss")]
+    fn save_string(&mut self, name: PCWSTR, value: Option<&str>) -> Result<(), Error> {
+        if self.root_key.is_invalid() || self.read_only {
+            return Ok(());
+        }
+        let result = match value {
+            Some(value) => {
+                let data: Vec<u8> = value
+                    .encode_utf16()
+                    .chain([0])
+                    .flat_map(u16::to_le_bytes)
+                    .collect();
+                // SAFETY: All provided pointers reference local variables, data is null-terminated
+                unsafe { RegSetValueExW(*self.root_key, name, 0, REG_SZ, Some(&data)) }
+            }
+            // SAFETY: String is null-terminated
+            None => match unsafe { RegDeleteValueW(*self.root_key, name) } {
+                ERROR_FILE_NOT_FOUND => ERROR_SUCCESS,
+                result => result,
+            },
+        };
+        if result != ERROR_SUCCESS {
+            return Err(Error::from(result));
+        }
+        Ok(())
+    }
+
+    fn load_tdp_setting(&self) -> TdpSetting {
+        match self.load_dword(w!("TdpSetting")) {
+            None | Some(0) => TdpSetting::Tracking,
+            Some(data) => TdpSetting::Forcing(data),
+        }
+    }
+
+    fn load_tray_limit(&self) -> TdpLimitKind {
+        match self.load_dword(w!("TrayTdpLimit")) {
+            Some(1) => TdpLimitKind::Slow,
+            Some(2) => TdpLimitKind::Stapm,
+            _ => TdpLimitKind::Fast,
+        }
+    }
+
+    fn load_charge_color_scheme(&self) -> ChargeColorScheme {
+        match self.load_dword(w!("ChargeColorScheme")) {
+            Some(1) => ChargeColorScheme::Inverted,
+            Some(2) => ChargeColorScheme::RateMagnitude,
+            _ => ChargeColorScheme::Classic,
+        }
+    }
+
+    fn load_temperature_unit(&self) -> TemperatureUnit {
+        match self.load_dword(w!("TemperatureUnit")) {
+            Some(1) => TemperatureUnit::Fahrenheit,
+            _ => TemperatureUnit::Celsius,
+        }
+    }
+
+    fn load_overlay_settings(&self) -> OverlaySettings {
+        let default = OverlaySettings::default();
+        OverlaySettings {
+            template: self.load_string(w!("OverlayTemplate")),
+            charging_color: self
+                .load_dword(w!("OverlayChargingColor"))
+                .unwrap_or(default.charging_color),
+            low_battery_color: self
+                .load_dword(w!("OverlayLowBatteryColor"))
+                .unwrap_or(default.low_battery_color),
+            low_battery_minutes: self
+                .load_dword(w!("OverlayLowBatteryMinutes"))
+                .unwrap_or(default.low_battery_minutes),
+            update_rate: self
+                .load_dword(w!("OverlayUpdateRate"))
+                .unwrap_or(default.update_rate)
+                .clamp(1, MAX_OVERLAY_UPDATE_RATE),
+            battery_graph_watts: match self.load_dword(w!("OverlayBatteryGraphWatts")) {
+                Some(0) => None,
+                Some(watts) => Some(watts),
+                None => default.battery_graph_watts,
+            },
+            show_clock: self.load_dword(w!("OverlayShowClock")) != Some(0),
+            battery_graph: self.load_graph_settings(
+                w!("OverlayBatteryGraphWidth"),
+                w!("OverlayBatteryGraphHeight"),
+                w!("OverlayBatteryGraphStyle"),
+            ),
+            fps_graph: self.load_graph_settings(
+                w!("OverlayFpsGraphWidth"),
+                w!("OverlayFpsGraphHeight"),
+                w!("OverlayFpsGraphStyle"),
+            ),
+            fps_graph_max: self
+                .load_dword(w!("OverlayFpsGraphMax"))
+                .filter(|&x| x != 0)
+                .unwrap_or(default.fps_graph_max),
+        }
+    }
+
+    fn load_graph_settings(
+        &self,
+        width: PCWSTR,
+        height: PCWSTR,
+        style: PCWSTR,
+    ) -> OverlayGraphSettings {
+        let default = OverlayGraphSettings::default();
+        let load_size = |name, default| {
+            self.load_dword(name)
+                .map_or(default, |x| x.clamp(1, MAX_OVERLAY_GRAPH_SIZE) as u16)
+        };
+        OverlayGraphSettings {
+            width: load_size(width, default.width),
+            height: load_size(height, default.height),
+            style: self.load_string(style),
+        }
+    }
+
+    fn load_string(&self, name: PCWSTR) -> Option<String> {
+        if self.root_key.is_invalid() {
+            return None;
+        }
+        let mut data_len = 0;
+        // SAFETY: String is null-terminated, no data buffer is provided, so only the size is returned
+        let result = unsafe {
+            RegGetValueW(
+                *self.root_key,
+                None,
+                name,
+                RRF_RT_REG_SZ,
+                None,
+                None,
+                Some(&mut data_len),
+            )
+        };
+        if result == ERROR_FILE_NOT_FOUND {
+            return None;
+        }
+        if result != ERROR_SUCCESS {
+            panic!("{}", Error::from(result));
+        }
+        let mut data = vec![0u16; data_len as usize / size_of::<u16>()];
+        // SAFETY: The buffer is allocated with the size returned by the previous call
+        let result = unsafe {
+            RegGetValueW(
+                *self.root_key,
+                None,
+                name,
+                RRF_RT_REG_SZ,
+                None,
+                Some(data.as_mut_ptr() as *mut _),
+                Some(&mut data_len),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            panic!("{}", Error::from(result));
+        }
+        let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+        Some(String::from_utf16_lossy(&data[..len]))
+    }
+
+    /// Reads all DWORD values of the key, keyed by their names.
+    fn load_dword_values(key: HKEY) -> HashMap<OsString, u32> {
+        if key.is_invalid() {
+            return HashMap::new();
+        }
         let mut values = 0;
         let mut max_value_name_len = 0;
         // SAFETY: All provided pointers reference local variables
         let result = unsafe {
             RegQueryInfoKeyW(
-                *self.app_key,
+                key,
                 PWSTR::null(),
                 None,
                 None,
@@ -117,7 +789,7 @@ impl SettingsStorage {
         if result != ERROR_SUCCESS {
             panic!("{}", Error::from(result));
         }
-        let mut app_limits = HashMap::new();
+        let mut dwords = HashMap::new();
         for i in 0..values {
             let mut value = vec![0; max_value_name_len as usize + 1];
             let mut value_name_len = max_value_name_len;
@@ -127,7 +799,7 @@ impl SettingsStorage {
             let result = unsafe {
                 // SAFETY: All provided pointers reference local variables, lengths are correct
                 RegEnumValueW(
-                    *self.app_key,
+                    key,
                     i,
                     PWSTR::from_raw(value.as_mut_ptr()),
                     &mut value_name_len,
@@ -142,23 +814,109 @@ impl SettingsStorage {
                 panic!("{}", Error::from(result));
             }
             if typ == REG_DWORD_LITTLE_ENDIAN.0 {
-                app_limits.insert(OsString::from_wide(&value[..value_name_len as usize]), data);
+                dwords.insert(OsString::from_wide(&value[..value_name_len as usize]), data);
             }
         }
+        dwords
+    }
+
+    pub fn load(&self) -> Settings {
+        let fullscreen_only_apps = Self::load_dword_values(*self.fullscreen_only_key)
+            .into_iter()
+            .filter(|&(_, flag)| flag != 0)
+            .map(|(app, _)| app)
+            .collect();
         Settings {
-            app_limits,
+            app_limits: Self::load_dword_values(*self.app_key),
+            fullscreen_only_apps,
             tdp: self.load_tdp_setting(),
+            tray_limit: self.load_tray_limit(),
+            charge_color_scheme: self.load_charge_color_scheme(),
+            temperature_unit: self.load_temperature_unit(),
+            charge_tooltip: self
+                .load_string(w!("ChargeTooltip"))
+                .map(|s| parse_charge_tooltip(&s))
+                .unwrap_or_default(),
+            remove_missing_apps: self.load_dword(w!("RemoveMissingApplications")) == Some(1),
+            fullscreen_detection: self.load_dword(w!("FullscreenDetection")) == Some(1),
+            apply_on_startup: self.load_dword(w!("ApplyOnStartup")) != Some(0),
+            pause_when_display_off: self.load_dword(w!("PauseWhenDisplayOff")) != Some(0),
+            reset_on_observe: self.load_dword(w!("ResetOnObserve")) == Some(1),
+            tray_shows_target: self.load_dword(w!("TrayShowsTarget")) == Some(1),
+            tray_gauge: self.load_dword(w!("TrayPowerGauge")) == Some(1),
+            tray_shows_power: self.load_dword(w!("TrayShowsPower")) == Some(1),
+            icon_outline: self.load_dword(w!("IconOutline")) != Some(0),
+            pause_for_competing_tools: self.load_dword(w!("PauseForCompetingTools")) != Some(0),
+            apply_failure_sound: self.load_dword(w!("ApplyFailureSound")) == Some(1),
+            tdp_history: self.load_dword(w!("TdpTooltipHistory")) == Some(1),
+            fine_tdp_options: self.load_dword(w!("FineTdpOptions")) == Some(1),
+            competing_tools: self
+                .load_string(w!("CompetingTools"))
+                .map(|s| parse_competing_tools(&s))
+                .unwrap_or_default(),
+            hide_charge_icon: self.load_dword(w!("ShowChargeIcon")) == Some(0),
+            charge_idle_band: self
+                .load_dword(w!("ChargeIdleBand"))
+                .unwrap_or(DEFAULT_CHARGE_IDLE_BAND)
+                .min(MAX_CHARGE_IDLE_BAND),
+            battery_saver_limit: self.load_dword(w!("BatterySaverTdp")).filter(|&x| x != 0),
+            push_to_boost_key: self.load_dword(w!("PushToBoostKey")).filter(|&x| x != 0),
+            step_app_tdp_up_key: self.load_dword(w!("StepAppTdpUpKey")).filter(|&x| x != 0),
+            step_app_tdp_down_key: self.load_dword(w!("StepAppTdpDownKey")).filter(|&x| x != 0),
+            update_check_days: self.load_dword(w!("UpdateCheckDays")).filter(|&x| x != 0),
+            csv_log_path: self.load_string(w!("CsvLogPath")),
+            overlay: self.load_overlay_settings(),
+        }
+    }
+
+    pub fn set_app_limit(
+        &mut self,
+        settings: &mut Settings,
+        app: OsString,
+        limit: u32,
+    ) -> Result<(), Error> {
+        self.save_app_dword(*self.app_key, &app, limit)?;
+        settings.app_limits.insert(app, limit);
+        Ok(())
+    }
+
+    /// Removes the limit of the application along with its other options.
+    pub fn remove_app_limit(&mut self, settings: &mut Settings, app: &OsStr) -> Result<(), Error> {
+        self.delete_app_value(*self.app_key, app)?;
+        settings.app_limits.remove(app);
+        self.set_app_fullscreen_only(settings, app, false)
+    }
+
+    pub fn set_app_fullscreen_only(
+        &mut self,
+        settings: &mut Settings,
+        app: &OsStr,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        if enabled {
+            self.save_app_dword(*self.fullscreen_only_key, app, 1)?;
+            settings.fullscreen_only_apps.insert(app.to_os_string());
+        } else {
+            match self.delete_app_value(*self.fullscreen_only_key, app) {
+                Err(err) if err != Error::from(ERROR_FILE_NOT_FOUND) => return Err(err),
+                _ => {}
+            }
+            settings.fullscreen_only_apps.remove(app);
         }
+        Ok(())
     }
 
-    pub fn set_app_limit(&mut self, settings: &mut Settings, app: OsString, limit: u32) {
+    fn save_app_dword(&self, key: HKEY, app: &OsStr, data: u32) -> Result<(), Error> {
+        if key.is_invalid() || self.read_only {
+            return Ok(());
+        }
         let mut value: Vec<u16> = app.encode_wide().collect();
         value.push(0);
-        let data: [u8; 4] = limit.to_le_bytes();
+        let data: [u8; 4] = data.to_le_bytes();
         // SAFETY: All provided pointers reference local variables, string is null-terminated
         let result = unsafe {
             RegSetValueExW(
-                *self.app_key,
+                key,
                 PCWSTR::from_raw(value.as_ptr()),
                 0,
                 REG_DWORD_LITTLE_ENDIAN,
@@ -166,41 +924,493 @@ impl SettingsStorage {
             )
         };
         if result != ERROR_SUCCESS {
-            panic!("{}", Error::from(result));
+            return Err(Error::from(result));
         }
-        settings.app_limits.insert(app, limit);
+        Ok(())
     }
 
-    pub fn remove_app_limit(&mut self, settings: &mut Settings, app: &OsStr) {
+    fn delete_app_value(&self, key: HKEY, app: &OsStr) -> Result<(), Error> {
+        if key.is_invalid() || self.read_only {
+            return Ok(());
+        }
         let mut value: Vec<u16> = app.encode_wide().collect();
         value.push(0);
         // SAFETY: String is null-terminated
-        let result = unsafe { RegDeleteValueW(*self.app_key, PCWSTR::from_raw(value.as_ptr())) };
+        let result = unsafe { RegDeleteValueW(key, PCWSTR::from_raw(value.as_ptr())) };
         if result != ERROR_SUCCESS {
-            panic!("{}", Error::from(result));
+            return Err(Error::from(result));
         }
-        settings.app_limits.remove(app);
+        Ok(())
     }
 
-    pub fn set_tdp_setting(&mut self, settings: &mut Settings, tdp: TdpSetting) {
+    pub fn set_tdp_setting(
+        &mut self,
+        settings: &mut Settings,
+        tdp: TdpSetting,
+    ) -> Result<(), Error> {
         let data = if let TdpSetting::Forcing(x) = tdp {
-            x.to_le_bytes()
+            x
         } else {
-            [0; 4]
-        };
-        // SAFETY: All provided pointers reference local variables, string is null-terminated
-        let result = unsafe {
-            RegSetValueExW(
-                *self.root_key,
-                w!("TdpSetting"),
-                0,
-                REG_DWORD_LITTLE_ENDIAN,
-                Some(&data),
-            )
+            0
         };
-        if result != ERROR_SUCCESS {
-            panic!("{}", Error::from(result));
-        }
+        self.save_dword(w!("TdpSetting"), data)?;
         settings.tdp = tdp;
+        Ok(())
+    }
+
+    pub fn apply_repair(&mut self, settings: &mut Settings, repair: Repair) -> Result<(), Error> {
+        match repair {
+            Repair::ClampAppLimit(app, limit) => self.set_app_limit(settings, app, limit),
+            Repair::RemoveApp(app) => self.remove_app_limit(settings, &app),
+            Repair::ClampTdpSetting(x) => self.set_tdp_setting(settings, TdpSetting::Forcing(x)),
+        }
+    }
+
+    pub fn set_tray_limit(
+        &mut self,
+        settings: &mut Settings,
+        kind: TdpLimitKind,
+    ) -> Result<(), Error> {
+        let data = match kind {
+            TdpLimitKind::Fast => 0,
+            TdpLimitKind::Slow => 1,
+            TdpLimitKind::Stapm => 2,
+        };
+        self.save_dword(w!("TrayTdpLimit"), data)?;
+        settings.tray_limit = kind;
+        Ok(())
+    }
+
+    pub fn set_charge_color_scheme(
+        &mut self,
+        settings: &mut Settings,
+        scheme: ChargeColorScheme,
+    ) -> Result<(), Error> {
+        let data = match scheme {
+            ChargeColorScheme::Classic => 0,
+            ChargeColorScheme::Inverted => 1,
+            ChargeColorScheme::RateMagnitude => 2,
+        };
+        self.save_dword(w!("ChargeColorScheme"), data)?;
+        settings.charge_color_scheme = scheme;
+        Ok(())
+    }
+
+    pub fn set_temperature_unit(
+        &mut self,
+        settings: &mut Settings,
+        unit: TemperatureUnit,
+    ) -> Result<(), Error> {
+        let data = match unit {
+            TemperatureUnit::Celsius => 0,
+            TemperatureUnit::Fahrenheit => 1,
+        };
+        self.save_dword(w!("TemperatureUnit"), data)?;
+        settings.temperature_unit = unit;
+        Ok(())
+    }
+
+    pub fn load_applied_tdp(&self) -> Option<AppliedTdp> {
+        let limit = self.load_dword(w!("AppliedTdp")).filter(|&x| x != 0)?;
+        let restore = self.load_dword(w!("AppliedTdpRestore")).filter(|&x| x != 0);
+        Some(AppliedTdp { limit, restore })
+    }
+
+    /// Records the applied limit, `None` once the system is back to its own limit.
+    pub fn save_applied_tdp(&mut self, applied: Option<AppliedTdp>) -> Result<(), Error> {
+        self.save_dword(w!("AppliedTdp"), applied.map_or(0, |a| a.limit))?;
+        let restore = applied.and_then(|a| a.restore).unwrap_or(0);
+        self.save_dword(w!("AppliedTdpRestore"), restore)
+    }
+
+    /// Returns when the application last checked for a newer version.
+    pub fn load_last_update_check(&self) -> Option<SystemTime> {
+        let secs = self.load_dword(w!("LastUpdateCheck")).filter(|&x| x != 0)?;
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
+
+    pub fn save_last_update_check(&mut self, time: SystemTime) -> Result<(), Error> {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.save_dword(w!("LastUpdateCheck"), secs as u32)
+    }
+
+    pub fn load_stock_tdp(&self) -> Option<StockTdp> {
+        Some(StockTdp {
+            fast: self.load_dword(w!("StockFastTdp"))?,
+            slow: self.load_dword(w!("StockSlowTdp"))?,
+            stapm: self.load_dword(w!("StockStapmTdp"))?,
+        })
+    }
+
+    pub fn save_stock_tdp(&mut self, stock: StockTdp) -> Result<(), Error> {
+        self.save_dword(w!("StockFastTdp"), stock.fast)?;
+        self.save_dword(w!("StockSlowTdp"), stock.slow)?;
+        self.save_dword(w!("StockStapmTdp"), stock.stapm)
+    }
+
+    pub fn set_remove_missing_apps(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("RemoveMissingApplications"), enabled as u32)?;
+        settings.remove_missing_apps = enabled;
+        Ok(())
+    }
+
+    pub fn set_fullscreen_detection(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("FullscreenDetection"), enabled as u32)?;
+        settings.fullscreen_detection = enabled;
+        Ok(())
+    }
+
+    pub fn set_apply_on_startup(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("ApplyOnStartup"), enabled as u32)?;
+        settings.apply_on_startup = enabled;
+        Ok(())
+    }
+
+    pub fn set_pause_when_display_off(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("PauseWhenDisplayOff"), enabled as u32)?;
+        settings.pause_when_display_off = enabled;
+        Ok(())
+    }
+
+    pub fn set_reset_on_observe(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("ResetOnObserve"), enabled as u32)?;
+        settings.reset_on_observe = enabled;
+        Ok(())
+    }
+
+    pub fn set_pause_for_competing_tools_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("PauseForCompetingTools"), enabled as u32)?;
+        settings.pause_for_competing_tools = enabled;
+        Ok(())
+    }
+
+    pub fn set_apply_failure_sound_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("ApplyFailureSound"), enabled as u32)?;
+        settings.apply_failure_sound = enabled;
+        Ok(())
+    }
+
+    pub fn set_tdp_history_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("TdpTooltipHistory"), enabled as u32)?;
+        settings.tdp_history = enabled;
+        Ok(())
+    }
+
+    pub fn set_fine_tdp_options_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("FineTdpOptions"), enabled as u32)?;
+        settings.fine_tdp_options = enabled;
+        Ok(())
+    }
+
+    pub fn set_tray_target_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("TrayShowsTarget"), enabled as u32)?;
+        settings.tray_shows_target = enabled;
+        Ok(())
+    }
+
+    pub fn set_tray_gauge_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("TrayPowerGauge"), enabled as u32)?;
+        settings.tray_gauge = enabled;
+        Ok(())
+    }
+
+    pub fn set_tray_power_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("TrayShowsPower"), enabled as u32)?;
+        settings.tray_shows_power = enabled;
+        Ok(())
+    }
+
+    pub fn set_icon_outline_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("IconOutline"), enabled as u32)?;
+        settings.icon_outline = enabled;
+        Ok(())
+    }
+
+    pub fn set_charge_icon_enabled(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("ShowChargeIcon"), enabled as u32)?;
+        settings.hide_charge_icon = !enabled;
+        Ok(())
+    }
+
+    pub fn set_battery_saver_limit(
+        &mut self,
+        settings: &mut Settings,
+        limit: Option<u32>,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("BatterySaverTdp"), limit.unwrap_or(0))?;
+        settings.battery_saver_limit = limit;
+        Ok(())
+    }
+
+    pub fn set_charge_idle_band(
+        &mut self,
+        settings: &mut Settings,
+        band: u32,
+    ) -> Result<(), Error> {
+        let band = band.min(MAX_CHARGE_IDLE_BAND);
+        self.save_dword(w!("ChargeIdleBand"), band)?;
+        settings.charge_idle_band = band;
+        Ok(())
+    }
+
+    pub fn set_update_check_days(
+        &mut self,
+        settings: &mut Settings,
+        days: Option<u32>,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("UpdateCheckDays"), days.unwrap_or(0))?;
+        settings.update_check_days = days;
+        Ok(())
+    }
+
+    #[cfg(feature = "rtss")]
+    pub fn set_overlay_update_rate(
+        &mut self,
+        settings: &mut Settings,
+        rate: u32,
+    ) -> Result<(), Error> {
+        let rate = rate.clamp(1, MAX_OVERLAY_UPDATE_RATE);
+        self.save_dword(w!("OverlayUpdateRate"), rate)?;
+        settings.overlay.update_rate = rate;
+        Ok(())
+    }
+
+    #[cfg(feature = "rtss")]
+    pub fn set_overlay_battery_graph_watts(
+        &mut self,
+        settings: &mut Settings,
+        watts: Option<u32>,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("OverlayBatteryGraphWatts"), watts.unwrap_or(0))?;
+        settings.overlay.battery_graph_watts = watts;
+        Ok(())
+    }
+
+    /// Replaces the presentation of the overlay, e.g. with a layout shared by someone else.
+    #[cfg(feature = "rtss")]
+    pub fn set_overlay_layout(
+        &mut self,
+        settings: &mut Settings,
+        overlay: OverlaySettings,
+    ) -> Result<(), Error> {
+        self.save_string(w!("OverlayTemplate"), overlay.template.as_deref())?;
+        self.save_dword(w!("OverlayChargingColor"), overlay.charging_color)?;
+        self.save_dword(w!("OverlayLowBatteryColor"), overlay.low_battery_color)?;
+        self.save_dword(w!("OverlayLowBatteryMinutes"), overlay.low_battery_minutes)?;
+        self.save_dword(
+            w!("OverlayBatteryGraphWatts"),
+            overlay.battery_graph_watts.unwrap_or(0),
+        )?;
+        self.save_dword(w!("OverlayShowClock"), overlay.show_clock as u32)?;
+        self.save_graph_settings(
+            &overlay.battery_graph,
+            w!("OverlayBatteryGraphWidth"),
+            w!("OverlayBatteryGraphHeight"),
+            w!("OverlayBatteryGraphStyle"),
+        )?;
+        self.save_graph_settings(
+            &overlay.fps_graph,
+            w!("OverlayFpsGraphWidth"),
+            w!("OverlayFpsGraphHeight"),
+            w!("OverlayFpsGraphStyle"),
+        )?;
+        self.save_dword(w!("OverlayFpsGraphMax"), overlay.fps_graph_max)?;
+        settings.overlay = overlay;
+        Ok(())
+    }
+
+    #[cfg(feature = "rtss")]
+    fn save_graph_settings(
+        &mut self,
+        graph: &OverlayGraphSettings,
+        width: PCWSTR,
+        height: PCWSTR,
+        style: PCWSTR,
+    ) -> Result<(), Error> {
+        self.save_dword(width, graph.width as u32)?;
+        self.save_dword(height, graph.height as u32)?;
+        self.save_string(style, graph.style.as_deref())
+    }
+
+    #[cfg(feature = "rtss")]
+    pub fn set_overlay_show_clock(
+        &mut self,
+        settings: &mut Settings,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.save_dword(w!("OverlayShowClock"), enabled as u32)?;
+        settings.overlay.show_clock = enabled;
+        Ok(())
+    }
+
+    /// Highest discharge rate in W the auto-scaled battery graph has reached,
+    /// so the scale does not start over every session.
+    #[cfg(feature = "rtss")]
+    pub fn load_battery_graph_peak(&self) -> Option<u32> {
+        self.load_dword(w!("OverlayBatteryGraphPeak"))
+    }
+
+    #[cfg(feature = "rtss")]
+    pub fn save_battery_graph_peak(&mut self, watts: u32) -> Result<(), Error> {
+        self.save_dword(w!("OverlayBatteryGraphPeak"), watts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_storage_loads_defaults() {
+        let settings = SettingsStorage::in_memory().load();
+        assert!(settings.get_tdp_setting() == TdpSetting::Tracking);
+        assert_eq!(settings.get_limited_apps().count(), 0);
+        assert_eq!(settings.get_charge_idle_band(), DEFAULT_CHARGE_IDLE_BAND);
+        assert!(settings.is_icon_outline_enabled());
+    }
+
+    #[test]
+    fn out_of_range_and_missing_values_are_repaired() {
+        let settings = Settings {
+            app_limits: HashMap::from([
+                (OsString::from("ok.exe"), 10000),
+                (OsString::from("high.exe"), 90000),
+                (OsString::from("gone.exe"), 100),
+            ]),
+            tdp: TdpSetting::Forcing(1),
+            remove_missing_apps: true,
+            ..Default::default()
+        };
+        let mut repairs = settings.find_repairs(&(5000..=28000), |app| app != "gone.exe");
+        repairs.sort_by_key(|r| format!("{:?}", r));
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::ClampAppLimit(OsString::from("high.exe"), 28000),
+                Repair::ClampTdpSetting(5000),
+                Repair::RemoveApp(OsString::from("gone.exe")),
+            ]
+        );
+    }
+
+    #[test]
+    fn charge_tooltip_fields_are_parsed() {
+        assert_eq!(
+            parse_charge_tooltip(" Percent,rate,,bogus,percent, health"),
+            vec![
+                ChargeTooltipField::Percent,
+                ChargeTooltipField::Rate,
+                ChargeTooltipField::Health,
+            ]
+        );
+        let settings = Settings {
+            charge_tooltip: parse_charge_tooltip("bogus"),
+            ..Default::default()
+        };
+        assert_eq!(settings.get_charge_tooltip_fields(), DEFAULT_CHARGE_TOOLTIP);
+    }
+
+    #[test]
+    fn competing_tools_are_parsed() {
+        assert_eq!(
+            parse_competing_tools(" RyzenController.exe,, other tool.exe "),
+            vec![
+                OsString::from("ryzencontroller.exe"),
+                OsString::from("other tool.exe"),
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_are_formatted_as_json() {
+        let settings = Settings {
+            app_limits: HashMap::from([
+                (OsString::from(r"c:\b.exe"), 10000),
+                (OsString::from("say \"hi\"\n"), 7500),
+            ]),
+            tdp: TdpSetting::Forcing(15000),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_settings_json(&settings),
+            concat!(
+                "{\n",
+                "  \"tdp\": 15000,\n",
+                "  \"applications\": {\n",
+                "    \"c:\\\\b.exe\": 10000,\n",
+                "    \"say \\\"hi\\\"\\u000a\": 7500\n",
+                "  }\n",
+                "}\n",
+            )
+        );
+        assert_eq!(
+            format_settings_json(&Settings::default()),
+            "{\n  \"tdp\": null,\n  \"applications\": {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn package_names_are_taken_from_app_ids() {
+        assert_eq!(
+            get_package_name(OsStr::new("Microsoft.MinecraftUWP_8wekyb3d8bbwe!App")),
+            Some("Microsoft.MinecraftUWP")
+        );
+        assert_eq!(get_package_name(OsStr::new(r"c:\games\game.exe")), None);
     }
 }
@@ -0,0 +1,537 @@
+use crate::settings::MAX_CHARGE_IDLE_BAND;
+#[cfg(feature = "rtss")]
+use crate::settings::MAX_OVERLAY_UPDATE_RATE;
+use crate::winapi::get_instance_handle;
+use std::ops::RangeInclusive;
+use std::ptr::copy_nonoverlapping;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    DialogBoxIndirectParamW, EndDialog, GetDlgItem, GetDlgItemInt, GetWindowLongPtrW, MessageBoxW,
+    SendDlgItemMessageW, SendMessageW, SetDlgItemInt, SetWindowLongPtrW, BM_GETCHECK, BM_SETCHECK,
+    BS_AUTOCHECKBOX, BS_DEFPUSHBUTTON, BS_PUSHBUTTON, DLGTEMPLATE, DS_CENTER, DS_MODALFRAME,
+    DS_SETFONT, ES_AUTOHSCROLL, ES_NUMBER, GWLP_USERDATA, IDCANCEL, IDOK, MB_ICONWARNING, MB_OK,
+    WM_COMMAND, WM_INITDIALOG, WM_NEXTDLGCTL, WS_BORDER, WS_CAPTION, WS_CHILD, WS_POPUP,
+    WS_SYSMENU, WS_TABSTOP, WS_VISIBLE,
+};
+
+const BUTTON_CLASS: u16 = 0x0080;
+const EDIT_CLASS: u16 = 0x0081;
+const STATIC_CLASS: u16 = 0x0082;
+/// Id of controls that are never referenced, e.g. labels.
+const ID_UNUSED: u16 = 0xFFFF;
+const ID_REMOVE_MISSING_APPS: u16 = 101;
+const ID_FULLSCREEN_DETECTION: u16 = 102;
+const ID_APPLY_ON_STARTUP: u16 = 103;
+const ID_BATTERY_SAVER_LIMIT: u16 = 104;
+#[cfg(feature = "rtss")]
+const ID_OVERLAY_UPDATE_RATE: u16 = 105;
+const ID_PAUSE_WHEN_DISPLAY_OFF: u16 = 106;
+const ID_UPDATE_CHECK_DAYS: u16 = 107;
+const ID_RESET_ON_OBSERVE: u16 = 108;
+const ID_SHOW_CHARGE_ICON: u16 = 110;
+const ID_TRAY_SHOWS_TARGET: u16 = 112;
+const ID_PAUSE_FOR_COMPETING_TOOLS: u16 = 113;
+const ID_APPLY_FAILURE_SOUND: u16 = 114;
+const ID_TDP_HISTORY: u16 = 115;
+const ID_CHARGE_IDLE_BAND: u16 = 116;
+const ID_TRAY_GAUGE: u16 = 117;
+const ID_TRAY_SHOWS_POWER: u16 = 118;
+const ID_ICON_OUTLINE: u16 = 119;
+const ID_TEMPERATURE_FAHRENHEIT: u16 = 120;
+const ID_FINE_TDP_OPTIONS: u16 = 121;
+#[cfg(feature = "rtss")]
+const ID_BATTERY_GRAPH_WATTS: u16 = 109;
+#[cfg(feature = "rtss")]
+const ID_OVERLAY_CLOCK: u16 = 111;
+
+// Layout in dialog units
+const DIALOG_WIDTH: i16 = 230;
+const MARGIN: i16 = 7;
+const ROW_HEIGHT: i16 = 14;
+const EDIT_WIDTH: i16 = 40;
+const BUTTON_WIDTH: i16 = 50;
+
+/// Options editable in the settings dialog.
+#[derive(Clone, PartialEq)]
+pub struct SettingsDialogValues {
+    pub remove_missing_apps: bool,
+    pub fullscreen_detection: bool,
+    pub apply_on_startup: bool,
+    pub pause_when_display_off: bool,
+    pub reset_on_observe: bool,
+    pub show_charge_icon: bool,
+    /// Whether the TDP icon shows the forced target instead of the measured limit.
+    pub tray_shows_target: bool,
+    /// Whether the TDP icon shows the power draw as a bar.
+    pub tray_gauge: bool,
+    /// Whether the TDP icon shows the power draw instead of the limit.
+    pub tray_shows_power: bool,
+    /// Whether the text of the tray icons is outlined.
+    pub icon_outline: bool,
+    /// Whether TDP changes stop while another tuning tool is running.
+    pub pause_for_competing_tools: bool,
+    /// Whether a sound is played when the TDP limit repeatedly fails to apply.
+    pub apply_failure_sound: bool,
+    /// Whether the TDP tooltip shows the last minute of measured limits.
+    pub tdp_history: bool,
+    /// Whether the TDP menus offer every watt up to the highest limit.
+    pub fine_tdp_options: bool,
+    /// Whether temperatures are shown in Fahrenheit instead of Celsius.
+    pub temperature_fahrenheit: bool,
+    /// TDP limit in mW to apply while Battery Saver is on, `None` if disabled.
+    pub battery_saver_limit: Option<u32>,
+    /// Range of the TDP menu options in mW that the Battery Saver TDP has to be in.
+    /// Not editable.
+    pub tdp_range: RangeInclusive<u32>,
+    /// Charge rate in mW around zero shown as idle, 0 if disabled.
+    pub charge_idle_band: u32,
+    /// Days between checks for a newer version, `None` if disabled.
+    pub update_check_days: Option<u32>,
+    #[cfg(feature = "rtss")]
+    pub overlay_update_rate: u32,
+    /// Bottom of the overlay battery graph in W, `None` to auto-scale.
+    #[cfg(feature = "rtss")]
+    pub battery_graph_watts: Option<u32>,
+    #[cfg(feature = "rtss")]
+    pub overlay_clock: bool,
+}
+
+/// In-memory `DLGTEMPLATE` with its items, so no resource file is needed.
+struct DialogTemplate {
+    words: Vec<u16>,
+    items: u16,
+    height: i16,
+}
+
+impl DialogTemplate {
+    fn new(title: &str) -> Self {
+        let style = WS_POPUP | WS_CAPTION | WS_SYSMENU;
+        let style = style.0 | (DS_MODALFRAME | DS_CENTER | DS_SETFONT) as u32;
+        let mut template = DialogTemplate {
+            words: vec![],
+            items: 0,
+            height: MARGIN,
+        };
+        template.push_u32(style);
+        template.push_u32(0);
+        // Item count and size are filled in by `build`
+        template.words.extend([0; 5]);
+        // No menu, default class
+        template.words.extend([0, 0]);
+        template.push_str(title);
+        // Font size in points, used thanks to DS_SETFONT
+        template.words.push(9);
+        template.push_str("Segoe UI");
+        template
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.words.push(value as u16);
+        self.words.push((value >> 16) as u16);
+    }
+
+    fn push_str(&mut self, value: &str) {
+        self.words.extend(value.encode_utf16());
+        self.words.push(0);
+    }
+
+    /// Bounds are `(x, y, width, height)`.
+    fn add_item(
+        &mut self,
+        class: u16,
+        style: u32,
+        id: u16,
+        title: &str,
+        bounds: (i16, i16, i16, i16),
+    ) {
+        let (x, y, cx, cy) = bounds;
+        // Each item starts on a DWORD boundary
+        if self.words.len() % 2 == 1 {
+            self.words.push(0);
+        }
+        self.push_u32(style | (WS_CHILD | WS_VISIBLE).0);
+        self.push_u32(0);
+        self.words
+            .extend([x as u16, y as u16, cx as u16, cy as u16, id]);
+        self.words.extend([0xFFFF, class]);
+        self.push_str(title);
+        // No creation data
+        self.words.push(0);
+        self.items += 1;
+    }
+
+    fn add_checkbox(&mut self, id: u16, title: &str) {
+        let style = BS_AUTOCHECKBOX as u32 | WS_TABSTOP.0;
+        let y = self.height;
+        self.add_item(
+            BUTTON_CLASS,
+            style,
+            id,
+            title,
+            (MARGIN, y, DIALOG_WIDTH - 2 * MARGIN, 10),
+        );
+        self.height += ROW_HEIGHT;
+    }
+
+    /// Adds an edit field for a number. The label mnemonic moves focus to the field.
+    fn add_number(&mut self, id: u16, title: &str) {
+        let y = self.height;
+        let label_width = DIALOG_WIDTH - 3 * MARGIN - EDIT_WIDTH;
+        let bounds = (MARGIN, y + 2, label_width, 8);
+        self.add_item(STATIC_CLASS, 0, ID_UNUSED, title, bounds);
+        let style = (ES_NUMBER | ES_AUTOHSCROLL) as u32 | (WS_BORDER | WS_TABSTOP).0;
+        let x = DIALOG_WIDTH - MARGIN - EDIT_WIDTH;
+        self.add_item(EDIT_CLASS, style, id, "", (x, y, EDIT_WIDTH, 12));
+        self.height += ROW_HEIGHT + 2;
+    }
+
+    fn add_buttons(&mut self) {
+        self.height += 4;
+        let y = self.height;
+        let ok_x = DIALOG_WIDTH - 2 * (MARGIN + BUTTON_WIDTH) + 3;
+        let style = BS_DEFPUSHBUTTON as u32 | WS_TABSTOP.0;
+        let bounds = (ok_x, y, BUTTON_WIDTH, 14);
+        self.add_item(BUTTON_CLASS, style, IDOK.0 as _, "OK", bounds);
+        let style = BS_PUSHBUTTON as u32 | WS_TABSTOP.0;
+        let cancel_x = DIALOG_WIDTH - MARGIN - BUTTON_WIDTH;
+        let bounds = (cancel_x, y, BUTTON_WIDTH, 14);
+        self.add_item(BUTTON_CLASS, style, IDCANCEL.0 as _, "Cancel", bounds);
+        self.height += 14 + MARGIN;
+    }
+
+    /// Returns the template in a DWORD-aligned buffer.
+    fn build(mut self) -> Vec<u32> {
+        self.words[4] = self.items;
+        self.words[7] = DIALOG_WIDTH as u16;
+        self.words[8] = self.height as u16;
+        let mut buffer = vec![0u32; self.words.len().div_ceil(2)];
+        // SAFETY: The buffer is large enough to hold all the words
+        unsafe {
+            copy_nonoverlapping(
+                self.words.as_ptr(),
+                buffer.as_mut_ptr() as *mut u16,
+                self.words.len(),
+            )
+        };
+        buffer
+    }
+}
+
+fn build_template() -> Vec<u32> {
+    let mut template = DialogTemplate::new("LilPowerMan Settings");
+    template.add_checkbox(
+        ID_REMOVE_MISSING_APPS,
+        "&Remove applications that no longer exist",
+    );
+    template.add_checkbox(
+        ID_FULLSCREEN_DETECTION,
+        "Keep &full-screen applications in the foreground",
+    );
+    template.add_checkbox(ID_APPLY_ON_STARTUP, "&Apply the TDP setting at startup");
+    template.add_checkbox(
+        ID_PAUSE_WHEN_DISPLAY_OFF,
+        "&Poll less often while the display is off",
+    );
+    template.add_checkbox(
+        ID_RESET_ON_OBSERVE,
+        "Re&set to stock limits when switching to observing",
+    );
+    template.add_checkbox(ID_SHOW_CHARGE_ICON, "Show the &charge rate icon");
+    template.add_checkbox(
+        ID_TRAY_SHOWS_TARGET,
+        "Show the &target instead of the measured TDP while forcing",
+    );
+    template.add_checkbox(
+        ID_TRAY_GAUGE,
+        "Show the power &draw as a bar in the TDP icon",
+    );
+    template.add_checkbox(
+        ID_TRAY_SHOWS_POWER,
+        "Show the power draw instead of the li&mit in the TDP icon",
+    );
+    template.add_checkbox(ID_ICON_OUTLINE, "Outli&ne the text of the tray icons");
+    template.add_checkbox(
+        ID_PAUSE_FOR_COMPETING_TOOLS,
+        "Pause TDP changes &while another tuning tool runs",
+    );
+    template.add_checkbox(
+        ID_APPLY_FAILURE_SOUND,
+        "Play a sound when the TDP &limit cannot be applied",
+    );
+    template.add_checkbox(ID_TDP_HISTORY, "Show the TDP &history in the tooltip");
+    template.add_checkbox(
+        ID_FINE_TDP_OPTIONS,
+        "Offer TDP limits in 1 W steps for e&xperimenting",
+    );
+    template.add_checkbox(
+        ID_TEMPERATURE_FAHRENHEIT,
+        "Show t&emperatures in Fahrenheit",
+    );
+    #[cfg(feature = "rtss")]
+    template.add_checkbox(ID_OVERLAY_CLOCK, "Show the cl&ock in the overlay");
+    template.height += 2;
+    template.add_number(
+        ID_BATTERY_SAVER_LIMIT,
+        "&Battery Saver TDP in W (empty to disable):",
+    );
+    #[cfg(feature = "rtss")]
+    template.add_number(ID_OVERLAY_UPDATE_RATE, "Overlay &updates per second:");
+    #[cfg(feature = "rtss")]
+    template.add_number(
+        ID_BATTERY_GRAPH_WATTS,
+        "Battery &graph range in W (empty to auto-scale):",
+    );
+    template.add_number(
+        ID_CHARGE_IDLE_BAND,
+        "&Idle charge rate band in mW (0 to disable):",
+    );
+    template.add_number(
+        ID_UPDATE_CHECK_DAYS,
+        "Check for new &versions every N days (empty to disable):",
+    );
+    template.add_buttons();
+    template.build()
+}
+
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn set_checked(dialog: HWND, id: u16, checked: bool) {
+    SendDlgItemMessageW(
+        dialog,
+        id as _,
+        BM_SETCHECK,
+        WPARAM(checked as _),
+        LPARAM(0),
+    );
+}
+
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn is_checked(dialog: HWND, id: u16) -> bool {
+    SendDlgItemMessageW(dialog, id as _, BM_GETCHECK, WPARAM(0), LPARAM(0)).0 == 1
+}
+
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn get_number(dialog: HWND, id: u16) -> Option<u32> {
+    let mut translated = BOOL(0);
+    let value = GetDlgItemInt(dialog, id as _, Some(&mut translated), false);
+    translated.as_bool().then_some(value)
+}
+
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn reject(dialog: HWND, id: u16, text: &str) {
+    let mut text: Vec<u16> = text.encode_utf16().collect();
+    text.push(0);
+    let text = PCWSTR::from_raw(text.as_ptr());
+    MessageBoxW(dialog, text, w!("LilPowerMan"), MB_OK | MB_ICONWARNING);
+    if let Ok(control) = GetDlgItem(dialog, id as _) {
+        SendMessageW(dialog, WM_NEXTDLGCTL, WPARAM(control.0 as _), LPARAM(1));
+    }
+}
+
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn init_controls(dialog: HWND, values: &SettingsDialogValues) {
+    set_checked(dialog, ID_REMOVE_MISSING_APPS, values.remove_missing_apps);
+    set_checked(dialog, ID_FULLSCREEN_DETECTION, values.fullscreen_detection);
+    set_checked(dialog, ID_APPLY_ON_STARTUP, values.apply_on_startup);
+    set_checked(
+        dialog,
+        ID_PAUSE_WHEN_DISPLAY_OFF,
+        values.pause_when_display_off,
+    );
+    set_checked(dialog, ID_RESET_ON_OBSERVE, values.reset_on_observe);
+    set_checked(dialog, ID_SHOW_CHARGE_ICON, values.show_charge_icon);
+    set_checked(dialog, ID_TRAY_SHOWS_TARGET, values.tray_shows_target);
+    set_checked(dialog, ID_TRAY_GAUGE, values.tray_gauge);
+    set_checked(dialog, ID_TRAY_SHOWS_POWER, values.tray_shows_power);
+    set_checked(dialog, ID_ICON_OUTLINE, values.icon_outline);
+    set_checked(
+        dialog,
+        ID_PAUSE_FOR_COMPETING_TOOLS,
+        values.pause_for_competing_tools,
+    );
+    set_checked(dialog, ID_APPLY_FAILURE_SOUND, values.apply_failure_sound);
+    set_checked(dialog, ID_TDP_HISTORY, values.tdp_history);
+    set_checked(dialog, ID_FINE_TDP_OPTIONS, values.fine_tdp_options);
+    set_checked(
+        dialog,
+        ID_TEMPERATURE_FAHRENHEIT,
+        values.temperature_fahrenheit,
+    );
+    if let Some(limit) = values.battery_saver_limit {
+        _ = SetDlgItemInt(dialog, ID_BATTERY_SAVER_LIMIT as _, limit / 1000, false);
+    }
+    let band = values.charge_idle_band;
+    _ = SetDlgItemInt(dialog, ID_CHARGE_IDLE_BAND as _, band, false);
+    if let Some(days) = values.update_check_days {
+        _ = SetDlgItemInt(dialog, ID_UPDATE_CHECK_DAYS as _, days, false);
+    }
+    #[cfg(feature = "rtss")]
+    {
+        set_checked(dialog, ID_OVERLAY_CLOCK, values.overlay_clock);
+        let rate = values.overlay_update_rate;
+        _ = SetDlgItemInt(dialog, ID_OVERLAY_UPDATE_RATE as _, rate, false);
+        if let Some(watts) = values.battery_graph_watts {
+            _ = SetDlgItemInt(dialog, ID_BATTERY_GRAPH_WATTS as _, watts, false);
+        }
+    }
+}
+
+/// Reads the values from the controls, or explains to the user what is wrong.
+///
+/// # Safety
+///
+/// The dialog handle must be valid.
+unsafe fn read_controls(
+    dialog: HWND,
+    tdp_range: RangeInclusive<u32>,
+) -> Option<SettingsDialogValues> {
+    let battery_saver_limit =
+        get_number(dialog, ID_BATTERY_SAVER_LIMIT).map(|w| w.saturating_mul(1000));
+    if battery_saver_limit.is_some_and(|limit| !tdp_range.contains(&limit)) {
+        let text = format!(
+            "Battery Saver TDP must be between {} and {} W, leave it empty to disable",
+            (*tdp_range.start() as f32) / 1000.0,
+            (*tdp_range.end() as f32) / 1000.0
+        );
+        reject(dialog, ID_BATTERY_SAVER_LIMIT, &text);
+        return None;
+    }
+    let charge_idle_band = match get_number(dialog, ID_CHARGE_IDLE_BAND) {
+        Some(band @ 0..=MAX_CHARGE_IDLE_BAND) => band,
+        _ => {
+            let text = format!(
+                "Idle charge rate band must be between 0 and {} mW",
+                MAX_CHARGE_IDLE_BAND
+            );
+            reject(dialog, ID_CHARGE_IDLE_BAND, &text);
+            return None;
+        }
+    };
+    let update_check_days = get_number(dialog, ID_UPDATE_CHECK_DAYS);
+    if update_check_days == Some(0) {
+        let text = "Days between update checks must be above zero, leave it empty to disable";
+        reject(dialog, ID_UPDATE_CHECK_DAYS, text);
+        return None;
+    }
+    #[cfg(feature = "rtss")]
+    let overlay_update_rate = match get_number(dialog, ID_OVERLAY_UPDATE_RATE) {
+        Some(rate @ 1..=MAX_OVERLAY_UPDATE_RATE) => rate,
+        _ => {
+            let text = format!(
+                "Overlay updates per second must be between 1 and {}",
+                MAX_OVERLAY_UPDATE_RATE
+            );
+            reject(dialog, ID_OVERLAY_UPDATE_RATE, &text);
+            return None;
+        }
+    };
+    #[cfg(feature = "rtss")]
+    let battery_graph_watts = get_number(dialog, ID_BATTERY_GRAPH_WATTS);
+    #[cfg(feature = "rtss")]
+    if battery_graph_watts == Some(0) {
+        let text = "Battery graph range must be above zero, leave it empty to auto-scale";
+        reject(dialog, ID_BATTERY_GRAPH_WATTS, text);
+        return None;
+    }
+    Some(SettingsDialogValues {
+        remove_missing_apps: is_checked(dialog, ID_REMOVE_MISSING_APPS),
+        fullscreen_detection: is_checked(dialog, ID_FULLSCREEN_DETECTION),
+        apply_on_startup: is_checked(dialog, ID_APPLY_ON_STARTUP),
+        pause_when_display_off: is_checked(dialog, ID_PAUSE_WHEN_DISPLAY_OFF),
+        reset_on_observe: is_checked(dialog, ID_RESET_ON_OBSERVE),
+        show_charge_icon: is_checked(dialog, ID_SHOW_CHARGE_ICON),
+        tray_shows_target: is_checked(dialog, ID_TRAY_SHOWS_TARGET),
+        tray_gauge: is_checked(dialog, ID_TRAY_GAUGE),
+        tray_shows_power: is_checked(dialog, ID_TRAY_SHOWS_POWER),
+        icon_outline: is_checked(dialog, ID_ICON_OUTLINE),
+        pause_for_competing_tools: is_checked(dialog, ID_PAUSE_FOR_COMPETING_TOOLS),
+        apply_failure_sound: is_checked(dialog, ID_APPLY_FAILURE_SOUND),
+        tdp_history: is_checked(dialog, ID_TDP_HISTORY),
+        fine_tdp_options: is_checked(dialog, ID_FINE_TDP_OPTIONS),
+        temperature_fahrenheit: is_checked(dialog, ID_TEMPERATURE_FAHRENHEIT),
+        battery_saver_limit,
+        tdp_range,
+        charge_idle_band,
+        update_check_days,
+        #[cfg(feature = "rtss")]
+        overlay_update_rate,
+        #[cfg(feature = "rtss")]
+        battery_graph_watts,
+        #[cfg(feature = "rtss")]
+        overlay_clock: is_checked(dialog, ID_OVERLAY_CLOCK),
+    })
+}
+
+unsafe extern "system" fn dialog_proc(
+    dialog: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> isize {
+    match message {
+        WM_INITDIALOG => {
+            // SAFETY: The pointer is passed by `show` and outlives the dialog
+            SetWindowLongPtrW(dialog, GWLP_USERDATA, l_param.0);
+            if let Some(values) = (l_param.0 as *const SettingsDialogValues).as_ref() {
+                init_controls(dialog, values);
+            }
+            // Let the system focus the first control
+            1
+        }
+        WM_COMMAND => {
+            let id = w_param.0 as u16 as i32;
+            if id == IDOK.0 {
+                let values = GetWindowLongPtrW(dialog, GWLP_USERDATA) as *mut SettingsDialogValues;
+                // SAFETY: The pointer was stored on WM_INITDIALOG and outlives the dialog
+                if let Some(values) = values.as_mut() {
+                    if let Some(new_values) = read_controls(dialog, values.tdp_range.clone()) {
+                        *values = new_values;
+                        _ = EndDialog(dialog, 1);
+                    }
+                }
+                1
+            } else if id == IDCANCEL.0 {
+                _ = EndDialog(dialog, 0);
+                1
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    }
+}
+
+/// Shows the modal settings dialog. Returns `true` and updates the values if the user
+/// pressed OK.
+pub fn show(values: &mut SettingsDialogValues) -> bool {
+    let template = build_template();
+    // SAFETY: The template is DWORD-aligned and well-formed,
+    //   the values outlive the dialog since the call is blocking
+    let result = unsafe {
+        DialogBoxIndirectParamW(
+            get_instance_handle(),
+            template.as_ptr() as *const DLGTEMPLATE,
+            None,
+            Some(dialog_proc),
+            LPARAM(values as *mut _ as _),
+        )
+    };
+    if result == -1 {
+        error!(
+            "Failed to show the settings dialog: {}",
+            windows::core::Error::from_win32()
+        );
+        return false;
+    }
+    result == 1
+}
@@ -0,0 +1,223 @@
+//! Opt-in check for a newer version. Nothing is downloaded or installed automatically.
+
+use std::ffi::c_void;
+use std::fmt::{Debug, Display, Formatter};
+use std::ptr::{null, null_mut};
+use std::thread;
+use windows::core::{w, Error as WindowsError, PCWSTR};
+use windows::Win32::Networking::WinHttp::{
+    WinHttpCloseHandle, WinHttpConnect, WinHttpOpen, WinHttpOpenRequest, WinHttpQueryHeaders,
+    WinHttpReadData, WinHttpReceiveResponse, WinHttpSendRequest, WinHttpSetTimeouts,
+    INTERNET_DEFAULT_HTTPS_PORT, WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY, WINHTTP_FLAG_SECURE,
+    WINHTTP_QUERY_FLAG_NUMBER, WINHTTP_QUERY_STATUS_CODE,
+};
+
+const MANIFEST_HOST: PCWSTR = w!("raw.githubusercontent.com");
+/// Plain text file with the latest version on the first line and its download page on the second.
+const MANIFEST_PATH: PCWSTR = w!("/Samarkin/LilPowerMan/main/version.txt");
+const TIMEOUT_MS: i32 = 10000;
+const MAX_MANIFEST_SIZE: usize = 4096;
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Release {
+    pub version: String,
+    pub url: String,
+}
+
+pub enum Error {
+    WindowsError(WindowsError),
+    HttpStatus(u32),
+    InvalidManifest,
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WindowsError(err) => write!(f, "{}", err),
+            Self::HttpStatus(status) => write!(f, "HTTP status {}", status),
+            Self::InvalidManifest => write!(f, "Version manifest is malformed"),
+        }
+    }
+}
+
+impl From<WindowsError> for Error {
+    fn from(err: WindowsError) -> Self {
+        Self::WindowsError(err)
+    }
+}
+
+/// WinHTTP handle that is closed on drop.
+struct InternetHandle(*mut c_void);
+
+impl InternetHandle {
+    fn new(handle: *mut c_void) -> Result<Self, WindowsError> {
+        if handle.is_null() {
+            return Err(WindowsError::from_win32());
+        }
+        Ok(InternetHandle(handle))
+    }
+}
+
+impl Drop for InternetHandle {
+    fn drop(&mut self) {
+        // SAFETY: The handle is valid and owned by us
+        if let Err(err) = unsafe { WinHttpCloseHandle(self.0) } {
+            warn!("Failed to close WinHTTP handle: {}", err);
+        }
+    }
+}
+
+fn fetch_manifest() -> Result<String, Error> {
+    let mut agent: Vec<u16> = format!("LilPowerMan/{}", CURRENT_VERSION)
+        .encode_utf16()
+        .collect();
+    agent.push(0);
+    // SAFETY: The agent string is null-terminated and outlives the call
+    let session = InternetHandle::new(unsafe {
+        WinHttpOpen(
+            PCWSTR::from_raw(agent.as_ptr()),
+            WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            0,
+        )
+    })?;
+    // SAFETY: The session handle is valid
+    unsafe { WinHttpSetTimeouts(session.0, TIMEOUT_MS, TIMEOUT_MS, TIMEOUT_MS, TIMEOUT_MS) }?;
+    // SAFETY: The session handle is valid, the host name is a static string
+    let connection = InternetHandle::new(unsafe {
+        WinHttpConnect(session.0, MANIFEST_HOST, INTERNET_DEFAULT_HTTPS_PORT, 0)
+    })?;
+    // SAFETY: The connection handle is valid, all strings are static
+    let request = InternetHandle::new(unsafe {
+        WinHttpOpenRequest(
+            connection.0,
+            w!("GET"),
+            MANIFEST_PATH,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            null(),
+            WINHTTP_FLAG_SECURE,
+        )
+    })?;
+    // SAFETY: The request handle is valid, there are no extra headers or body
+    unsafe { WinHttpSendRequest(request.0, None, None, 0, 0, 0) }?;
+    // SAFETY: The request handle is valid
+    unsafe { WinHttpReceiveResponse(request.0, null_mut()) }?;
+    let mut status = 0u32;
+    let mut len = size_of_val(&status) as u32;
+    // SAFETY: The provided pointers reference local variables of the specified size
+    unsafe {
+        WinHttpQueryHeaders(
+            request.0,
+            WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+            PCWSTR::null(),
+            Some(&mut status as *mut _ as _),
+            &mut len,
+            null_mut(),
+        )
+    }?;
+    if status != 200 {
+        return Err(Error::HttpStatus(status));
+    }
+    let mut body = vec![];
+    let mut chunk = [0u8; 1024];
+    loop {
+        let mut read = 0;
+        // SAFETY: The buffer is a local array of the specified size
+        unsafe {
+            WinHttpReadData(
+                request.0,
+                chunk.as_mut_ptr() as _,
+                chunk.len() as u32,
+                &mut read,
+            )
+        }?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read as usize]);
+        if body.len() > MAX_MANIFEST_SIZE {
+            return Err(Error::InvalidManifest);
+        }
+    }
+    String::from_utf8(body).map_err(|_| Error::InvalidManifest)
+}
+
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn parse_manifest(manifest: &str) -> Result<Release, Error> {
+    let mut lines = manifest.lines().map(str::trim);
+    let (Some(version), Some(url)) = (lines.next(), lines.next()) else {
+        return Err(Error::InvalidManifest);
+    };
+    if parse_version(version).is_none() || !url.starts_with("https://") {
+        return Err(Error::InvalidManifest);
+    }
+    Ok(Release {
+        version: version.to_string(),
+        url: url.to_string(),
+    })
+}
+
+fn is_newer(version: &str, current: &str) -> bool {
+    match (parse_version(version), parse_version(current)) {
+        (Some(version), Some(current)) => version > current,
+        _ => false,
+    }
+}
+
+fn check() -> Result<Option<Release>, Error> {
+    let release = parse_manifest(&fetch_manifest()?)?;
+    Ok(is_newer(&release.version, CURRENT_VERSION).then_some(release))
+}
+
+/// Checks for a newer version without blocking the caller. `on_update` is called on a background
+/// thread, and only if a newer version is available. Failures are only logged.
+pub fn check_in_background(on_update: impl FnOnce(Release) + Send + 'static) {
+    let spawned = thread::Builder::new()
+        .name(String::from("Update check"))
+        .spawn(move || match check() {
+            Ok(Some(release)) => {
+                info!("Version {} is available", release.version);
+                on_update(release);
+            }
+            Ok(None) => debug!("Version {} is up to date", CURRENT_VERSION),
+            Err(err) => warn!("Update check failed: {}", err),
+        });
+    if let Err(err) = spawned {
+        warn!("Failed to start the update check: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_compared_numerically() {
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(is_newer("1.0", "0.9.9"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1.0-beta", "0.0.1"));
+    }
+
+    #[test]
+    fn manifest_requires_version_and_https_url() {
+        let release = parse_manifest("0.2.0\r\nhttps://example.com/releases\r\n").unwrap();
+        assert_eq!(release.version, "0.2.0");
+        assert_eq!(release.url, "https://example.com/releases");
+        assert!(parse_manifest("0.2.0").is_err());
+        assert!(parse_manifest("0.2.0\nhttp://example.com").is_err());
+        assert!(parse_manifest("latest\nhttps://example.com").is_err());
+    }
+}
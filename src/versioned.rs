@@ -76,6 +76,7 @@ impl<T: PartialEq + Default> Default for Versioned<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::settings::{Settings, SettingsStorage};
     use std::cell::RefCell;
 
     #[test]
@@ -128,4 +129,21 @@ mod tests {
         _ = b == a;
         assert_eq!(b.eq_d.take(), true);
     }
+
+    #[test]
+    fn settings_are_compared_by_version() {
+        // The model keeps its settings this way, so that the view can compare them cheaply
+        let mut storage = SettingsStorage::in_memory();
+        let old_settings = Versioned::new(Settings::default());
+        let mut new_settings = old_settings.clone();
+        // Clones are equal without comparing the settings themselves
+        assert_eq!(old_settings.version, new_settings.version);
+        assert!(old_settings == new_settings);
+
+        storage
+            .set_tray_target_enabled(&mut new_settings, true)
+            .unwrap();
+        assert_ne!(old_settings.version, new_settings.version);
+        assert!(old_settings != new_settings);
+    }
 }
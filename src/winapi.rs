@@ -3,15 +3,49 @@ mod dc;
 mod files;
 mod paint;
 
-use windows::core::{w, Error, Owned, Result, PCWSTR};
-use windows::Win32::Foundation::{BOOL, HANDLE, HINSTANCE, SYSTEMTIME};
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use windows::core::{w, Error, Owned, Result, PCWSTR, PWSTR};
+#[cfg(feature = "rtss")]
+use windows::Win32::Foundation::HGLOBAL;
+use windows::Win32::Foundation::{
+    GlobalFree, APPMODEL_ERROR_NO_APPLICATION, BOOL, COLORREF, HANDLE, HINSTANCE, HWND, LPARAM,
+    RECT, SYSTEMTIME,
+};
+use windows::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, GetSysColor, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONULL,
+    SYS_COLOR_INDEX,
+};
+use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+use windows::Win32::Storage::Packaging::Appx::{
+    GetApplicationUserModelId, APPLICATION_USER_MODEL_ID_MAX_LENGTH,
+};
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows::Win32::System::DataExchange::{
+    CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
+};
+#[cfg(feature = "rtss")]
+use windows::Win32::System::DataExchange::{GetClipboardData, IsClipboardFormatAvailable};
+use windows::Win32::System::Diagnostics::Debug::MessageBeep;
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+#[cfg(feature = "rtss")]
+use windows::Win32::System::Memory::GlobalSize;
+use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
 use windows::Win32::System::SystemInformation::GetLocalTime;
-use windows::Win32::System::Threading::GetCurrentProcessId;
+use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, OpenProcessToken};
 use windows::Win32::System::IO::DeviceIoControl;
+use windows::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+use windows::Win32::UI::HiDpi::{GetDpiForSystem, GetDpiForWindow};
+use windows::Win32::UI::Shell::ShellExecuteW;
 use windows::Win32::UI::WindowsAndMessaging::{
-    DispatchMessageW, GetForegroundWindow, GetMessageW, GetWindowThreadProcessId, LoadCursorW,
-    MessageBoxW, TranslateMessage, HCURSOR, IDC_ARROW, MB_OK, MSG,
+    DispatchMessageW, EnumChildWindows, FindWindowW, GetClassNameW, GetDesktopWindow,
+    GetForegroundWindow, GetMessageW, GetShellWindow, GetWindowRect, GetWindowThreadProcessId,
+    IsWindowVisible, LoadCursorW, MessageBoxW, SystemParametersInfoW, TranslateMessage, HCURSOR,
+    IDC_ARROW, IDYES, MB_ICONQUESTION, MB_ICONWARNING, MB_OK, MB_YESNO, MSG, SPI_GETHIGHCONTRAST,
+    SW_SHOWNORMAL, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
 };
 
 pub use dc::AcquiredDC;
@@ -19,6 +53,8 @@ pub use files::Files;
 pub use paint::PaintContext;
 
 const APP_NAME: PCWSTR = w!("LilPowerMan");
+/// Standard clipboard format, declared in the OLE bindings that are not pulled in otherwise.
+const CF_UNICODETEXT: u32 = 13;
 
 pub fn show_error_message_box(text: &str) {
     let mut text: Vec<u16> = text.encode_utf16().collect();
@@ -26,6 +62,52 @@ pub fn show_error_message_box(text: &str) {
     unsafe { MessageBoxW(None, PCWSTR::from_raw(text.as_ptr()), APP_NAME, MB_OK) };
 }
 
+/// Asks a yes/no question, returns `true` if the user answered yes.
+pub fn ask_confirmation(owner: HWND, text: &str) -> bool {
+    let mut text: Vec<u16> = text.encode_utf16().collect();
+    text.push(0);
+    // SAFETY: The text is null-terminated and outlives the call, any owner handle is accepted
+    let result = unsafe {
+        MessageBoxW(
+            owner,
+            PCWSTR::from_raw(text.as_ptr()),
+            APP_NAME,
+            MB_YESNO | MB_ICONQUESTION,
+        )
+    };
+    result == IDYES
+}
+
+/// Plays the system warning sound, e.g. when something stops working in the background.
+pub fn play_alert_sound() {
+    // SAFETY: The call does not have any preconditions
+    if let Err(err) = unsafe { MessageBeep(MB_ICONWARNING) } {
+        warn!("Failed to play the alert sound: {}", err);
+    }
+}
+
+/// Opens the URL in the default browser.
+pub fn open_url(url: &str) -> Result<()> {
+    let mut url: Vec<u16> = url.encode_utf16().collect();
+    url.push(0);
+    // SAFETY: The URL is null-terminated and outlives the call
+    let result = unsafe {
+        ShellExecuteW(
+            None,
+            w!("open"),
+            PCWSTR::from_raw(url.as_ptr()),
+            None,
+            None,
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values above 32 indicate success
+    if result.0 as usize <= 32 {
+        return Err(Error::from_win32());
+    }
+    Ok(())
+}
+
 pub fn get_instance_handle() -> HINSTANCE {
     // SAFETY: lpModuleName is None instead of a raw pointer
     // The call is sound and should always return the handle of the main module (.exe file)
@@ -48,6 +130,37 @@ pub fn get_self_pid() -> u32 {
     unsafe { GetCurrentProcessId() }
 }
 
+/// Returns `true` if the application runs with administrator privileges.
+pub fn is_elevated() -> Result<bool> {
+    let mut token = HANDLE::default();
+    // SAFETY: The pseudo-handle of the current process is always valid,
+    //   the provided pointer is valid for the duration of the WinAPI call
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }?;
+    // SAFETY: The handle has just been opened and is not owned by anything else
+    let token = unsafe { Owned::new(token) };
+    let mut elevation = TOKEN_ELEVATION::default();
+    let mut size = 0;
+    // SAFETY: The provided pointers reference local variables of the specified size
+    unsafe {
+        GetTokenInformation(
+            *token,
+            TokenElevation,
+            Some(&mut elevation as *mut _ as *mut _),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        )
+    }?;
+    Ok(elevation.TokenIsElevated != 0)
+}
+
+/// Lets a GUI application write to the console of the command prompt it was started from.
+pub fn attach_parent_console() -> Result<()> {
+    // SAFETY: The call does not have any preconditions
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) }
+}
+
+/// Returns the process of the foreground window. For UWP applications, that is the process
+/// shown in the frame rather than `ApplicationFrameHost.exe` that owns the frame.
 pub fn get_fg_application_pid() -> Result<u32> {
     // SAFETY: The call is always sound
     let hwnd = unsafe { GetForegroundWindow() };
@@ -57,9 +170,232 @@ pub fn get_fg_application_pid() -> Result<u32> {
     if tid == 0 {
         Err(Error::from_win32())?
     }
+    if is_application_frame(hwnd) {
+        if let Some(hosted_pid) = find_hosted_pid(hwnd, pid) {
+            return Ok(hosted_pid);
+        }
+    }
     Ok(pid)
 }
 
+fn is_application_frame(hwnd: HWND) -> bool {
+    let mut class = [0u16; 32];
+    // SAFETY: The call is sound for any window handle
+    let len = unsafe { GetClassNameW(hwnd, &mut class) };
+    len > 0 && OsString::from_wide(&class[..len as usize]) == "ApplicationFrameWindow"
+}
+
+/// Returns the process of the first child window that is owned by another process.
+fn find_hosted_pid(frame: HWND, frame_pid: u32) -> Option<u32> {
+    struct Search {
+        frame_pid: u32,
+        found: Option<u32>,
+    }
+    unsafe extern "system" fn callback(hwnd: HWND, l_param: LPARAM) -> BOOL {
+        // SAFETY: The parameter points to the search, which outlives the enumeration
+        let search = unsafe { &mut *(l_param.0 as *mut Search) };
+        let mut pid = 0;
+        // SAFETY: The provided pointer is valid for the duration of the WinAPI call
+        unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+        if pid != 0 && pid != search.frame_pid {
+            search.found = Some(pid);
+            // Stop the enumeration
+            return BOOL(0);
+        }
+        BOOL(1)
+    }
+    let mut search = Search {
+        frame_pid,
+        found: None,
+    };
+    // SAFETY: The search is a local structure that outlives the enumeration
+    // The result is not meaningful, only the callback tells whether anything was found
+    _ = unsafe {
+        EnumChildWindows(
+            frame,
+            Some(callback),
+            LPARAM(&mut search as *mut Search as isize),
+        )
+    };
+    search.found
+}
+
+/// Returns the Application User Model ID of a packaged (UWP or Store) application,
+/// `None` if the process is not packaged.
+pub fn get_package_app_id(process: HANDLE) -> Result<Option<OsString>> {
+    let mut id = [0u16; APPLICATION_USER_MODEL_ID_MAX_LENGTH as usize];
+    let mut len = id.len() as u32;
+    // SAFETY: The provided pointer is pointing to an allocated area of the specified size
+    let result =
+        unsafe { GetApplicationUserModelId(process, &mut len, PWSTR::from_raw(id.as_mut_ptr())) };
+    if result == APPMODEL_ERROR_NO_APPLICATION {
+        return Ok(None);
+    }
+    result.ok()?;
+    // The length includes the terminating null
+    Ok(Some(OsString::from_wide(&id[..len as usize - 1])))
+}
+
+/// Returns the executable file names of all running processes, e.g. `explorer.exe`.
+pub fn get_process_names() -> Result<Vec<OsString>> {
+    // SAFETY: The call does not have any preconditions, the handle is owned from here on
+    let snapshot = unsafe { Owned::new(CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)?) };
+    let mut entry = PROCESSENTRY32W {
+        dwSize: size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut names = vec![];
+    // SAFETY: The entry is a local structure of the specified size
+    let mut result = unsafe { Process32FirstW(*snapshot, &mut entry) };
+    while result.is_ok() {
+        let name = &entry.szExeFile;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        names.push(OsString::from_wide(&name[..len]));
+        // SAFETY: Same as above
+        result = unsafe { Process32NextW(*snapshot, &mut entry) };
+    }
+    Ok(names)
+}
+
+pub fn is_high_contrast() -> bool {
+    let mut high_contrast = HIGHCONTRASTW {
+        cbSize: size_of::<HIGHCONTRASTW>() as u32,
+        ..Default::default()
+    };
+    // SAFETY: The provided pointer references a local structure of the specified size
+    let result = unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            high_contrast.cbSize,
+            Some(&mut high_contrast as *mut _ as *mut _),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    result.is_ok() && high_contrast.dwFlags.contains(HCF_HIGHCONTRASTON)
+}
+
+/// Replaces the clipboard contents with the provided text.
+pub fn set_clipboard_text(owner: HWND, text: &str) -> Result<()> {
+    let mut text: Vec<u16> = text.encode_utf16().collect();
+    text.push(0);
+    let size = text.len() * size_of::<u16>();
+    // SAFETY: The allocated block is large enough for the copied text
+    let memory = unsafe {
+        let memory = GlobalAlloc(GMEM_MOVEABLE, size)?;
+        let ptr = GlobalLock(memory) as *mut u16;
+        if ptr.is_null() {
+            let err = Error::from_win32();
+            _ = GlobalFree(memory);
+            return Err(err);
+        }
+        ptr.copy_from_nonoverlapping(text.as_ptr(), text.len());
+        // Fails with NO_ERROR once the block is unlocked
+        _ = GlobalUnlock(memory);
+        memory
+    };
+    // SAFETY: The clipboard is closed before returning, and the system owns the block
+    //   once it is set as the clipboard data
+    unsafe {
+        let result = OpenClipboard(owner).and_then(|_| {
+            let result = EmptyClipboard()
+                .and_then(|_| SetClipboardData(CF_UNICODETEXT, HANDLE(memory.0)).map(|_| ()));
+            _ = CloseClipboard();
+            result
+        });
+        if result.is_err() {
+            _ = GlobalFree(memory);
+        }
+        result
+    }
+}
+
+/// Reads the text from the clipboard, `None` if it holds something else.
+#[cfg(feature = "rtss")]
+pub fn get_clipboard_text(owner: HWND) -> Result<Option<String>> {
+    // SAFETY: The clipboard is closed before returning, the block is only read while locked
+    //   and within its size
+    unsafe {
+        if IsClipboardFormatAvailable(CF_UNICODETEXT).is_err() {
+            return Ok(None);
+        }
+        OpenClipboard(owner)?;
+        let result = GetClipboardData(CF_UNICODETEXT).and_then(|data| {
+            let memory = HGLOBAL(data.0);
+            let ptr = GlobalLock(memory) as *const u16;
+            if ptr.is_null() {
+                return Err(Error::from_win32());
+            }
+            let data = std::slice::from_raw_parts(ptr, GlobalSize(memory) / size_of::<u16>());
+            let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+            let text = String::from_utf16_lossy(&data[..len]);
+            _ = GlobalUnlock(memory);
+            Ok(Some(text))
+        });
+        _ = CloseClipboard();
+        result
+    }
+}
+
+/// Returns the DPI of the monitor with the primary taskbar, where the notification icons are.
+pub fn get_taskbar_dpi() -> u32 {
+    // SAFETY: Neither call has preconditions, GetDpiForWindow returns 0 for a stale handle
+    let dpi = unsafe { FindWindowW(w!("Shell_TrayWnd"), None) }
+        .map_or(0, |taskbar| unsafe { GetDpiForWindow(taskbar) });
+    if dpi != 0 {
+        dpi
+    } else {
+        // SAFETY: The call does not have any preconditions and is always sound
+        unsafe { GetDpiForSystem() }
+    }
+}
+
+pub fn get_sys_color(index: SYS_COLOR_INDEX) -> COLORREF {
+    // SAFETY: The call is always sound
+    COLORREF(unsafe { GetSysColor(index) })
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FgWindowKind {
+    /// Desktop or shell window, e.g. during a full-screen mode switch.
+    Desktop,
+    /// Visible window that covers the entire monitor.
+    Fullscreen,
+    Other,
+}
+
+pub fn get_fg_window_kind() -> FgWindowKind {
+    // SAFETY: The calls are always sound
+    let (hwnd, desktop, shell) =
+        unsafe { (GetForegroundWindow(), GetDesktopWindow(), GetShellWindow()) };
+    if hwnd.is_invalid() || hwnd == desktop || hwnd == shell {
+        return FgWindowKind::Desktop;
+    }
+    // SAFETY: The call is sound for any window handle
+    if !unsafe { IsWindowVisible(hwnd) }.as_bool() {
+        return FgWindowKind::Other;
+    }
+    let mut rect = RECT::default();
+    // SAFETY: The provided pointer is valid for the duration of the WinAPI call
+    if unsafe { GetWindowRect(hwnd, &mut rect) }.is_err() {
+        return FgWindowKind::Other;
+    }
+    // SAFETY: The call is sound for any window handle
+    let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONULL) };
+    let mut info = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    // SAFETY: The provided pointer is valid for the duration of the WinAPI call
+    if monitor.is_invalid() || !unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+        return FgWindowKind::Other;
+    }
+    if rect == info.rcMonitor {
+        FgWindowKind::Fullscreen
+    } else {
+        FgWindowKind::Other
+    }
+}
+
 #[inline]
 fn unwrap_winapi_bool(bool: BOOL) -> Result<bool> {
     match bool.0 {
@@ -110,3 +446,32 @@ pub fn device_io_control<Input, Output: Default>(
     };
     Ok(buffer)
 }
+
+/// Sends a control code to the device that responds with a null-terminated wide string.
+pub fn device_io_control_string<Input>(
+    device: &Owned<HANDLE>,
+    control_code: u32,
+    param: &Input,
+) -> Result<String> {
+    let mut buffer = [0u16; 256];
+    let mut bytes_returned = 0;
+    // SAFETY: Owned handle outlives the copy, buffer size is passed along with the pointer
+    unsafe {
+        DeviceIoControl(
+            **device,
+            control_code,
+            Some(param as *const _ as *const _),
+            size_of::<Input>() as u32,
+            Some(buffer.as_mut_ptr() as *mut _),
+            size_of_val(&buffer) as u32,
+            Some(&mut bytes_returned),
+            None,
+        )?
+    };
+    let returned = &buffer[..bytes_returned as usize / size_of::<u16>()];
+    let len = returned
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(returned.len());
+    Ok(String::from_utf16_lossy(&returned[..len]))
+}
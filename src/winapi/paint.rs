@@ -1,9 +1,9 @@
 use windows::core::Owned;
-use windows::Win32::Foundation::{COLORREF, HWND, RECT};
+use windows::Win32::Foundation::{COLORREF, HWND, RECT, SIZE};
 use windows::Win32::Graphics::Gdi::{
-    BeginPaint, CreateCompatibleDC, DeleteDC, EndPaint, FillRect, GetSysColorBrush, SelectObject,
-    SetBkColor, SetTextColor, TextOutW, CLR_INVALID, COLOR_WINDOW, HBITMAP, HBRUSH, HDC, HFONT,
-    PAINTSTRUCT,
+    BeginPaint, BitBlt, CreateCompatibleDC, DeleteDC, EndPaint, FillRect, GetSysColorBrush,
+    GetTextExtentPoint32W, SelectObject, SetBkColor, SetTextColor, TextOutW, CLR_INVALID,
+    COLOR_WINDOW, HBITMAP, HBRUSH, HDC, HFONT, PAINTSTRUCT, SRCCOPY,
 };
 
 enum DeviceContextSource {
@@ -98,6 +98,42 @@ impl PaintContext {
             panic!("Failed to call TextOut");
         }
     }
+
+    /// Returns the size of the text drawn with the current font.
+    pub fn measure_text(&self, text: &str) -> SIZE {
+        let chars: Vec<u16> = text.encode_utf16().collect();
+        let mut size = SIZE::default();
+        // SAFETY: `hdc` is guaranteed to be valid for `PaintContext`, pointers reference locals
+        if !unsafe { GetTextExtentPoint32W(self.hdc, chars.as_slice(), &mut size) }.as_bool() {
+            panic!("Failed to call GetTextExtentPoint32");
+        }
+        size
+    }
+
+    /// Copies the top left corner of the bitmap to the top left corner of the context.
+    ///
+    /// # Safety
+    ///
+    /// Bitmap must be valid and not selected into any other device context.
+    pub unsafe fn draw_bitmap(&mut self, bitmap: HBITMAP, width: i32, height: i32) {
+        // SAFETY: `hdc` is guaranteed to be valid for `PaintContext`
+        let source = unsafe { CreateCompatibleDC(self.hdc) };
+        if source.is_invalid() {
+            panic!("CreateCompatibleDC returned an invalid HDC");
+        }
+        // SAFETY: We own the source DC, and validity of the bitmap is guaranteed by the caller
+        let previous = unsafe { SelectObject(source, bitmap) };
+        // SAFETY: Both device contexts are valid
+        let result = unsafe { BitBlt(self.hdc, 0, 0, width, height, source, 0, 0, SRCCOPY) };
+        // SAFETY: The source DC is still owned by us, the bitmap is released before deleting it
+        unsafe {
+            SelectObject(source, previous);
+            _ = DeleteDC(source);
+        }
+        if let Err(err) = result {
+            error!("Failed to copy the bitmap: {}", err);
+        }
+    }
 }
 
 impl Drop for PaintContext {